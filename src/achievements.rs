@@ -0,0 +1,174 @@
+use crate::{crypto, ensure_data_dir, history::Event, tr, State, StreakState};
+use chrono::prelude::*;
+use std::{fs, io, path::PathBuf};
+
+struct Achievement {
+    id: &'static str,
+    title: &'static str,
+    description: &'static str,
+    /// Whether the achievement is earned by this state, which still holds the events of the
+    /// change that produced it
+    earned: fn(&State) -> bool,
+}
+
+const ACHIEVEMENTS: [Achievement; 8] = [
+    Achievement {
+        id: "first-hit",
+        title: "First step",
+        description: "hit a streak for the first time",
+        earned: |state| state.streaks.values().any(|streak| streak.max_count > 0),
+    },
+    Achievement {
+        id: "comeback",
+        title: "Comeback",
+        description: "start a streak again after it expired",
+        earned: |state| {
+            state.events.iter().any(|entry| {
+                matches!(entry.event, Event::Hit)
+                    && state
+                        .streaks
+                        .get(&entry.name)
+                        .is_some_and(|streak| streak.current_count == 1 && streak.max_count > 1)
+            })
+        },
+    },
+    Achievement {
+        id: "perfect-day",
+        title: "Perfect day",
+        description: "complete every one of at least 3 streaks on the same day",
+        earned: |state| {
+            state.streaks.len() >= 3
+                && state
+                    .streaks
+                    .values()
+                    .all(|streak| matches!(streak.state, StreakState::Done))
+        },
+    },
+    Achievement {
+        id: "hat-trick",
+        title: "Hat trick",
+        description: "keep 3 streaks going for a week at the same time",
+        earned: |state| {
+            state
+                .streaks
+                .values()
+                .filter(|streak| streak.current_count >= 7)
+                .count()
+                >= 3
+        },
+    },
+    Achievement {
+        id: "month",
+        title: "Monthly regular",
+        description: "keep a streak going for 30 days",
+        earned: |state| state.streaks.values().any(|streak| streak.max_count >= 30),
+    },
+    Achievement {
+        id: "century",
+        title: "Century",
+        description: "keep a streak going for 100 days",
+        earned: |state| state.streaks.values().any(|streak| streak.max_count >= 100),
+    },
+    Achievement {
+        id: "year",
+        title: "Full circle",
+        description: "keep a streak going for a whole year",
+        earned: |state| state.streaks.values().any(|streak| streak.max_count >= 365),
+    },
+    Achievement {
+        id: "collector",
+        title: "Collector",
+        description: "track 10 streaks at once",
+        earned: |state| state.streaks.len() >= 10,
+    },
+];
+
+fn achievements_path() -> Result<PathBuf, String> {
+    let mut path = ensure_data_dir()?;
+    path.push("achievements.txt");
+    Ok(path)
+}
+
+/// Returns the ids of the earned achievements and when they were earned
+fn read_earned() -> Result<Vec<(String, DateTime<Local>)>, String> {
+    let bytes = match fs::read(achievements_path()?) {
+        Ok(bytes) => bytes,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Vec::new(),
+        Err(err) => return Err(tr!("couldn't read achievements file: {}", err)),
+    };
+    let string =
+        crypto::decode(bytes).map_err(|err| tr!("couldn't decode achievements file: {}", err))?;
+    string
+        .lines()
+        .map(|line| {
+            let (id, time) = line
+                .split_once(',')
+                .ok_or_else(|| tr!("expected id and time for achievement: \"{}\"", line))?;
+            let time = time
+                .parse::<DateTime<Local>>()
+                .map_err(|err| tr!("expected local datetime for achievement: {}", err))?;
+            Ok((id.to_owned(), time))
+        })
+        .collect()
+}
+
+fn write_earned(earned: &[(String, DateTime<Local>)]) -> Result<(), String> {
+    let string: String = earned
+        .iter()
+        .map(|(id, time)| format!("{},{}\n", id, time))
+        .collect();
+    fs::write(achievements_path()?, crypto::encode(&string)?)
+        .map_err(|err| tr!("couldn't write achievements file: {}", err))
+}
+
+/// Award any achievements newly earned by a state change
+pub fn evaluate(state: &State) {
+    let mut earned = match read_earned() {
+        Ok(earned) => earned,
+        Err(err) => {
+            eprintln!("{}", tr!("couldn't check achievements: {}", err));
+            return;
+        }
+    };
+    let before = earned.len();
+    for achievement in ACHIEVEMENTS.iter() {
+        if !earned.iter().any(|(id, _time)| id == achievement.id) && (achievement.earned)(state) {
+            println!(
+                "{}",
+                tr!(
+                    "achievement unlocked: {} - {}",
+                    achievement.title,
+                    achievement.description
+                )
+            );
+            earned.push((achievement.id.to_owned(), Local::now()));
+        }
+    }
+    if earned.len() > before {
+        if let Err(err) = write_earned(&earned) {
+            eprintln!("{}", tr!("couldn't save achievements: {}", err));
+        }
+    }
+}
+
+/// List every achievement, marking the earned ones with when they were earned
+pub fn print_badges() {
+    let earned = match read_earned() {
+        Ok(earned) => earned,
+        Err(err) => {
+            eprintln!("{}", err);
+            return;
+        }
+    };
+    for achievement in ACHIEVEMENTS.iter() {
+        match earned.iter().find(|(id, _time)| id == achievement.id) {
+            Some((_id, time)) => println!(
+                "[x] {} ({}) - {}",
+                achievement.title,
+                time.format("%Y-%m-%d"),
+                achievement.description
+            ),
+            None => println!("[ ] {} - {}", achievement.title, achievement.description),
+        }
+    }
+}
@@ -0,0 +1,169 @@
+use crate::{crypto, ensure_data_dir, error::Error, tr};
+use chrono::prelude::*;
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::PathBuf,
+};
+
+/// A change made to the streaks, kept so what happened to them can be pieced together later
+#[derive(Clone)]
+pub struct Record {
+    pub time: DateTime<Local>,
+    /// One of add, remove, rename, merge, hit, repair, reset and set
+    pub action: String,
+    pub name: String,
+    /// Anything else worth knowing, like the new name or count
+    pub detail: String,
+}
+
+impl Record {
+    pub fn new(action: &str, name: &str, detail: String) -> Self {
+        Self {
+            time: Local::now(),
+            action: action.to_owned(),
+            name: name.to_owned(),
+            detail,
+        }
+    }
+
+    /// A streak's current and max counts being set by hand
+    pub fn set(name: &str, before: (u32, u32), after: (u32, u32)) -> Self {
+        Self::new(
+            "set",
+            name,
+            format!(
+                "from {} (max {}) to {} (max {})",
+                before.0, before.1, after.0, after.1
+            ),
+        )
+    }
+
+    pub fn serialize(&self) -> String {
+        format!(
+            "{},{},{},{}",
+            self.time, self.action, self.name, self.detail
+        )
+    }
+
+    pub fn deserialize(line: &str) -> Result<Self, String> {
+        // the detail is last so it can contain commas
+        let values: Vec<_> = line.splitn(4, ',').collect();
+        match values.as_slice() {
+            [time, action, name, detail] => Ok(Self {
+                time: time
+                    .parse::<DateTime<Local>>()
+                    .map_err(|err| tr!("expected local datetime for time: {}", err))?,
+                action: (*action).to_owned(),
+                name: (*name).to_owned(),
+                detail: (*detail).to_owned(),
+            }),
+            _ => Err(tr!(
+                "expected time, action, name and detail for an audit record: \"{}\"",
+                line
+            )),
+        }
+    }
+}
+
+fn ensure_audit_path() -> Result<PathBuf, String> {
+    let mut path = ensure_data_dir()?;
+    path.push("audit.txt");
+    Ok(path)
+}
+
+pub fn read_audit() -> Result<Vec<Record>, String> {
+    let path = ensure_audit_path()?;
+    let bytes = match fs::read(&path) {
+        Ok(bytes) => bytes,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Vec::new(),
+        Err(err) => return Err(tr!("couldn't read audit log: {}", err)),
+    };
+    let string = crypto::decode(bytes).map_err(|err| tr!("couldn't decode audit log: {}", err))?;
+    string
+        .lines()
+        .enumerate()
+        .map(|(line_number, line)| {
+            Record::deserialize(line).map_err(|err| {
+                tr!(
+                    "failed to parse audit log on line {}: {}",
+                    line_number + 1,
+                    err
+                )
+            })
+        })
+        .collect()
+}
+
+fn serialize_audit(records: &[Record]) -> String {
+    records
+        .iter()
+        .map(|record| record.serialize() + "\n")
+        .collect()
+}
+
+/// Append new records to the end of the audit log, which is never rewritten otherwise
+pub fn append(records: &[Record]) {
+    if records.is_empty() {
+        return;
+    }
+    let path = match ensure_audit_path() {
+        Ok(path) => path,
+        Err(err) => {
+            eprintln!("{}", tr!("couldn't update audit log: {}", err));
+            return;
+        }
+    };
+    if crypto::enabled() {
+        // the ciphertext can't be appended to, so the whole file has to be rewritten
+        let result = read_audit().and_then(|mut existing| {
+            existing.extend(records.iter().cloned());
+            let bytes = crypto::encode(&serialize_audit(&existing))?;
+            fs::write(&path, bytes).map_err(|err| err.to_string())
+        });
+        if let Err(err) = result {
+            eprintln!("{}", tr!("couldn't update audit log: {}", err));
+        }
+        return;
+    }
+    let result = OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open(&path)
+        .and_then(|mut file| file.write_all(serialize_audit(records).as_bytes()));
+    if let Err(err) = result {
+        eprintln!("{}", tr!("couldn't write audit log: {}", err));
+    }
+}
+
+/// Print the audit log newest first like `git log`, optionally only about one streak and only
+/// the latest so many records
+pub fn print_log(name: Option<&str>, limit: Option<usize>) -> Result<(), Error> {
+    let records = match read_audit() {
+        Ok(records) => records,
+        Err(err) => {
+            eprintln!("{}", err);
+            return Err(Error::Failed);
+        }
+    };
+    let shown = records
+        .iter()
+        .rev()
+        .filter(|record| name.is_none_or(|name| record.name == name))
+        .take(limit.unwrap_or(usize::MAX));
+    for (i, record) in shown.enumerate() {
+        if i > 0 {
+            println!();
+        }
+        println!("{} {}", record.action, record.name);
+        println!(
+            "{}",
+            tr!("Date:   {}", record.time.format("%a %b %e %H:%M:%S %Y %z"))
+        );
+        if !record.detail.is_empty() {
+            println!();
+            println!("    {}", record.detail);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,125 @@
+use crate::{crypto, ensure_data_dir, error::Error, history, tr, State, StateLock};
+use chrono::prelude::*;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Entries of the data directory which are never part of a backup
+const EXCLUDED: [&str; 4] = ["backups", "profiles", "state.lock", ".git"];
+
+fn default_backup_path() -> Result<PathBuf, Error> {
+    let mut path = ensure_data_dir()?;
+    path.push("backups");
+    path.push(Local::now().format("backup-%Y%m%d-%H%M%S").to_string());
+    Ok(path)
+}
+
+/// Copy the regular files of one directory into another, skipping excluded names
+fn copy_files(from: &Path, to: &Path) -> io::Result<usize> {
+    fs::create_dir_all(to)?;
+    let mut copied = 0;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        if EXCLUDED.iter().any(|excluded| name == *excluded) || !entry.file_type()?.is_file() {
+            continue;
+        }
+        fs::copy(entry.path(), to.join(&name))?;
+        copied += 1;
+    }
+    Ok(copied)
+}
+
+/// Remove the regular files of a directory which don't exist in another
+fn remove_extra_files(dir: &Path, reference: &Path) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        if EXCLUDED.iter().any(|excluded| name == *excluded) || !entry.file_type()?.is_file() {
+            continue;
+        }
+        if !reference.join(&name).exists() {
+            fs::remove_file(entry.path())?;
+        }
+    }
+    Ok(())
+}
+
+/// Check that the data files in a backup can actually be loaded
+fn verify(dir: &Path) -> Result<(), String> {
+    let read = |file: &str| -> Result<Option<String>, String> {
+        match fs::read(dir.join(file)) {
+            Ok(bytes) => crypto::decode(bytes)
+                .map(Some)
+                .map_err(|err| tr!("couldn't decode {}: {}", file, err)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(tr!("couldn't read {}: {}", file, err)),
+        }
+    };
+    let state = read("state.txt")?.ok_or_else(|| tr!("no state.txt in backup"))?;
+    State::deserialize(&state).map_err(|err| tr!("couldn't parse state.txt: {}", err))?;
+    if let Some(entries) = read("history.txt")? {
+        history::deserialize_history(&entries)
+            .map_err(|err| tr!("couldn't parse history.txt: {}", err))?;
+    }
+    Ok(())
+}
+
+/// Snapshot the data directory, to the given path or a timestamped folder in the data directory
+pub fn backup(path: Option<&Path>) -> Result<PathBuf, Error> {
+    let path = match path {
+        Some(path) => path.to_owned(),
+        None => default_backup_path()?,
+    };
+    let _lock = StateLock::acquire()?;
+    match copy_files(&ensure_data_dir()?, &path) {
+        Ok(copied) => {
+            println!(
+                "{}",
+                tr!("backed up {} files to {}", copied, path.display())
+            );
+            Ok(path)
+        }
+        Err(err) => {
+            eprintln!("{}", tr!("couldn't back up to {}: {}", path.display(), err));
+            Err(Error::Failed)
+        }
+    }
+}
+
+/// Replace the data directory with a verified backup, first backing up the current data
+pub fn restore(path: &Path) -> Result<(), Error> {
+    if let Err(err) = verify(path) {
+        eprintln!(
+            "{}",
+            tr!("not restoring invalid backup {}: {}", path.display(), err)
+        );
+        return Err(Error::Failed);
+    }
+    if let Err(err) = backup(None) {
+        eprintln!(
+            "{}",
+            tr!("not restoring since the current data couldn't be backed up")
+        );
+        return Err(err);
+    }
+    let _lock = StateLock::acquire()?;
+    let data_dir = ensure_data_dir()?;
+    match remove_extra_files(&data_dir, path).and_then(|()| copy_files(path, &data_dir)) {
+        Ok(copied) => {
+            println!(
+                "{}",
+                tr!("restored {} files from {}", copied, path.display())
+            );
+            Ok(())
+        }
+        Err(err) => {
+            eprintln!(
+                "{}",
+                tr!("couldn't restore from {}: {}", path.display(), err)
+            );
+            Err(Error::Failed)
+        }
+    }
+}
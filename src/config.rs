@@ -0,0 +1,637 @@
+use crate::{error::Error, parse_weekday, templates::Template, validate_tag, Schedule, MILESTONES};
+use chrono::NaiveDate;
+use std::{collections::HashSet, convert::TryFrom, fs, io, path::PathBuf, sync::OnceLock};
+use toml::Value;
+
+/// Credentials for the Habitica API
+pub struct Habitica {
+    pub user_id: String,
+    pub api_token: String,
+}
+
+/// What a webhook expects to be posted to it
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Service {
+    /// A JSON object from the template
+    Json,
+    /// A Slack incoming webhook, taking the template as the message text
+    Slack,
+    /// A Discord webhook, taking the template as the message content
+    Discord,
+}
+
+/// An HTTP endpoint notified of streak events
+pub struct Webhook {
+    pub url: String,
+    pub service: Service,
+    /// Which of "hit", "expire", "milestone" and "remind" to send, all of them if unset, or only
+    /// milestones and expiries for chat services
+    pub events: Option<Vec<String>>,
+    pub template: Option<String>,
+}
+
+/// Shell commands run on streak events, with the streak name and count appended
+#[derive(Default)]
+pub struct Hooks {
+    pub hit: Option<String>,
+    pub expire: Option<String>,
+    pub milestone: Option<String>,
+    pub remind: Option<String>,
+}
+
+impl Hooks {
+    pub fn command(&self, kind: &str) -> Option<&str> {
+        match kind {
+            "hit" => self.hit.as_deref(),
+            "expire" => self.expire.as_deref(),
+            "milestone" => self.milestone.as_deref(),
+            "remind" => self.remind.as_deref(),
+            _ => None,
+        }
+    }
+}
+
+/// Symbols standing in for the state of each streak in glyph mode
+pub struct Glyphs {
+    pub done: String,
+    pub pending: String,
+    pub expired: String,
+    pub new: String,
+}
+
+impl Default for Glyphs {
+    fn default() -> Self {
+        Self {
+            done: "✅".to_owned(),
+            pending: "🕑".to_owned(),
+            expired: "❌".to_owned(),
+            new: "🆕".to_owned(),
+        }
+    }
+}
+
+/// Symbols before each count in `status --plain`
+pub struct StatusSymbols {
+    pub done: String,
+    pub pending: String,
+    pub expired: String,
+}
+
+impl Default for StatusSymbols {
+    fn default() -> Self {
+        Self {
+            done: "●".to_owned(),
+            pending: "○".to_owned(),
+            expired: "✗".to_owned(),
+        }
+    }
+}
+
+/// A streaks server holding streaks shared with other people
+pub struct Shared {
+    pub url: String,
+    /// Names of the streaks which live on the server instead of locally
+    pub streaks: Vec<String>,
+}
+
+/// A git repository to sync the data directory with
+pub struct Git {
+    pub remote: String,
+    pub branch: String,
+}
+
+/// A WebDAV folder holding the canonical copy of the state and history
+pub struct WebDav {
+    pub url: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+/// Addresses for `digest --email`, and the SMTP server to send it through if not piped to
+/// sendmail
+pub struct Email {
+    pub from: String,
+    pub to: String,
+    pub smtp_host: Option<String>,
+    pub smtp_port: Option<u16>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+/// An MQTT broker to publish streak events to
+pub struct Mqtt {
+    pub host: String,
+    pub port: u16,
+    /// Topic of each event, where `{name}` and `{event}` are substituted
+    pub topic: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Which of "hit", "expire", "milestone" and "remind" to publish, all of them if unset
+    pub events: Option<Vec<String>>,
+}
+
+/// A Telegram bot sending reminders to a chat and taking `/hit <name>` from it
+pub struct Telegram {
+    pub token: String,
+    /// The only chat the bot talks to, as a number or `@channel` name
+    pub chat_id: String,
+}
+
+/// Encryption of the data files at rest, keyed by a keyfile or the `STREAKS_PASSPHRASE` variable
+pub struct Encryption {
+    pub keyfile: Option<PathBuf>,
+}
+
+/// Days that don't count, on which streaks aren't expected to be hit
+#[derive(Default)]
+pub struct Holidays {
+    pub dates: Vec<NaiveDate>,
+    /// An iCalendar file of holidays, like one a country's public holidays can be downloaded as
+    pub calendar: Option<PathBuf>,
+}
+
+impl Holidays {
+    /// The configured dates with those of the calendar, which is read each time so that it can
+    /// be replaced with next year's without touching the config
+    pub fn all(&self) -> HashSet<NaiveDate> {
+        let mut dates: HashSet<_> = self.dates.iter().copied().collect();
+        if let Some(calendar) = &self.calendar {
+            match fs::read_to_string(calendar) {
+                // only the first day of each event, which is all a public holiday has
+                Ok(string) => dates.extend(
+                    string
+                        .lines()
+                        .filter(|line| line.starts_with("DTSTART"))
+                        .filter_map(|line| line.rsplit(':').next())
+                        .filter_map(|value| value.get(..8))
+                        .filter_map(|value| NaiveDate::parse_from_str(value, "%Y%m%d").ok()),
+                ),
+                Err(err) => eprintln!(
+                    "couldn't read holiday calendar {}: {}",
+                    calendar.display(),
+                    err
+                ),
+            }
+        }
+        dates
+    }
+}
+
+/// User configuration read from `config.toml`, every section being optional
+pub struct Config {
+    pub habitica: Option<Habitica>,
+    pub webhooks: Vec<Webhook>,
+    pub hooks: Hooks,
+    pub shared: Option<Shared>,
+    pub git: Option<Git>,
+    pub email: Option<Email>,
+    pub telegram: Option<Telegram>,
+    pub mqtt: Option<Mqtt>,
+    pub webdav: Option<WebDav>,
+    pub encryption: Option<Encryption>,
+    /// How many previous versions of the state file to keep around
+    pub keep_backups: u32,
+    /// Named presets for `add --template`
+    pub templates: Vec<(String, Template)>,
+    /// Streak counts to celebrate and record in the history
+    pub milestones: Vec<u32>,
+    /// How long removed streaks can still be undeleted
+    pub keep_trash_days: u32,
+    /// Line format for `display` instead of the table
+    pub display_format: Option<String>,
+    /// Columns of the `display` table in order, the default ones if unset
+    pub display_columns: Option<Vec<String>>,
+    /// Whether `display` shows recent hits in the table
+    pub sparklines: bool,
+    /// Whether `display` shows states as glyphs
+    pub use_glyphs: bool,
+    /// Whether `display` leaves out streaks already done
+    pub hide_done: bool,
+    pub glyphs: Glyphs,
+    pub status_symbols: StatusSymbols,
+    /// Language of the messages, overriding `LANG`
+    pub language: Option<String>,
+    /// Whether streak names are looked up regardless of case
+    pub ignore_case: bool,
+    /// How many consecutive hits earn a skip token, none if skips are turned off
+    pub skip_every: Option<u32>,
+    pub holidays: Holidays,
+    /// Hour at which one day turns into the next, midnight unless set
+    pub day_start_hour: u32,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            habitica: None,
+            webhooks: Vec::new(),
+            hooks: Hooks::default(),
+            shared: None,
+            git: None,
+            email: None,
+            telegram: None,
+            mqtt: None,
+            webdav: None,
+            encryption: None,
+            keep_backups: 3,
+            templates: Vec::new(),
+            milestones: MILESTONES.to_vec(),
+            keep_trash_days: 30,
+            display_format: None,
+            display_columns: None,
+            sparklines: false,
+            use_glyphs: false,
+            hide_done: false,
+            glyphs: Glyphs::default(),
+            status_symbols: StatusSymbols::default(),
+            language: None,
+            ignore_case: true,
+            skip_every: None,
+            holidays: Holidays::default(),
+            day_start_hour: 0,
+        }
+    }
+}
+
+// errors found while loading the config stay untranslated, since `tr!` needs the config to
+// know the language and would wait on it forever
+
+fn get_str(table: &Value, section: &str, key: &str) -> Result<Option<String>, String> {
+    match table.get(key) {
+        None => Ok(None),
+        Some(value) => value
+            .as_str()
+            .map(|s| Some(s.to_owned()))
+            .ok_or_else(|| format!("expected string for {}.{}", section, key)),
+    }
+}
+
+fn get_str_list(table: &Value, section: &str, key: &str) -> Result<Option<Vec<String>>, String> {
+    let error = || format!("expected list of strings for {}.{}", section, key);
+    match table.get(key) {
+        None => Ok(None),
+        Some(value) => value
+            .as_array()
+            .ok_or_else(error)?
+            .iter()
+            .map(|item| item.as_str().map(str::to_owned).ok_or_else(error))
+            .collect::<Result<_, _>>()
+            .map(Some),
+    }
+}
+
+fn get_bool(table: &Value, section: &str, key: &str) -> Result<Option<bool>, String> {
+    match table.get(key) {
+        None => Ok(None),
+        Some(value) => value
+            .as_bool()
+            .map(Some)
+            .ok_or_else(|| format!("expected boolean for {}.{}", section, key)),
+    }
+}
+
+fn get_u32(table: &Value, section: &str, key: &str) -> Result<Option<u32>, String> {
+    match table.get(key) {
+        None => Ok(None),
+        Some(value) => value
+            .as_integer()
+            .and_then(|n| u32::try_from(n).ok())
+            .map(Some)
+            .ok_or_else(|| format!("expected unsigned integer for {}.{}", section, key)),
+    }
+}
+
+fn get_u32_list(table: &Value, section: &str, key: &str) -> Result<Option<Vec<u32>>, String> {
+    let error = || format!("expected list of unsigned integers for {}.{}", section, key);
+    match table.get(key) {
+        None => Ok(None),
+        Some(value) => value
+            .as_array()
+            .ok_or_else(error)?
+            .iter()
+            .map(|item| {
+                item.as_integer()
+                    .and_then(|n| u32::try_from(n).ok())
+                    .ok_or_else(error)
+            })
+            .collect::<Result<_, _>>()
+            .map(Some),
+    }
+}
+
+fn require_str(table: &Value, section: &str, key: &str) -> Result<String, String> {
+    get_str(table, section, key)?.ok_or_else(|| format!("missing {}.{}", section, key))
+}
+
+fn template(table: &Value, name: &str) -> Result<Template, String> {
+    let section = format!("templates.{}", name);
+    let schedule = match (
+        get_u32(table, &section, "every")?,
+        get_str_list(table, &section, "days")?,
+    ) {
+        (Some(_), Some(_)) => {
+            return Err(format!(
+                "only one of every and days allowed for {}",
+                section
+            ))
+        }
+        (Some(0), None) => return Err(format!("expected positive {}.every", section)),
+        (Some(days), None) => Schedule::Every(days),
+        (None, Some(days)) if days.is_empty() => {
+            return Err(format!("expected some days for {}.days", section))
+        }
+        (None, Some(days)) => Schedule::Days(
+            days.iter()
+                .map(|day| parse_weekday(day))
+                .collect::<Result<_, _>>()?,
+        ),
+        (None, None) => Schedule::Daily,
+    };
+    let tags = get_str_list(table, &section, "tags")?.unwrap_or_default();
+    for tag in tags.iter() {
+        validate_tag(tag)?;
+    }
+    Ok(Template {
+        schedule,
+        target: get_u32(table, &section, "target")?.unwrap_or(1).max(1),
+        tags,
+        note: get_str(table, &section, "note")?,
+        reminder: None,
+    })
+}
+
+impl Config {
+    fn deserialize(string: &str) -> Result<Self, String> {
+        let root = string.parse::<Value>().map_err(|err| err.to_string())?;
+        let habitica = match root.get("habitica") {
+            None => None,
+            Some(table) => Some(Habitica {
+                user_id: require_str(table, "habitica", "user_id")?,
+                api_token: require_str(table, "habitica", "api_token")?,
+            }),
+        };
+        let webhooks = match root.get("webhooks") {
+            None => Vec::new(),
+            Some(value) => value
+                .as_array()
+                .ok_or("expected [[webhooks]] to be a list of tables")?
+                .iter()
+                .map(|table| {
+                    Ok(Webhook {
+                        url: require_str(table, "webhooks", "url")?,
+                        service: match get_str(table, "webhooks", "service")?.as_deref() {
+                            None | Some("json") => Service::Json,
+                            Some("slack") => Service::Slack,
+                            Some("discord") => Service::Discord,
+                            Some(other) => {
+                                return Err(format!(
+                                "expected json, slack or discord for webhooks.service, got \"{}\"",
+                                other
+                            ))
+                            }
+                        },
+                        events: get_str_list(table, "webhooks", "events")?,
+                        template: get_str(table, "webhooks", "template")?,
+                    })
+                })
+                .collect::<Result<_, String>>()?,
+        };
+        let hooks = match root.get("hooks") {
+            None => Hooks::default(),
+            Some(table) => Hooks {
+                hit: get_str(table, "hooks", "hit")?,
+                expire: get_str(table, "hooks", "expire")?,
+                milestone: get_str(table, "hooks", "milestone")?,
+                remind: get_str(table, "hooks", "remind")?,
+            },
+        };
+        let shared = match root.get("shared") {
+            None => None,
+            Some(table) => Some(Shared {
+                url: require_str(table, "shared", "url")?,
+                streaks: get_str_list(table, "shared", "streaks")?.unwrap_or_default(),
+            }),
+        };
+        let git = match root.get("git") {
+            None => None,
+            Some(table) => Some(Git {
+                remote: require_str(table, "git", "remote")?,
+                branch: get_str(table, "git", "branch")?.unwrap_or_else(|| "main".to_owned()),
+            }),
+        };
+        let webdav = match root.get("webdav") {
+            None => None,
+            Some(table) => Some(WebDav {
+                url: require_str(table, "webdav", "url")?,
+                username: get_str(table, "webdav", "username")?,
+                password: get_str(table, "webdav", "password")?,
+            }),
+        };
+        let email = match root.get("email") {
+            None => None,
+            Some(table) => Some(Email {
+                from: require_str(table, "email", "from")?,
+                to: require_str(table, "email", "to")?,
+                smtp_host: get_str(table, "email", "smtp_host")?,
+                smtp_port: get_u32(table, "email", "smtp_port")?
+                    .map(|port| {
+                        u16::try_from(port)
+                            .map_err(|_| "expected port number for email.smtp_port".to_owned())
+                    })
+                    .transpose()?,
+                username: get_str(table, "email", "username")?,
+                password: get_str(table, "email", "password")?,
+            }),
+        };
+        let telegram = match root.get("telegram") {
+            None => None,
+            Some(table) => Some(Telegram {
+                token: require_str(table, "telegram", "token")?,
+                chat_id: require_str(table, "telegram", "chat_id")?,
+            }),
+        };
+        let mqtt = match root.get("mqtt") {
+            None => None,
+            Some(table) => Some(Mqtt {
+                host: require_str(table, "mqtt", "host")?,
+                port: get_u32(table, "mqtt", "port")?
+                    .map(|port| {
+                        u16::try_from(port)
+                            .map_err(|_| "expected port number for mqtt.port".to_owned())
+                    })
+                    .transpose()?
+                    .unwrap_or(1883),
+                topic: get_str(table, "mqtt", "topic")?
+                    .unwrap_or_else(|| "streaks/{event}".to_owned()),
+                username: get_str(table, "mqtt", "username")?,
+                password: get_str(table, "mqtt", "password")?,
+                events: get_str_list(table, "mqtt", "events")?,
+            }),
+        };
+        let encryption = match root.get("encryption") {
+            None => None,
+            Some(table) => Some(Encryption {
+                keyfile: get_str(table, "encryption", "keyfile")?.map(PathBuf::from),
+            }),
+        };
+        let holidays = match root.get("holidays") {
+            None => Holidays::default(),
+            Some(table) => Holidays {
+                dates: get_str_list(table, "holidays", "dates")?
+                    .unwrap_or_default()
+                    .iter()
+                    .map(|date| {
+                        date.parse::<NaiveDate>().map_err(|_| {
+                            format!("expected YYYY-MM-DD for holidays.dates: {}", date)
+                        })
+                    })
+                    .collect::<Result<_, _>>()?,
+                calendar: get_str(table, "holidays", "calendar")?.map(PathBuf::from),
+            },
+        };
+        let day_start_hour = match root.get("days") {
+            None => None,
+            Some(table) => match get_u32(table, "days", "start_hour")? {
+                Some(hour) if hour > 23 => {
+                    return Err("expected days.start_hour to be from 0 to 23".to_owned())
+                }
+                hour => hour,
+            },
+        };
+        let keep_backups = match root.get("backups") {
+            None => None,
+            Some(table) => get_u32(table, "backups", "keep")?,
+        };
+        let templates = match root.get("templates") {
+            None => Vec::new(),
+            Some(value) => value
+                .as_table()
+                .ok_or("expected [templates] to be a table")?
+                .iter()
+                .map(|(name, table)| Ok((name.clone(), template(table, name)?)))
+                .collect::<Result<_, String>>()?,
+        };
+        let milestones = match root.get("milestones") {
+            None => None,
+            Some(table) => get_u32_list(table, "milestones", "counts")?,
+        };
+        let keep_trash_days = match root.get("trash") {
+            None => None,
+            Some(table) => get_u32(table, "trash", "keep_days")?,
+        };
+        let (display_format, display_columns, sparklines, use_glyphs, hide_done) =
+            match root.get("display") {
+                None => (None, None, None, None, None),
+                Some(table) => (
+                    get_str(table, "display", "format")?,
+                    get_str_list(table, "display", "columns")?,
+                    get_bool(table, "display", "sparkline")?,
+                    get_bool(table, "display", "glyphs")?,
+                    get_bool(table, "display", "hide_done")?,
+                ),
+            };
+        let glyphs = match root.get("glyphs") {
+            None => Glyphs::default(),
+            Some(table) => {
+                let default = Glyphs::default();
+                Glyphs {
+                    done: get_str(table, "glyphs", "done")?.unwrap_or(default.done),
+                    pending: get_str(table, "glyphs", "pending")?.unwrap_or(default.pending),
+                    expired: get_str(table, "glyphs", "expired")?.unwrap_or(default.expired),
+                    new: get_str(table, "glyphs", "new")?.unwrap_or(default.new),
+                }
+            }
+        };
+        let status_symbols = match root.get("status") {
+            None => StatusSymbols::default(),
+            Some(table) => {
+                let default = StatusSymbols::default();
+                StatusSymbols {
+                    done: get_str(table, "status", "done")?.unwrap_or(default.done),
+                    pending: get_str(table, "status", "pending")?.unwrap_or(default.pending),
+                    expired: get_str(table, "status", "expired")?.unwrap_or(default.expired),
+                }
+            }
+        };
+        let language = match root.get("locale") {
+            None => None,
+            Some(table) => get_str(table, "locale", "language")?,
+        };
+        let ignore_case = match root.get("names") {
+            None => None,
+            Some(table) => get_bool(table, "names", "ignore_case")?,
+        };
+        let skip_every = match root.get("skips") {
+            None => None,
+            Some(table) => match get_u32(table, "skips", "every")? {
+                Some(0) => return Err("expected skips.every to be positive".to_owned()),
+                every => every,
+            },
+        };
+        Ok(Self {
+            habitica,
+            webhooks,
+            hooks,
+            shared,
+            git,
+            email,
+            telegram,
+            mqtt,
+            webdav,
+            encryption,
+            keep_backups: keep_backups.unwrap_or(Config::default().keep_backups),
+            templates,
+            milestones: milestones.unwrap_or_else(|| MILESTONES.to_vec()),
+            keep_trash_days: keep_trash_days.unwrap_or(Config::default().keep_trash_days),
+            display_format,
+            display_columns,
+            sparklines: sparklines.unwrap_or(Config::default().sparklines),
+            use_glyphs: use_glyphs.unwrap_or(Config::default().use_glyphs),
+            hide_done: hide_done.unwrap_or(Config::default().hide_done),
+            glyphs,
+            status_symbols,
+            language,
+            ignore_case: ignore_case.unwrap_or(Config::default().ignore_case),
+            skip_every,
+            holidays,
+            day_start_hour: day_start_hour.unwrap_or(Config::default().day_start_hour),
+        })
+    }
+}
+
+/// Where the config file is, if the platform has a config directory
+pub fn config_path() -> Option<PathBuf> {
+    let mut path = dirs::config_dir()?;
+    path.push("streaks");
+    path.push("config.toml");
+    Some(path)
+}
+
+/// Read the config file, falling back to the defaults if there isn't one
+pub fn load_config() -> Result<Config, Error> {
+    let path = match config_path() {
+        Some(path) => path,
+        None => return Ok(Config::default()),
+    };
+    match fs::read_to_string(&path) {
+        Ok(string) => Config::deserialize(&string).map_err(|err| Error::ParseConfig(path, err)),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Config::default()),
+        Err(err) => Err(Error::ReadConfig(path, err)),
+    }
+}
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+/// Load the config once for the whole command, failing if it can't be
+pub fn init() -> Result<&'static Config, Error> {
+    if let Some(config) = CONFIG.get() {
+        return Ok(config);
+    }
+    let config = load_config()?;
+    Ok(CONFIG.get_or_init(|| config))
+}
+
+/// The config, which `main` has already checked can be loaded
+pub fn read_config() -> &'static Config {
+    CONFIG.get_or_init(|| load_config().unwrap_or_default())
+}
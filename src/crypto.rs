@@ -0,0 +1,126 @@
+//! Optional encryption of the data files at rest, enabled by an `[encryption]` config section.
+//!
+//! Encrypted files start with a magic line followed by the salt and nonce, then the
+//! ChaCha20-Poly1305 ciphertext. The key is derived with Argon2 from the contents of the
+//! configured keyfile, or from the `STREAKS_PASSPHRASE` environment variable. Plaintext files
+//! are still read so that turning encryption on converts them on the next write.
+
+use crate::{config, tr};
+use std::{env, fs};
+
+const MAGIC: &[u8] = b"streaks-encrypted-v1\n";
+const PASSPHRASE_VAR: &str = "STREAKS_PASSPHRASE";
+
+/// Whether files are written encrypted
+pub fn enabled() -> bool {
+    config::read_config().encryption.is_some()
+}
+
+fn secret(encryption: &config::Encryption) -> Result<Vec<u8>, String> {
+    match &encryption.keyfile {
+        Some(keyfile) => fs::read(keyfile)
+            .map_err(|err| tr!("couldn't read keyfile {}: {}", keyfile.display(), err)),
+        None => env::var(PASSPHRASE_VAR)
+            .map(String::into_bytes)
+            .map_err(|_| tr!("set {} or configure a keyfile", PASSPHRASE_VAR)),
+    }
+}
+
+#[cfg(feature = "encryption")]
+mod cipher {
+    use crate::tr;
+    use chacha20poly1305::{
+        aead::{Aead, KeyInit},
+        ChaCha20Poly1305, Key, Nonce,
+    };
+
+    const SALT_LEN: usize = 16;
+    const NONCE_LEN: usize = 12;
+
+    fn derive_key(secret: &[u8], salt: &[u8]) -> Result<[u8; 32], String> {
+        let mut key = [0; 32];
+        argon2::Argon2::default()
+            .hash_password_into(secret, salt, &mut key)
+            .map_err(|err| tr!("couldn't derive key: {}", err))?;
+        Ok(key)
+    }
+
+    /// Returns the salt and nonce followed by the ciphertext
+    pub fn encrypt(secret: &[u8], plaintext: &[u8]) -> Result<Vec<u8>, String> {
+        let mut header = [0; SALT_LEN + NONCE_LEN];
+        getrandom::getrandom(&mut header).map_err(|err| tr!("couldn't generate nonce: {}", err))?;
+        let (salt, nonce) = header.split_at(SALT_LEN);
+        let key = derive_key(secret, salt)?;
+        let ciphertext = ChaCha20Poly1305::new(Key::from_slice(&key))
+            .encrypt(Nonce::from_slice(nonce), plaintext)
+            .map_err(|_| "encryption failed".to_owned())?;
+        Ok([&header[..], &ciphertext].concat())
+    }
+
+    pub fn decrypt(secret: &[u8], data: &[u8]) -> Result<Vec<u8>, String> {
+        if data.len() < SALT_LEN + NONCE_LEN {
+            return Err(tr!("encrypted file is truncated"));
+        }
+        let (salt, rest) = data.split_at(SALT_LEN);
+        let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+        let key = derive_key(secret, salt)?;
+        ChaCha20Poly1305::new(Key::from_slice(&key))
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| "wrong key or corrupted file".to_owned())
+    }
+}
+
+#[cfg(not(feature = "encryption"))]
+mod cipher {
+    const UNSUPPORTED: &str = "streaks was built without the \"encryption\" feature";
+
+    pub fn encrypt(_secret: &[u8], _plaintext: &[u8]) -> Result<Vec<u8>, String> {
+        Err(UNSUPPORTED.to_owned())
+    }
+
+    pub fn decrypt(_secret: &[u8], _data: &[u8]) -> Result<Vec<u8>, String> {
+        Err(UNSUPPORTED.to_owned())
+    }
+}
+
+/// Turn the raw contents of a data file back into text, decrypting it if needed
+pub fn decode(bytes: Vec<u8>) -> Result<String, String> {
+    String::from_utf8(decrypt(bytes)?).map_err(|err| tr!("invalid utf-8: {}", err))
+}
+
+/// Like `decode`, but replacing anything that isn't valid UTF-8 instead of failing, returning
+/// whether there was any
+pub fn decode_lossy(bytes: Vec<u8>) -> Result<(String, bool), String> {
+    match String::from_utf8(decrypt(bytes)?) {
+        Ok(string) => Ok((string, false)),
+        Err(err) => Ok((String::from_utf8_lossy(err.as_bytes()).into_owned(), true)),
+    }
+}
+
+fn decrypt(bytes: Vec<u8>) -> Result<Vec<u8>, String> {
+    match bytes.strip_prefix(MAGIC) {
+        Some(data) => {
+            let encryption = config::read_config().encryption.as_ref().ok_or_else(|| {
+                tr!("file is encrypted but there is no [encryption] config section")
+            })?;
+            cipher::decrypt(&secret(encryption)?, data)
+        }
+        None => Ok(bytes),
+    }
+}
+
+/// Whether the start of a data file shows it's encrypted, and so has to be decoded as a whole
+pub fn is_encrypted(start: &[u8]) -> bool {
+    start.starts_with(MAGIC)
+}
+
+/// Prepare text to be written to a data file, encrypting it if configured
+pub fn encode(string: &str) -> Result<Vec<u8>, String> {
+    match &config::read_config().encryption {
+        Some(encryption) => {
+            let ciphertext = cipher::encrypt(&secret(encryption)?, string.as_bytes())?;
+            Ok([MAGIC, &ciphertext].concat())
+        }
+        None => Ok(string.as_bytes().to_vec()),
+    }
+}
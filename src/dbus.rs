@@ -0,0 +1,142 @@
+//! Optional D-Bus service for desktop widgets, run by `streaks daemon`.
+//!
+//! The session bus name `io.github.Luminiscental.Streaks` serves the object
+//! `/io/github/Luminiscental/Streaks` with the `io.github.Luminiscental.Streaks1` interface:
+//! `ListStreaks() -> a(suus)` giving each streak's name, count, max and state, `Hit(s) -> u`
+//! hitting a streak by name or alias and returning its new count, and a `StateChanged` signal
+//! sent whenever the state file changes, whether through the service or the command line.
+
+use crate::{error::Error, tr};
+
+#[cfg(feature = "dbus-service")]
+mod service {
+    use crate::{ensure_state_path, eventlog, modify_state, read_state, tr, StreakState};
+    use dbus::{
+        blocking::Connection,
+        channel::{MatchingReceiver, Sender},
+        message::MatchRule,
+        Message, MethodErr,
+    };
+    use dbus_crossroads::{Crossroads, IfaceBuilder};
+    use itertools::Itertools;
+    use std::{fs, path::PathBuf, time::Duration, time::SystemTime};
+
+    const NAME: &str = "io.github.Luminiscental.Streaks";
+    const PATH: &str = "/io/github/Luminiscental/Streaks";
+    const INTERFACE: &str = "io.github.Luminiscental.Streaks1";
+    /// How often the state file is checked for changes
+    const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+    fn list_streaks() -> Result<Vec<(String, u32, u32, String)>, MethodErr> {
+        let state = read_state().map_err(|err| MethodErr::failed(&err))?;
+        Ok(state
+            .streaks
+            .iter()
+            .sorted_by_key(|pair| pair.0)
+            .map(|(name, streak)| {
+                (
+                    name.clone(),
+                    streak.current_count,
+                    streak.max_count,
+                    streak.state.serialize().to_owned(),
+                )
+            })
+            .collect())
+    }
+
+    fn hit(name: &str) -> Result<u32, MethodErr> {
+        let mut result = Err(MethodErr::invalid_arg(&tr!(
+            "no streak called \"{}\"",
+            name
+        )));
+        modify_state(|state| {
+            let name = match state.lookup_or_alias(name) {
+                Some(name) => name,
+                None => return,
+            };
+            result = if matches!(state.streaks[&name].state, StreakState::Done) {
+                Err(MethodErr::failed(&tr!(
+                    "\"{}\" is already done today",
+                    name
+                )))
+            } else {
+                state
+                    .hit_exact(&name)
+                    .ok_or_else(|| MethodErr::failed(&tr!("couldn't hit \"{}\"", name)))
+            };
+        })
+        .map_err(|err| MethodErr::failed(&err))?;
+        result
+    }
+
+    /// When the state last changed, in its snapshot or the log of changes since
+    fn modified(paths: &[PathBuf]) -> Option<SystemTime> {
+        paths
+            .iter()
+            .filter_map(|path| fs::metadata(path).and_then(|meta| meta.modified()).ok())
+            .max()
+    }
+
+    pub fn serve() -> Result<(), String> {
+        let connection = Connection::new_session()
+            .map_err(|err| tr!("couldn't connect to the session bus: {}", err))?;
+        connection
+            .request_name(NAME, false, true, true)
+            .map_err(|err| tr!("couldn't own {} on the session bus: {}", NAME, err))?;
+        let mut crossroads = Crossroads::new();
+        let interface = crossroads.register(INTERFACE, |builder: &mut IfaceBuilder<()>| {
+            builder.signal::<(), _>("StateChanged", ());
+            builder.method("ListStreaks", (), ("streaks",), |_, _, ()| {
+                list_streaks().map(|streaks| (streaks,))
+            });
+            builder.method("Hit", ("name",), ("count",), |_, _, (name,): (String,)| {
+                hit(&name).map(|count| (count,))
+            });
+        });
+        crossroads.insert(PATH, &[interface], ());
+        connection.start_receive(
+            MatchRule::new_method_call(),
+            Box::new(move |message, connection| {
+                if crossroads.handle_message(message, connection).is_err() {
+                    eprintln!("{}", tr!("couldn't handle d-bus message"));
+                }
+                true
+            }),
+        );
+        println!("{}", tr!("serving {} on the session bus", NAME));
+        let paths = [ensure_state_path()?, eventlog::log_path()?];
+        let mut last_modified = modified(&paths);
+        loop {
+            connection
+                .process(POLL_INTERVAL)
+                .map_err(|err| tr!("lost the session bus: {}", err))?;
+            let now_modified = modified(&paths);
+            if now_modified != last_modified {
+                last_modified = now_modified;
+                let signal = Message::new_signal(PATH, INTERFACE, "StateChanged")?;
+                if connection.send(signal).is_err() {
+                    eprintln!("{}", tr!("couldn't send StateChanged signal"));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "dbus-service"))]
+mod service {
+    use crate::tr;
+
+    pub fn serve() -> Result<(), String> {
+        Err(tr!(
+            "streaks was built without the \"dbus-service\" feature"
+        ))
+    }
+}
+
+/// Serve the D-Bus interface until interrupted
+pub fn serve() -> Result<(), Error> {
+    service::serve().map_err(|err| {
+        eprintln!("{}", tr!("couldn't run d-bus service: {}", err));
+        Error::Failed
+    })
+}
@@ -0,0 +1,197 @@
+use crate::{
+    config, current_day, day_of,
+    error::Error,
+    history,
+    report::{self, Period},
+    tr, State, StreakState,
+};
+use itertools::Itertools;
+use lettre::{
+    message::{Mailbox, MultiPart},
+    transport::smtp::authentication::Credentials,
+    Message, SmtpTransport, Transport,
+};
+
+const BOUNDARY: &str = "streaks-digest";
+
+/// The parts of the digest, rendered as both plain text and HTML
+struct Digest {
+    subject: String,
+    summary: String,
+    /// Each streak as its name and a line about it
+    lines: Vec<(String, String)>,
+}
+
+impl Digest {
+    fn new(state: &State, entries: &[history::Entry], period: &Period) -> Self {
+        let today = current_day();
+        let hits = report::days_hit(entries, period, today);
+        let done = state
+            .streaks
+            .values()
+            .filter(|streak| {
+                matches!(streak.state, StreakState::Done) && day_of(streak.last_hit) == today
+            })
+            .count();
+        let at_risk: Vec<_> = state
+            .streaks
+            .iter()
+            .filter(|(_name, streak)| streak.current_count > 0 && streak.due_today(today))
+            .map(|(name, _streak)| name.as_str())
+            .sorted()
+            .collect();
+        let mut summary = tr!("{} of {} streaks done today.", done, state.streaks.len());
+        if !at_risk.is_empty() {
+            summary += " ";
+            summary += &tr!("At risk: {}.", at_risk.join(", "));
+        }
+        let lines = state
+            .streaks
+            .iter()
+            .sorted_by_key(|pair| pair.0)
+            .map(|(name, streak)| {
+                let line = tr!(
+                    "{} (max {}), {}, hit on {} of {} days",
+                    streak.current_count,
+                    streak.max_count,
+                    streak.state.serialize(),
+                    hits.get(name.as_str()).copied().unwrap_or(0),
+                    period.days()
+                );
+                (name.clone(), line)
+            })
+            .collect();
+        Self {
+            subject: tr!("{} streak digest for {}", period.title(), today),
+            summary,
+            lines,
+        }
+    }
+
+    fn text(&self) -> String {
+        let mut text = format!("{}\n\n{}\n\n", self.subject, self.summary);
+        for (name, line) in self.lines.iter() {
+            text += &format!("{}: {}\n", name, line);
+        }
+        text
+    }
+
+    fn html(&self) -> String {
+        let rows: String = self
+            .lines
+            .iter()
+            .map(|(name, line)| {
+                format!(
+                    "<tr><td><b>{}</b></td><td>{}</td></tr>\n",
+                    html_escape(name),
+                    html_escape(line)
+                )
+            })
+            .collect();
+        format!(
+            "<html><body>\n<h1>{}</h1>\n<p>{}</p>\n<table>\n{}</table>\n</body></html>\n",
+            html_escape(&self.subject),
+            html_escape(&self.summary),
+            rows
+        )
+    }
+
+    /// The whole message with headers, ready to pipe into `sendmail -t`
+    fn mime(&self, email: Option<&config::Email>) -> String {
+        let mut message = String::new();
+        if let Some(email) = email {
+            message += &format!("From: {}\nTo: {}\n", email.from, email.to);
+        }
+        message += &format!(
+            "Subject: {}\nMIME-Version: 1.0\nContent-Type: multipart/alternative; boundary=\"{}\"\n\n",
+            self.subject, BOUNDARY
+        );
+        for (kind, body) in [("plain", self.text()), ("html", self.html())] {
+            message += &format!(
+                "--{}\nContent-Type: text/{}; charset=utf-8\nContent-Transfer-Encoding: 8bit\n\n{}\n",
+                BOUNDARY, kind, body
+            );
+        }
+        message + &format!("--{}--\n", BOUNDARY)
+    }
+}
+
+fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn send(digest: &Digest, email: &config::Email) -> Result<(), String> {
+    let host = email.smtp_host.as_deref().ok_or_else(|| {
+        tr!("set email.smtp_host to send the digest, or pipe it to sendmail instead")
+    })?;
+    let mailbox = |address: &str| {
+        address
+            .parse::<Mailbox>()
+            .map_err(|err| tr!("invalid address \"{}\": {}", address, err))
+    };
+    let message = Message::builder()
+        .from(mailbox(&email.from)?)
+        .to(mailbox(&email.to)?)
+        .subject(digest.subject.clone())
+        .multipart(MultiPart::alternative_plain_html(
+            digest.text(),
+            digest.html(),
+        ))
+        .map_err(|err| tr!("couldn't build email: {}", err))?;
+    let mut transport =
+        SmtpTransport::relay(host).map_err(|err| tr!("couldn't connect to {}: {}", host, err))?;
+    if let Some(port) = email.smtp_port {
+        transport = transport.port(port);
+    }
+    if let (Some(username), Some(password)) = (&email.username, &email.password) {
+        transport = transport.credentials(Credentials::new(username.clone(), password.clone()));
+    }
+    transport
+        .build()
+        .send(&message)
+        .map(|_| ())
+        .map_err(|err| tr!("couldn't send email through {}: {}", host, err))
+}
+
+/// Print the digest as an email for sendmail, or send it through the configured SMTP server
+pub fn email(state: &State, period: &Period, smtp: bool) -> Result<(), Error> {
+    let entries = history::read_history().unwrap_or_else(|err| {
+        eprintln!(
+            "{}",
+            tr!("couldn't read history, hits will be missing: {}", err)
+        );
+        Vec::new()
+    });
+    let digest = Digest::new(state, &entries, period);
+    let config = config::read_config();
+    if !smtp {
+        print!("{}", digest.mime(config.email.as_ref()));
+        return Ok(());
+    }
+    let email = match &config.email {
+        Some(email) => email,
+        None => {
+            eprintln!(
+                "{}",
+                tr!(
+                    "no [email] section with from and to in {}",
+                    config::config_path().unwrap_or_default().display()
+                )
+            );
+            return Err(Error::Failed);
+        }
+    };
+    match send(&digest, email) {
+        Ok(()) => {
+            println!("{}", tr!("sent digest to {}", email.to));
+            Ok(())
+        }
+        Err(err) => {
+            eprintln!("{}", err);
+            Err(Error::Failed)
+        }
+    }
+}
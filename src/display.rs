@@ -0,0 +1,132 @@
+use crate::{day_of, format_remaining, i18n, tr, State, Streak, StreakState};
+use chrono::prelude::*;
+use itertools::Itertools;
+
+enum Field {
+    Name,
+    Count,
+    Max,
+    State,
+    LastHit,
+    /// The last hit as e.g. `2 days ago`
+    LastHitRelative,
+    LastHitDate,
+    Expires,
+    Schedule,
+    Target,
+    Tags,
+    Note,
+    Skips,
+}
+
+enum Segment {
+    Text(String),
+    Field(Field),
+}
+
+const FIELDS: &str =
+    "name, count, max, state, last_hit[:relative|:date], expires, schedule, target, tags, note, skips";
+
+fn field(placeholder: &str) -> Result<Field, String> {
+    Ok(match placeholder {
+        "name" => Field::Name,
+        "count" => Field::Count,
+        "max" => Field::Max,
+        "state" => Field::State,
+        "last_hit" => Field::LastHit,
+        "last_hit:relative" => Field::LastHitRelative,
+        "last_hit:date" => Field::LastHitDate,
+        "expires" => Field::Expires,
+        "schedule" => Field::Schedule,
+        "target" => Field::Target,
+        "tags" => Field::Tags,
+        "note" => Field::Note,
+        "skips" => Field::Skips,
+        _ => {
+            return Err(tr!(
+                "unknown placeholder {{{}}}, expected one of {}",
+                placeholder,
+                FIELDS
+            ))
+        }
+    })
+}
+
+/// Split a format like `{name}: {count}/{max}` into text and fields, `{{` and `}}` being braces
+fn parse(format: &str) -> Result<Vec<Segment>, String> {
+    let mut segments = Vec::new();
+    let mut text = String::new();
+    let mut chars = format.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                text.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                text.push('}');
+            }
+            '{' => {
+                let placeholder: String = chars.by_ref().take_while(|&c| c != '}').collect();
+                if !text.is_empty() {
+                    segments.push(Segment::Text(std::mem::take(&mut text)));
+                }
+                segments.push(Segment::Field(field(&placeholder)?));
+            }
+            c => text.push(c),
+        }
+    }
+    if !text.is_empty() {
+        segments.push(Segment::Text(text));
+    }
+    Ok(segments)
+}
+
+fn relative(date: NaiveDate, today: NaiveDate) -> String {
+    match today.signed_duration_since(date).num_days() {
+        0 => tr!("today"),
+        1 => tr!("yesterday"),
+        days => tr!("{} days ago", days),
+    }
+}
+
+fn render(segments: &[Segment], name: &str, streak: &Streak, now: DateTime<Local>) -> String {
+    segments
+        .iter()
+        .map(|segment| match segment {
+            Segment::Text(text) => text.clone(),
+            Segment::Field(field) => match field {
+                Field::Name => name.to_owned(),
+                Field::Count => streak.current_count.to_string(),
+                Field::Max => streak.max_count.to_string(),
+                Field::State => i18n::translate(streak.state.serialize(), &[]),
+                Field::LastHit => streak.last_hit.format("%Y-%m-%d %H:%M").to_string(),
+                Field::LastHitRelative => relative(day_of(streak.last_hit), day_of(now)),
+                Field::LastHitDate => streak.last_hit.format("%Y-%m-%d").to_string(),
+                Field::Expires => match streak.state {
+                    StreakState::Pending => streak
+                        .expires_at(now)
+                        .map(|expiry| format_remaining(expiry.signed_duration_since(now)))
+                        .unwrap_or_default(),
+                    _ => String::new(),
+                },
+                Field::Schedule => streak.schedule.describe(),
+                Field::Target => streak.target.to_string(),
+                Field::Tags => streak.tags.join(" "),
+                Field::Note => streak.note.clone().unwrap_or_default(),
+                Field::Skips => streak.skips.to_string(),
+            },
+        })
+        .collect()
+}
+
+/// Print a line per streak in the given format instead of the table
+pub fn print_formatted(state: &State, format: &str) -> Result<(), String> {
+    let segments = parse(format)?;
+    let now = Local::now();
+    for (name, streak) in state.streaks.iter().sorted_by_key(|pair| pair.0) {
+        println!("{}", render(&segments, name, streak, now));
+    }
+    Ok(())
+}
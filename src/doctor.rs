@@ -0,0 +1,259 @@
+use crate::{
+    check_length, crypto, ensure_data_dir, ensure_state_path, error::Error, eventlog, migrate, tr,
+    validate_name, yes_or_no, ParseError, StateLock, Streak, StreakState, STATE_HEADER,
+    STATE_VERSION,
+};
+use chrono::prelude::*;
+use std::{collections::HashMap, fs};
+
+/// What to do about a problem
+enum Fix {
+    /// Swap the line for a corrected one
+    Replace(String),
+    /// Take the line out of the state file, keeping it in a file of its own
+    SetAside,
+}
+
+struct Problem {
+    /// Index of the line in the migrated body
+    index: usize,
+    description: String,
+    fix: Fix,
+}
+
+/// A streak or removed streak line with what was wrong with it corrected
+struct Checked {
+    problems: Vec<String>,
+    streak: Streak,
+    fixed: String,
+}
+
+/// Check a streak or removed streak line, correcting what can be corrected without guessing
+fn check_line(
+    text: &str,
+    line: usize,
+    first_column: usize,
+    now: DateTime<Local>,
+) -> Result<Checked, ParseError> {
+    let mut values: Vec<_> = text.split(',').collect();
+    let prefix = (first_column - 1).min(values.len());
+    validate_name(values[0]).map_err(|reason| ParseError::BadValue {
+        line,
+        column: 1,
+        value: values[0].to_owned(),
+        reason,
+    })?;
+    let mut problems = Vec::new();
+    let mut streak = match Streak::deserialize(&values[prefix..], line, first_column) {
+        Ok(streak) => streak,
+        // a state that isn't known is most likely pending, and update sorts out the rest
+        Err(ParseError::UnknownState { column, value, .. }) => {
+            problems.push(tr!("unknown state \"{}\", setting it to Pending", value));
+            values[column - 1] = StreakState::Pending.serialize();
+            Streak::deserialize(&values[prefix..], line, first_column)?
+        }
+        Err(err) => return Err(err),
+    };
+    if streak.last_hit > now {
+        problems.push(tr!(
+            "last hit {} is in the future, setting it to now",
+            streak.last_hit.format("%Y-%m-%d %H:%M")
+        ));
+        streak.last_hit = now;
+    }
+    if let Some(created) = streak.created.filter(|&created| created > now) {
+        problems.push(tr!(
+            "creation time {} is in the future, setting it to now",
+            created.format("%Y-%m-%d %H:%M")
+        ));
+        streak.created = Some(now);
+    }
+    if streak.max_count < streak.current_count {
+        problems.push(tr!(
+            "max count {} is less than the count {}, raising it",
+            streak.max_count,
+            streak.current_count
+        ));
+        streak.max_count = streak.current_count;
+    }
+    let fixed = format!("{},{}", values[..prefix].join(","), streak.serialize());
+    Ok(Checked {
+        problems,
+        streak,
+        fixed,
+    })
+}
+
+/// Look for problems in the raw lines of the state file
+fn diagnose(body: &str, first_line: usize) -> Vec<Problem> {
+    let now = Local::now();
+    let mut problems = Vec::new();
+    // the line and last hit of the first streak with each name
+    let mut seen: HashMap<String, (usize, DateTime<Local>)> = HashMap::new();
+    let mut section = 0;
+    for (index, text) in body.lines().enumerate() {
+        let line = first_line + index;
+        if text.is_empty() {
+            section += 1;
+            continue;
+        }
+        if section >= 2 {
+            continue;
+        }
+        // removed streaks have their name and removal time before the streak fields
+        if let Err(err) = check_length(text, line) {
+            problems.push(Problem {
+                index,
+                description: err.to_string(),
+                fix: Fix::SetAside,
+            });
+            continue;
+        }
+        let first_column = if section == 0 { 2 } else { 3 };
+        let checked = match check_line(text, line, first_column, now) {
+            Ok(checked) => checked,
+            Err(err) => {
+                problems.push(Problem {
+                    index,
+                    description: err.to_string(),
+                    fix: Fix::SetAside,
+                });
+                continue;
+            }
+        };
+        if !checked.problems.is_empty() {
+            problems.push(Problem {
+                index,
+                description: tr!("line {}: {}", line, checked.problems.join(", ")),
+                fix: Fix::Replace(checked.fixed),
+            });
+        }
+        if section == 1 {
+            continue;
+        }
+        let name = text.split(',').next().unwrap_or_default().to_owned();
+        let last_hit = checked.streak.last_hit;
+        match seen.get(&name).copied() {
+            None => {
+                seen.insert(name, (index, last_hit));
+            }
+            Some((other, other_last_hit)) => {
+                // keep whichever was hit most recently
+                let (older, newer) = if last_hit > other_last_hit {
+                    seen.insert(name.clone(), (index, last_hit));
+                    (other, index)
+                } else {
+                    (index, other)
+                };
+                problems.push(Problem {
+                    index: older,
+                    description: tr!(
+                        "line {}: \"{}\" also appears on line {}, which was hit more recently",
+                        first_line + older,
+                        name,
+                        first_line + newer
+                    ),
+                    fix: Fix::SetAside,
+                });
+            }
+        }
+    }
+    problems
+}
+
+/// Check the state file for problems, offering to fix them
+pub fn doctor() -> Result<(), Error> {
+    let _lock = StateLock::acquire()?;
+    // the problems are looked for in the state file itself
+    eventlog::compact()?;
+    let path = ensure_state_path()?;
+    let bytes = match fs::read(&path) {
+        Ok(bytes) => bytes,
+        Err(err) => return Err(Error::ReadState(path, err)),
+    };
+    let (string, replaced) =
+        crypto::decode_lossy(bytes).map_err(|err| Error::DecodeState(path.clone(), err))?;
+    let first_line = if string.starts_with(STATE_HEADER) {
+        2
+    } else {
+        1
+    };
+    let body = match migrate(&string) {
+        Ok(body) => body,
+        Err(err) => {
+            // without knowing the format, nothing else can be checked
+            eprintln!("{}", err);
+            return Err(Error::Failed);
+        }
+    };
+    let problems = diagnose(&body, first_line);
+    if problems.is_empty() && !replaced {
+        println!("{}", tr!("no problems found in {}", path.display()));
+        return Ok(());
+    }
+    if replaced {
+        // the bytes were already replaced when reading, so writing the file back fixes it
+        println!(
+            "{}",
+            tr!("the file isn't valid UTF-8, the bad bytes will be replaced with �")
+        );
+    }
+    for problem in problems.iter() {
+        println!("{}", problem.description);
+    }
+    let count = problems.len() + usize::from(replaced);
+    let prompt = tr!(
+        "fix {} problem{}, setting aside lines that can't be fixed?",
+        count,
+        if count == 1 { "" } else { "s" }
+    );
+    if !yes_or_no(&prompt) {
+        return Ok(());
+    }
+    let mut lines: Vec<Option<String>> = body.lines().map(|line| Some(line.to_owned())).collect();
+    let mut set_aside = Vec::new();
+    for problem in problems {
+        match problem.fix {
+            Fix::Replace(fixed) => lines[problem.index] = Some(fixed),
+            Fix::SetAside => {
+                if let Some(line) = lines[problem.index].take() {
+                    set_aside.push(line);
+                }
+            }
+        }
+    }
+    if !set_aside.is_empty() {
+        let mut aside_path = ensure_data_dir()?;
+        aside_path.push(format!(
+            "set-aside-{}.txt",
+            Local::now().format("%Y%m%d-%H%M%S")
+        ));
+        let written = crypto::encode(&(set_aside.join("\n") + "\n"))
+            .and_then(|bytes| fs::write(&aside_path, bytes).map_err(|err| err.to_string()));
+        if let Err(err) = written {
+            eprintln!(
+                "{}",
+                tr!(
+                    "couldn't set lines aside in {}: {}",
+                    aside_path.display(),
+                    err
+                )
+            );
+            return Err(Error::Failed);
+        }
+        println!(
+            "{}",
+            tr!(
+                "set {} line(s) aside in {}",
+                set_aside.len(),
+                aside_path.display()
+            )
+        );
+    }
+    let mut fixed = vec![format!("{}{}", STATE_HEADER, STATE_VERSION)];
+    fixed.extend(lines.into_iter().flatten());
+    // through the log, so later changes are logged against the fixed snapshot
+    eventlog::snapshot(&fixed.join("\n"))?;
+    println!("{}", tr!("fixed the state file"));
+    Ok(())
+}
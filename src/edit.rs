@@ -0,0 +1,261 @@
+use crate::{
+    audit, error::Error, modify_state, parse_weekday, read_state, tr, validate_note, validate_tag,
+    Priority, Schedule, Streak,
+};
+use chrono::Weekday;
+use itertools::Itertools;
+use std::{env, fs, process};
+use toml::Value;
+
+/// A validated new value for one of the editable fields of a streak
+enum Change {
+    Schedule(Schedule),
+    Target(u32),
+    Tags(Vec<String>),
+    Note(Option<String>),
+    Icon(Option<String>),
+    Priority(Priority),
+    Rest(Vec<Weekday>),
+    Current(u32),
+    Max(u32),
+}
+
+fn parse_count(key: &str, value: &str) -> Result<u32, String> {
+    value
+        .parse::<u32>()
+        .map_err(|_| tr!("expected unsigned integer for {}: \"{}\"", key, value))
+}
+
+fn parse_change(key: &str, value: &str) -> Result<Change, String> {
+    match key {
+        "schedule" => Schedule::deserialize(value).map(Change::Schedule),
+        "target" => match parse_count(key, value)? {
+            0 => Err(tr!("expected positive target")),
+            target => Ok(Change::Target(target)),
+        },
+        "tags" => {
+            let mut tags: Vec<String> = Vec::new();
+            for tag in value.split_whitespace() {
+                validate_tag(tag)?;
+                if !tags.iter().any(|existing| existing == tag) {
+                    tags.push(tag.to_owned());
+                }
+            }
+            Ok(Change::Tags(tags))
+        }
+        "note" | "description" => {
+            validate_note(value)?;
+            Ok(Change::Note(
+                Some(value.to_owned()).filter(|note| !note.is_empty()),
+            ))
+        }
+        "icon" => {
+            if value.contains(|c: char| c == ',' || c.is_control()) {
+                return Err(tr!("icons can't contain commas or control characters"));
+            }
+            Ok(Change::Icon(
+                Some(value.to_owned()).filter(|icon| !icon.is_empty()),
+            ))
+        }
+        "priority" => Priority::deserialize(value).map(Change::Priority),
+        "rest" => value
+            .split(|c: char| c == '+' || c == ',' || c.is_whitespace())
+            .filter(|day| !day.is_empty() && *day != "none")
+            .map(parse_weekday)
+            .collect::<Result<Vec<_>, _>>()
+            .map(|days| Change::Rest(days.into_iter().unique().collect())),
+        "current" => parse_count(key, value).map(Change::Current),
+        "max" => parse_count(key, value).map(Change::Max),
+        _ => Err(tr!(
+            "unknown field \"{}\", expected schedule, target, tags, note, icon, priority, rest, current or max",
+            key
+        )),
+    }
+}
+
+fn apply(streak: &mut Streak, changes: Vec<Change>) -> Result<(), String> {
+    let (mut current, mut max) = (streak.current_count, streak.max_count);
+    for change in changes.iter() {
+        match change {
+            Change::Current(count) => current = *count,
+            Change::Max(count) => max = *count,
+            _ => (),
+        }
+    }
+    if max < current {
+        return Err(tr!(
+            "max count {} can't be less than the current count {}",
+            max,
+            current
+        ));
+    }
+    for change in changes {
+        match change {
+            Change::Schedule(schedule) => streak.schedule = schedule,
+            Change::Target(target) => streak.target = target,
+            Change::Tags(tags) => streak.tags = tags,
+            Change::Note(note) => streak.note = note,
+            Change::Icon(icon) => streak.icon = icon,
+            Change::Priority(priority) => streak.priority = priority,
+            Change::Rest(days) => streak.rest = days,
+            Change::Current(_) | Change::Max(_) => (),
+        }
+    }
+    streak.current_count = current;
+    streak.max_count = max;
+    Ok(())
+}
+
+/// The fields of a streak as a TOML document for editing
+fn to_toml(name: &str, streak: &Streak) -> String {
+    let string = |value: &str| Value::String(value.to_owned()).to_string();
+    [
+        format!(
+            "# fields of \"{}\", schedule is daily, every:<days> or days:<mon+wed+...>",
+            name
+        ),
+        format!("schedule = {}", string(&streak.schedule.serialize())),
+        format!("target = {}", streak.target),
+        format!("tags = {}", string(&streak.tags.join(" "))),
+        format!(
+            "note = {}",
+            string(streak.note.as_deref().unwrap_or_default())
+        ),
+        format!(
+            "icon = {}",
+            string(streak.icon.as_deref().unwrap_or_default())
+        ),
+        format!("priority = {}", string(streak.priority.serialize())),
+        format!(
+            "rest = {}",
+            string(
+                &streak
+                    .rest
+                    .iter()
+                    .map(|day| day.to_string().to_lowercase())
+                    .join(" ")
+            )
+        ),
+        format!("current = {}", streak.current_count),
+        format!("max = {}", streak.max_count),
+    ]
+    .join("\n")
+        + "\n"
+}
+
+fn from_toml(string: &str) -> Result<Vec<Change>, String> {
+    let document = string
+        .parse::<Value>()
+        .map_err(|err| tr!("couldn't parse edited fields: {}", err))?;
+    let table = document
+        .as_table()
+        .ok_or_else(|| tr!("expected a table of fields"))?;
+    table
+        .iter()
+        .map(|(key, value)| {
+            let value = match value {
+                Value::String(value) => value.clone(),
+                Value::Integer(value) => value.to_string(),
+                Value::Array(values) => values
+                    .iter()
+                    .map(|value| {
+                        value
+                            .as_str()
+                            .ok_or_else(|| tr!("expected a list of strings"))
+                    })
+                    .collect::<Result<Vec<_>, _>>()?
+                    .join(" "),
+                _ => return Err(tr!("unexpected value for {}", key)),
+            };
+            parse_change(key, &value)
+        })
+        .collect()
+}
+
+/// Open the fields in `$VISUAL` or `$EDITOR` and read them back once it exits
+fn edit_in_editor(name: &str, streak: &Streak) -> Result<Option<Vec<Change>>, String> {
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_owned());
+    let path = env::temp_dir().join(format!("streaks-edit-{}.toml", process::id()));
+    let original = to_toml(name, streak);
+    fs::write(&path, &original).map_err(|err| tr!("couldn't write {}: {}", path.display(), err))?;
+    // the editor command can have its own arguments, so let the shell split it
+    let status = process::Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$@\"", editor))
+        .arg("streaks")
+        .arg(&path)
+        .status();
+    let edited = fs::read_to_string(&path);
+    if let Err(err) = fs::remove_file(&path) {
+        eprintln!("{}", tr!("couldn't remove {}: {}", path.display(), err));
+    }
+    match status {
+        Ok(status) if status.success() => (),
+        Ok(status) => return Err(tr!("editor exited with {}, nothing was changed", status)),
+        Err(err) => return Err(tr!("couldn't run editor \"{}\": {}", editor, err)),
+    }
+    let edited = edited.map_err(|err| tr!("couldn't read edited fields: {}", err))?;
+    if edited == original {
+        return Ok(None);
+    }
+    from_toml(&edited).map(Some)
+}
+
+/// Change the fields of a streak from `key=value` arguments, or in an editor if there are none
+pub fn edit(name: &str, sets: &[String]) -> Result<(), Error> {
+    let mut state = read_state()?;
+    let streak = match state.streaks.get(name) {
+        Some(streak) => streak,
+        None => {
+            state.not_found(name);
+            return Ok(());
+        }
+    };
+    let changes = if sets.is_empty() {
+        edit_in_editor(name, streak)
+    } else {
+        sets.iter()
+            .map(|set| match set.split_once('=') {
+                Some((key, value)) => parse_change(key.trim(), value),
+                None => Err(tr!("expected key=value: \"{}\"", set)),
+            })
+            .collect::<Result<_, _>>()
+            .map(Some)
+    };
+    let changes = match changes {
+        Ok(Some(changes)) => changes,
+        Ok(None) => {
+            println!("{}", tr!("no changes made to \"{}\"", name));
+            return Ok(());
+        }
+        Err(err) => {
+            eprintln!("{}", err);
+            return Err(Error::Failed);
+        }
+    };
+    let mut result = Ok(());
+    modify_state(|state| {
+        result = match state.streaks.get_mut(name) {
+            Some(streak) => {
+                let before = (streak.current_count, streak.max_count);
+                let applied = apply(streak, changes);
+                let after = (streak.current_count, streak.max_count);
+                if before != after {
+                    state.audit.push(audit::Record::set(name, before, after));
+                }
+                applied
+            }
+            None => Err(tr!("streak \"{}\" was removed while editing", name)),
+        }
+    })?;
+    match result {
+        Ok(()) => println!("{}", tr!("updated streak \"{}\"", name)),
+        Err(err) => {
+            eprintln!("{}", err);
+            return Err(Error::Failed);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,119 @@
+use crate::{tr, ParseError};
+use std::{fmt, io, path::PathBuf};
+
+/// Why a command couldn't run, each with a suggestion and an exit code from `sysexits.h`
+pub enum Error {
+    /// The platform has no directory of the given kind to keep files in
+    NoDirectory(&'static str),
+    InvalidProfile(String),
+    CreateDataDir(PathBuf, io::Error),
+    ReadConfig(PathBuf, io::Error),
+    ParseConfig(PathBuf, String),
+    ReadState(PathBuf, io::Error),
+    DecodeState(PathBuf, String),
+    ParseState(PathBuf, ParseError),
+    WriteState(PathBuf, String),
+    Lock(PathBuf, io::Error),
+    LockTimeout(PathBuf),
+    /// The arguments don't make sense for the command
+    Usage(String),
+    /// A streak, alias or group named on the command line doesn't exist, already reported
+    NotFound,
+    /// The command already reported what went wrong
+    Failed,
+}
+
+impl Error {
+    pub fn hint(&self) -> Option<String> {
+        match self {
+            Error::NoDirectory(_) => Some(tr!("make sure HOME is set")),
+            Error::InvalidProfile(_) => Some(tr!(
+                "profile names can't be empty or contain slashes, check --profile and STREAKS_PROFILE"
+            )),
+            Error::CreateDataDir(path, _) | Error::WriteState(path, _) | Error::Lock(path, _) => {
+                Some(tr!(
+                    "check that you can write to {}",
+                    path.parent().unwrap_or(path).display()
+                ))
+            }
+            Error::ReadConfig(path, _) | Error::ParseConfig(path, _) => Some(tr!(
+                "fix {} or move it aside to use the defaults",
+                path.display()
+            )),
+            Error::ReadState(path, _) => Some(tr!("check the permissions of {}", path.display())),
+            Error::DecodeState(..) => Some(tr!(
+                "check the [encryption] section of the config and STREAKS_PASSPHRASE"
+            )),
+            Error::ParseState(path, _) => Some(tr!(
+                "fix the file by hand or restore the previous version from {}.1",
+                path.display()
+            )),
+            Error::LockTimeout(path) => Some(tr!(
+                "another streaks process is holding {}, it may be waiting at a prompt",
+                path.display()
+            )),
+            Error::Usage(_) => Some(tr!("run streaks without arguments to see how to use it")),
+            Error::NotFound | Error::Failed => None,
+        }
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::InvalidProfile(_) | Error::Usage(_) => 64,
+            Error::DecodeState(..) | Error::ParseState(..) => 65,
+            Error::NoDirectory(_)
+            | Error::CreateDataDir(..)
+            | Error::ReadConfig(..)
+            | Error::ReadState(..)
+            | Error::WriteState(..)
+            | Error::Lock(..) => 74,
+            Error::LockTimeout(_) => 75,
+            Error::ParseConfig(..) => 78,
+            Error::NotFound => 66,
+            Error::Failed => 1,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let message = match self {
+            Error::NoDirectory(kind) => tr!("couldn't locate the {} directory", kind),
+            Error::InvalidProfile(err) => err.clone(),
+            Error::CreateDataDir(path, err) => {
+                tr!("couldn't create data directory {}: {}", path.display(), err)
+            }
+            Error::ReadConfig(path, err) => {
+                tr!("couldn't read config file {}: {}", path.display(), err)
+            }
+            Error::ParseConfig(path, err) => {
+                tr!("couldn't parse config file {}: {}", path.display(), err)
+            }
+            Error::ReadState(path, err) => {
+                tr!("couldn't read state file {}: {}", path.display(), err)
+            }
+            Error::DecodeState(path, err) => {
+                tr!("couldn't decode state file {}: {}", path.display(), err)
+            }
+            Error::ParseState(path, err) => {
+                tr!("couldn't parse state file {}: {}", path.display(), err)
+            }
+            Error::WriteState(path, err) => {
+                tr!("couldn't write state file {}: {}", path.display(), err)
+            }
+            Error::Lock(path, err) => tr!("couldn't lock {}: {}", path.display(), err),
+            Error::LockTimeout(path) => tr!("timed out waiting for {}", path.display()),
+            Error::Usage(err) => err.clone(),
+            Error::NotFound => tr!("not found"),
+            Error::Failed => tr!("the command failed"),
+        };
+        write!(f, "{}", message)
+    }
+}
+
+/// For the modules that report failures as plain messages
+impl From<Error> for String {
+    fn from(err: Error) -> Self {
+        err.to_string()
+    }
+}
@@ -0,0 +1,483 @@
+//! The state is kept as a snapshot in `state.txt` plus an append-only log of the changes made
+//! since in `state.log`, so that a write only ever appends a few lines instead of rewriting
+//! everything, and a write cut short loses at most the change being made.
+//!
+//! Each change is a line `<time> <put|delete> <section> <record>`, where the sections are those
+//! of the state file (streak, trash, alias, group and order) and a record is one of its lines, or
+//! just the key of the line for a delete. Streaks, aliases and groups are keyed by their name,
+//! removed streaks by their name and removal time, and the single line of the order by its first
+//! name. The current state is the snapshot with the
+//! changes applied in order, and every so many changes it's written out as a new snapshot.
+//!
+//! The log starts with a fingerprint of the snapshot it applies to, so a snapshot replaced from
+//! elsewhere, like a sync or a restored backup, is never mixed up with changes made to an older
+//! one. Changes are only logged against a snapshot in the current format, which is migrated as
+//! a whole once they're applied. With encryption the ciphertext can't be appended to, so every
+//! write is a snapshot.
+//!
+//! This is a log of lines put and deleted, not of what happened to the streaks. Folding the state
+//! from events like a streak being added, hit or renamed would need an event and a way to replay
+//! it for every kind of change, including the ones `update` makes from the clock, holidays and
+//! skip tokens, which would then have to be kept in step with the code making them. Diffing the
+//! lines keeps any change loggable without that. What happened is already kept elsewhere: the
+//! hits, resets and expiries in the history, and the adds, removes and renames in the audit log.
+//! Syncing still goes through the snapshot, which `compact` brings up to date first.
+
+use crate::{
+    config, crypto, ensure_data_dir, ensure_state_path, error::Error, rotate_backups, tr,
+    STATE_HEADER, STATE_VERSION,
+};
+use chrono::prelude::*;
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+const LOG_HEADER: &str = "# streaks log for snapshot ";
+/// How many changes are logged before the state is written out as a new snapshot
+const SNAPSHOT_EVERY: usize = 200;
+const SECTIONS: [&str; 5] = ["streak", "trash", "alias", "group", "order"];
+
+pub fn log_path() -> Result<PathBuf, Error> {
+    let mut path = ensure_data_dir()?;
+    path.push("state.log");
+    Ok(path)
+}
+
+/// FNV-1a, which unlike the standard hasher is guaranteed to stay the same between builds
+pub fn fingerprint(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+fn log_header(snapshot: &[u8]) -> String {
+    format!("{}{:016x}", LOG_HEADER, fingerprint(snapshot))
+}
+
+/// The lines of each section of a state file body, with their keys
+struct Records {
+    header: Option<String>,
+    sections: Vec<Vec<(String, String)>>,
+}
+
+fn key(section: usize, line: &str) -> String {
+    let fields = if section == 1 { 2 } else { 1 };
+    line.splitn(fields + 1, ',')
+        .take(fields)
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+impl Records {
+    fn parse(string: &str) -> Self {
+        let (header, body) = match string.strip_prefix(STATE_HEADER) {
+            Some(_) => {
+                let (header, body) = string.split_once('\n').unwrap_or((string, ""));
+                (Some(header.to_owned()), body)
+            }
+            None => (None, string),
+        };
+        let mut sections = vec![Vec::new(); SECTIONS.len()];
+        let mut section = 0;
+        for line in body.lines() {
+            if line.is_empty() {
+                section = (section + 1).min(SECTIONS.len() - 1);
+                continue;
+            }
+            sections[section].push((key(section, line), line.to_owned()));
+        }
+        Self { header, sections }
+    }
+
+    fn put(&mut self, section: usize, line: &str) {
+        let key = key(section, line);
+        let records = &mut self.sections[section];
+        match records
+            .iter_mut()
+            .find(|(existing, _line)| *existing == key)
+        {
+            Some(record) => record.1 = line.to_owned(),
+            None => records.push((key, line.to_owned())),
+        }
+    }
+
+    fn delete(&mut self, section: usize, key: &str) {
+        self.sections[section].retain(|(existing, _line)| existing != key);
+    }
+
+    /// Apply a logged change, failing on a malformed line such as one whose write was cut short
+    fn apply(&mut self, change: &str) -> Result<(), String> {
+        let mut fields = change.splitn(4, ' ');
+        let (_time, operation, section, record) =
+            match (fields.next(), fields.next(), fields.next(), fields.next()) {
+                (Some(time), Some(operation), Some(section), Some(record)) => {
+                    (time, operation, section, record)
+                }
+                _ => return Err(tr!("malformed change: \"{}\"", change)),
+            };
+        let section = SECTIONS
+            .iter()
+            .position(|&name| name == section)
+            .ok_or_else(|| tr!("unknown section \"{}\"", section))?;
+        match operation {
+            "put" => self.put(section, record),
+            "delete" => self.delete(section, record),
+            _ => return Err(tr!("unknown change \"{}\"", operation)),
+        }
+        Ok(())
+    }
+
+    /// The changes turning these records into others, in the log's format
+    fn changes_to(&self, other: &Records) -> Vec<String> {
+        let time = Local::now().to_rfc3339();
+        let mut changes = Vec::new();
+        for (section, (ours, theirs)) in self.sections.iter().zip(other.sections.iter()).enumerate()
+        {
+            for (key, _line) in ours.iter() {
+                if !theirs.iter().any(|(other, _line)| other == key) {
+                    changes.push(format!("{} delete {} {}", time, SECTIONS[section], key));
+                }
+            }
+            for (key, line) in theirs.iter() {
+                if !ours
+                    .iter()
+                    .any(|(other, ours)| other == key && ours == line)
+                {
+                    changes.push(format!("{} put {} {}", time, SECTIONS[section], line));
+                }
+            }
+        }
+        changes
+    }
+
+    fn serialize(&self) -> String {
+        let mut sections = self.sections.as_slice();
+        while sections.last().is_some_and(Vec::is_empty) {
+            sections = &sections[..sections.len() - 1];
+        }
+        let mut lines: Vec<_> = self.header.iter().map(String::as_str).collect();
+        // like the state file, the sections are separated by blank lines
+        for (i, records) in sections.iter().enumerate() {
+            if i > 0 {
+                lines.push("");
+            }
+            lines.extend(records.iter().map(|(_key, line)| line.as_str()));
+        }
+        lines.join("\n")
+    }
+}
+
+fn read_snapshot(path: &Path) -> Result<Vec<u8>, Error> {
+    match fs::read(path) {
+        Ok(bytes) => Ok(bytes),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => Err(Error::ReadState(path.to_owned(), err)),
+    }
+}
+
+/// The changes in a log, or none if it was started for another snapshot than this one
+fn changes_in(log: &str, snapshot: &[u8]) -> Option<Vec<String>> {
+    let mut lines = log.lines();
+    match lines.next() {
+        Some(header) if header == log_header(snapshot) => Some(lines.map(str::to_owned).collect()),
+        _ => None,
+    }
+}
+
+/// The logged changes which apply to the snapshot, or none if there's no log for it
+fn read_changes(snapshot: &[u8]) -> Result<Option<Vec<String>>, Error> {
+    let path = log_path()?;
+    // a damaged log shouldn't stop the state from being read, bad lines are skipped in `fold`
+    let string = match fs::read(&path) {
+        Ok(bytes) => String::from_utf8_lossy(&bytes).into_owned(),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(Error::ReadState(path, err)),
+    };
+    let changes = changes_in(&string, snapshot);
+    if changes.is_none() && string.lines().nth(1).is_some() {
+        eprintln!(
+            "{}",
+            tr!(
+                "warning: ignoring {} since the state file was replaced after it was written",
+                path.display()
+            )
+        );
+    }
+    Ok(changes)
+}
+
+/// Read the snapshot and apply the changes logged since, returning the state file it amounts to
+/// and how many changes there were, or none if there's no log for the snapshot to append to
+fn fold() -> Result<(String, Option<usize>), Error> {
+    let path = ensure_state_path()?;
+    let bytes = read_snapshot(&path)?;
+    let changes = read_changes(&bytes)?;
+    let (string, replaced) =
+        crypto::decode_lossy(bytes).map_err(|err| Error::DecodeState(path.clone(), err))?;
+    if replaced {
+        eprintln!(
+            "{}",
+            tr!(
+                "warning: {} isn't valid UTF-8, reading it with the bad bytes replaced, see `streaks doctor`",
+                path.display()
+            )
+        );
+    }
+    let changes = match changes {
+        Some(changes) if !changes.is_empty() => changes,
+        _ => return Ok((string, changes.map(|_empty| 0))),
+    };
+    let mut records = Records::parse(&string);
+    for (i, change) in changes.iter().enumerate() {
+        if let Err(err) = records.apply(change) {
+            // only the last write can have been cut short, anything else is worth knowing about
+            if i + 1 < changes.len() {
+                eprintln!("{}", tr!("warning: skipping logged change: {}", err));
+            }
+        }
+    }
+    Ok((records.serialize(), Some(changes.len())))
+}
+
+/// The current state file, as the snapshot with the logged changes applied
+pub fn load() -> Result<String, Error> {
+    fold().map(|(string, _changes)| string)
+}
+
+/// Replace the snapshot with the state and start a fresh log for it
+pub fn snapshot(serialized: &str) -> Result<(), Error> {
+    let path = ensure_state_path()?;
+    if let Err(err) = rotate_backups(&path, config::read_config().keep_backups) {
+        eprintln!("{}", tr!("couldn't back up previous state file: {}", err));
+    }
+    let bytes = crypto::encode(serialized).map_err(|err| Error::WriteState(path.clone(), err))?;
+    // write next to the old snapshot and move it over, so there's always a whole one
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".new");
+    let temporary = PathBuf::from(temporary);
+    fs::write(&temporary, &bytes)
+        .and_then(|()| fs::rename(&temporary, &path))
+        .map_err(|err| Error::WriteState(path.clone(), err.to_string()))?;
+    start_log(&bytes)
+}
+
+fn start_log(snapshot: &[u8]) -> Result<(), Error> {
+    let log = log_path()?;
+    fs::write(&log, log_header(snapshot) + "\n")
+        .map_err(|err| Error::WriteState(log, err.to_string()))
+}
+
+/// Start a fresh log for a snapshot written from elsewhere, like one pulled in by a sync, which
+/// has to already have every logged change in it
+pub fn restart() -> Result<(), Error> {
+    let path = ensure_state_path()?;
+    start_log(&read_snapshot(&path)?)
+}
+
+/// A write of the state worked out before it's made, so that it can be journaled first
+pub enum Pending {
+    /// Changes to append to the log
+    Changes(Vec<String>),
+    /// A whole new snapshot, when the changes can't be logged or there are enough already
+    Snapshot(String),
+}
+
+/// Work out how to get from the current state to the new one
+pub fn prepare(serialized: &str) -> Result<Pending, Error> {
+    if crypto::enabled() {
+        return Ok(Pending::Snapshot(serialized.to_owned()));
+    }
+    let (current, logged) = fold()?;
+    Ok(plan(&current, logged, serialized))
+}
+
+/// How to write the state given the current one and how many changes are logged against its
+/// snapshot, if any can be
+fn plan(current: &str, logged: Option<usize>, serialized: &str) -> Pending {
+    // changes can only be logged against a snapshot in the current format
+    let header = format!("{}{}", STATE_HEADER, STATE_VERSION);
+    let outdated = current.lines().next() != Some(header.as_str());
+    let changes = Records::parse(current).changes_to(&Records::parse(serialized));
+    match logged {
+        Some(logged) if !outdated && logged + changes.len() < SNAPSHOT_EVERY => {
+            Pending::Changes(changes)
+        }
+        // without a log started for the snapshot, appended changes would be ignored when read
+        _ => Pending::Snapshot(serialized.to_owned()),
+    }
+}
+
+/// Make a prepared write
+pub fn commit(pending: &Pending) -> Result<(), Error> {
+    let changes = match pending {
+        Pending::Snapshot(serialized) => return snapshot(serialized),
+        Pending::Changes(changes) if changes.is_empty() => return Ok(()),
+        Pending::Changes(changes) => changes,
+    };
+    let log = log_path()?;
+    OpenOptions::new()
+        .read(true)
+        .append(true)
+        .open(&log)
+        .and_then(|mut file| {
+            drop_cut_short(&mut file)?;
+            file.write_all((changes.join("\n") + "\n").as_bytes())?;
+            file.sync_data()
+        })
+        .map_err(|err| Error::WriteState(log, err.to_string()))
+}
+
+/// Cut off the end of a change whose write was cut short, so that the next one starts on a line
+/// of its own instead of leaving a malformed line in the middle of the log
+fn drop_cut_short(file: &mut File) -> io::Result<()> {
+    // the log is only ever a few hundred short lines before it's snapshotted
+    let mut bytes = Vec::new();
+    file.seek(SeekFrom::Start(0))?;
+    file.read_to_end(&mut bytes)?;
+    if bytes.last().is_none_or(|&byte| byte == b'\n') {
+        return Ok(());
+    }
+    // the header is written whole with the snapshot, so there's always a line to keep
+    match bytes.iter().rposition(|&byte| byte == b'\n') {
+        Some(end) => file.set_len(end as u64 + 1),
+        None => Ok(()),
+    }
+}
+
+/// Record the new state, appending the changes from the current one to the log
+pub fn store(serialized: &str) -> Result<(), Error> {
+    commit(&prepare(serialized)?)
+}
+
+/// Apply logged changes to the current state again, which only changes what they hadn't yet
+pub fn replay(changes: &[String]) -> Result<(), Error> {
+    let mut records = Records::parse(&load()?);
+    for change in changes {
+        if let Err(err) = records.apply(change) {
+            eprintln!("{}", tr!("warning: skipping logged change: {}", err));
+        }
+    }
+    store(&records.serialize())
+}
+
+/// Write the logged changes out as a new snapshot, for anything reading the state file directly
+pub fn compact() -> Result<(), Error> {
+    match fold()? {
+        (_current, None) | (_current, Some(0)) => Ok(()),
+        (current, Some(_changes)) => snapshot(&current),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::State;
+
+    const STATE: &str = "# streaks state v16
+gym,3,5,2024-03-01 12:00:00 +00:00,Done,daily,1,,fit,,2024-01-01 12:00:00 +00:00,0,,,,normal,,
+read,0,2,2024-02-28 12:00:00 +00:00,Expired,daily,1,,,,,0,,,,normal,sat+sun,
+
+run,2024-02-01 12:00:00 +00:00,1,1,2024-01-31 12:00:00 +00:00,New,daily,1,,,,,0,,,,normal,,
+
+g,gym
+
+fit,gym,run
+
+gym,read";
+
+    /// Log the changes between two states and fold them back onto the first
+    fn replay(before: &str, after: &str) -> String {
+        let mut records = Records::parse(before);
+        for change in records.changes_to(&Records::parse(after)) {
+            records.apply(&change).unwrap();
+        }
+        records.serialize()
+    }
+
+    #[test]
+    fn unchanged_records_serialize_like_the_state() {
+        let serialized = State::deserialize(STATE).unwrap().serialize();
+        assert_eq!(Records::parse(&serialized).serialize(), serialized);
+    }
+
+    #[test]
+    fn replayed_changes_match_the_state() {
+        let before = State::deserialize(STATE).unwrap();
+        let mut after = State::deserialize(STATE).unwrap();
+        after.streaks.remove("read");
+        after.aliases.insert("r".to_owned(), "run".to_owned());
+        after.groups.remove("fit");
+        after.order = vec!["gym".to_owned()];
+        let folded = replay(&before.serialize(), &after.serialize());
+        assert_eq!(folded, after.serialize());
+        // the folded state keeps every line in its own section
+        let reparsed = State::deserialize(&folded).unwrap();
+        assert_eq!(reparsed.trash.len(), 1);
+        assert_eq!(reparsed.aliases.len(), 2);
+        assert!(reparsed.groups.is_empty());
+    }
+
+    #[test]
+    fn changes_to_a_state_with_only_later_sections_stay_in_them() {
+        let mut after = State::deserialize(STATE).unwrap();
+        after.streaks.clear();
+        after.trash.clear();
+        let empty = State::deserialize("").unwrap().serialize();
+        let folded = replay(&empty, &after.serialize());
+        assert_eq!(folded, after.serialize());
+    }
+
+    #[test]
+    fn writes_after_the_snapshot_is_replaced_make_a_new_one() {
+        let before = State::deserialize(STATE).unwrap().serialize();
+        let mut after = State::deserialize(STATE).unwrap();
+        after.streaks.remove("read");
+        let change = &Records::parse(&before).changes_to(&Records::parse(&after.serialize()))[0];
+        let log = format!("{}\n{}\n", log_header(before.as_bytes()), change);
+        assert_eq!(
+            changes_in(&log, before.as_bytes()),
+            Some(vec![change.clone()])
+        );
+        assert!(matches!(
+            plan(&before, Some(1), &before),
+            Pending::Changes(changes) if changes.is_empty()
+        ));
+        // like doctor fixing the state file, or a sync pulling in another one
+        let replaced = after.serialize();
+        assert_eq!(changes_in(&log, replaced.as_bytes()), None);
+        after.aliases.clear();
+        assert!(matches!(
+            plan(&replaced, None, &after.serialize()),
+            Pending::Snapshot(serialized) if serialized == after.serialize()
+        ));
+    }
+
+    #[test]
+    fn changes_cut_short_are_dropped_before_appending() {
+        let path = std::env::temp_dir().join(format!("streaks-{}-log", std::process::id()));
+        fs::write(
+            &path,
+            "# streaks log for snapshot 0\nfirst change\nsecond ch",
+        )
+        .unwrap();
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .open(&path)
+            .unwrap();
+        drop_cut_short(&mut file).unwrap();
+        file.write_all(b"second change\n").unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "# streaks log for snapshot 0\nfirst change\nsecond change\n"
+        );
+        // whole changes are left alone
+        drop_cut_short(&mut file).unwrap();
+        assert!(fs::read_to_string(&path)
+            .unwrap()
+            .ends_with("second change\n"));
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,186 @@
+use crate::{
+    current_day, day_of,
+    error::Error,
+    history::{self, Event},
+    tr, State, StreakState,
+};
+use chrono::{prelude::*, Duration};
+use itertools::Itertools;
+use std::{fs, path::Path};
+
+/// Escape a TEXT value as described in RFC 5545
+fn ical_escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+/// Fold a content line so that no physical line exceeds 75 octets
+fn ical_fold(line: &str) -> String {
+    let mut folded = String::new();
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > 75 {
+            folded.push_str("\r\n ");
+            width = 1;
+        }
+        folded.push(c);
+        width += c.len_utf8();
+    }
+    folded.push_str("\r\n");
+    folded
+}
+
+/// Quote a field as described in RFC 4180 if it needs it
+fn csv_field(field: &str) -> String {
+    if field.contains(&[',', '"', '\r', '\n'][..]) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+fn csv_record(fields: &[String]) -> String {
+    let mut record = fields.iter().map(|field| csv_field(field)).join(",");
+    record.push_str("\r\n");
+    record
+}
+
+fn streaks_csv(state: &State) -> String {
+    let mut csv = csv_record(&[
+        "name".to_owned(),
+        "current_count".to_owned(),
+        "max_count".to_owned(),
+        "last_hit".to_owned(),
+        "state".to_owned(),
+    ]);
+    for (name, streak) in state.streaks.iter().sorted_by_key(|pair| pair.0) {
+        csv.push_str(&csv_record(&[
+            name.clone(),
+            streak.current_count.to_string(),
+            streak.max_count.to_string(),
+            streak.last_hit.to_rfc3339(),
+            streak.state.serialize().to_owned(),
+        ]));
+    }
+    csv
+}
+
+fn history_csv(entries: &[history::Entry]) -> String {
+    let mut csv = csv_record(&["name".to_owned(), "time".to_owned(), "event".to_owned()]);
+    for entry in entries.iter() {
+        csv.push_str(&csv_record(&[
+            entry.name.clone(),
+            entry.time.to_rfc3339(),
+            entry.event.serialize(),
+        ]));
+    }
+    csv
+}
+
+fn ical_date(date: NaiveDate) -> String {
+    date.format("%Y%m%d").to_string()
+}
+
+/// Render the hit history as all-day events and pending streaks as todos
+fn ical(state: &State, entries: &[history::Entry]) -> String {
+    let stamp = Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_owned(),
+        "VERSION:2.0".to_owned(),
+        "PRODID:-//streaks//streaks//EN".to_owned(),
+    ];
+    let hits = entries
+        .iter()
+        .filter(|entry| matches!(entry.event, Event::Hit))
+        .map(|entry| (&entry.name, day_of(entry.time)))
+        .unique();
+    for (name, date) in hits {
+        lines.push("BEGIN:VEVENT".to_owned());
+        lines.push(format!(
+            "UID:{}-{}@streaks",
+            ical_date(date),
+            ical_escape(name)
+        ));
+        lines.push(format!("DTSTAMP:{}", stamp));
+        lines.push(format!("DTSTART;VALUE=DATE:{}", ical_date(date)));
+        lines.push(format!(
+            "DTEND;VALUE=DATE:{}",
+            ical_date(date + Duration::days(1))
+        ));
+        lines.push(format!("SUMMARY:{}", ical_escape(name)));
+        lines.push("END:VEVENT".to_owned());
+    }
+    let today = current_day();
+    let pending = state
+        .streaks
+        .iter()
+        .filter(|pair| matches!(pair.1.state, StreakState::Pending))
+        .sorted_by_key(|pair| pair.0);
+    for (name, streak) in pending {
+        lines.push("BEGIN:VTODO".to_owned());
+        lines.push(format!(
+            "UID:{}-{}@streaks",
+            ical_date(today),
+            ical_escape(name)
+        ));
+        lines.push(format!("DTSTAMP:{}", stamp));
+        lines.push(format!("DUE;VALUE=DATE:{}", ical_date(today)));
+        lines.push(format!("SUMMARY:{}", ical_escape(name)));
+        lines.push(format!(
+            "DESCRIPTION:currently at {}\\, max {}",
+            streak.current_count, streak.max_count
+        ));
+        lines.push("STATUS:NEEDS-ACTION".to_owned());
+        lines.push("END:VTODO".to_owned());
+    }
+    lines.push("END:VCALENDAR".to_owned());
+    lines.iter().map(|line| ical_fold(line)).collect()
+}
+
+pub fn export_ical(state: &State, path: &Path) -> Result<(), Error> {
+    let entries = match history::read_history() {
+        Ok(entries) => entries,
+        Err(err) => {
+            eprintln!("{}", tr!("couldn't export history: {}", err));
+            return Err(Error::Failed);
+        }
+    };
+    match fs::write(path, ical(state, &entries)) {
+        Ok(()) => {
+            println!("{}", tr!("exported calendar to {}", path.display()));
+            Ok(())
+        }
+        Err(err) => {
+            eprintln!("{}", tr!("couldn't write calendar file: {}", err));
+            Err(Error::Failed)
+        }
+    }
+}
+
+pub fn export_csv(state: &State, dir: &Path) -> Result<(), Error> {
+    let entries = match history::read_history() {
+        Ok(entries) => entries,
+        Err(err) => {
+            eprintln!("{}", tr!("couldn't export history: {}", err));
+            return Err(Error::Failed);
+        }
+    };
+    let result = fs::create_dir_all(dir)
+        .and_then(|()| fs::write(dir.join("streaks.csv"), streaks_csv(state)))
+        .and_then(|()| fs::write(dir.join("history.csv"), history_csv(&entries)));
+    match result {
+        Ok(()) => {
+            println!(
+                "{}",
+                tr!("exported streaks and history to {}", dir.display())
+            );
+            Ok(())
+        }
+        Err(err) => {
+            eprintln!("{}", tr!("couldn't write csv files: {}", err));
+            Err(Error::Failed)
+        }
+    }
+}
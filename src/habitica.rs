@@ -0,0 +1,151 @@
+use crate::{audit, config, error::Error, history, tr, validate_name, State, Streak, StreakState};
+use chrono::prelude::*;
+use serde_json::Value;
+
+const API_URL: &str = "https://habitica.com/api/v3";
+
+/// The parts of a Habitica daily that map onto a streak
+struct Daily {
+    id: String,
+    text: String,
+    streak: u32,
+    completed: bool,
+}
+
+fn request(credentials: &config::Habitica, method: &str, path: &str) -> ureq::Request {
+    ureq::request(method, &format!("{}{}", API_URL, path))
+        .set("x-api-user", &credentials.user_id)
+        .set("x-api-key", &credentials.api_token)
+        .set("x-client", "streaks")
+}
+
+fn fetch_dailies(credentials: &config::Habitica) -> Result<Vec<Daily>, String> {
+    let body = request(credentials, "GET", "/tasks/user?type=dailys")
+        .call()
+        .map_err(|err| tr!("request failed: {}", err))?
+        .into_string()
+        .map_err(|err| tr!("couldn't read response: {}", err))?;
+    let root: Value =
+        serde_json::from_str(&body).map_err(|err| tr!("invalid response: {}", err))?;
+    let tasks = root
+        .get("data")
+        .and_then(Value::as_array)
+        .ok_or_else(|| tr!("expected a list of tasks in the response"))?;
+    Ok(tasks
+        .iter()
+        .filter_map(|task| {
+            Some(Daily {
+                id: task.get("id")?.as_str()?.to_owned(),
+                text: task.get("text")?.as_str()?.to_owned(),
+                streak: task.get("streak").and_then(Value::as_u64).unwrap_or(0) as u32,
+                completed: task
+                    .get("completed")
+                    .and_then(Value::as_bool)
+                    .unwrap_or(false),
+            })
+        })
+        .collect())
+}
+
+fn credentials() -> Option<&'static config::Habitica> {
+    let credentials = config::read_config().habitica.as_ref();
+    if credentials.is_none() {
+        eprintln!(
+            "{}",
+            tr!(
+                "no [habitica] section with user_id and api_token in {}",
+                config::config_path().unwrap_or_default().display()
+            )
+        );
+    }
+    credentials
+}
+
+/// Create or update a streak for every Habitica daily
+pub fn pull(state: &mut State) -> Result<(), Error> {
+    let credentials = credentials().ok_or(Error::Failed)?;
+    let dailies = match fetch_dailies(credentials) {
+        Ok(dailies) => dailies,
+        Err(err) => {
+            eprintln!("{}", tr!("couldn't fetch dailies from habitica: {}", err));
+            return Err(Error::Failed);
+        }
+    };
+    for daily in dailies {
+        if let Err(err) = validate_name(&daily.text) {
+            eprintln!("{}", tr!("skipping daily: {}", err));
+            continue;
+        }
+        if !state.streaks.contains_key(&daily.text) {
+            state.audit.push(audit::Record::new(
+                "add",
+                &daily.text,
+                tr!("pulled from habitica"),
+            ));
+        }
+        let streak = state
+            .streaks
+            .entry(daily.text.clone())
+            .or_insert_with(Streak::new);
+        streak.update_count(|_old_count| daily.streak);
+        let newly_done = daily.completed && !matches!(streak.state, StreakState::Done);
+        if daily.completed {
+            streak.state = StreakState::Done;
+            streak.last_hit = Local::now();
+        } else if daily.streak > 0 {
+            streak.state = StreakState::Pending;
+        }
+        if newly_done {
+            state
+                .events
+                .push(history::Entry::new(&daily.text, history::Event::Hit));
+            state.audit.push(audit::Record::new(
+                "hit",
+                &daily.text,
+                format!("now at {}", daily.streak),
+            ));
+        }
+        println!(
+            "{}",
+            tr!("pulled \"{}\": now at {}", daily.text, daily.streak)
+        );
+    }
+    Ok(())
+}
+
+/// Score up the dailies matching streaks which are done today but not yet completed on Habitica
+pub fn push(state: &State) -> Result<(), Error> {
+    let credentials = credentials().ok_or(Error::Failed)?;
+    let dailies = match fetch_dailies(credentials) {
+        Ok(dailies) => dailies,
+        Err(err) => {
+            eprintln!("{}", tr!("couldn't fetch dailies from habitica: {}", err));
+            return Err(Error::Failed);
+        }
+    };
+    let mut failed = false;
+    for daily in dailies.iter().filter(|daily| !daily.completed) {
+        let done = state
+            .streaks
+            .get(&daily.text)
+            .is_some_and(|streak| matches!(streak.state, StreakState::Done));
+        if !done {
+            continue;
+        }
+        let path = format!("/tasks/{}/score/up", daily.id);
+        match request(credentials, "POST", &path).call() {
+            Ok(_) => println!("{}", tr!("pushed hit of \"{}\" to habitica", daily.text)),
+            Err(err) => {
+                eprintln!(
+                    "{}",
+                    tr!("couldn't push \"{}\" to habitica: {}", daily.text, err)
+                );
+                failed = true;
+            }
+        }
+    }
+    if failed {
+        return Err(Error::Failed);
+    }
+    Ok(())
+}
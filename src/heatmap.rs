@@ -0,0 +1,176 @@
+use crate::{current_day, error::Error, history, read_state, tr};
+use chrono::{prelude::*, Duration};
+use std::{fs, path::Path};
+
+/// Weeks shown, so that the current week is the 53rd
+const WEEKS: i64 = 53;
+const CELL: usize = 11;
+const STEP: usize = CELL + 2;
+/// Room for the weekday labels on the left and month labels at the top
+const LEFT: usize = 30;
+const TOP: usize = 20;
+/// From no hits to the most hits in a day
+const COLORS: [(u8, u8, u8); 5] = [
+    (0xeb, 0xed, 0xf0),
+    (0x9b, 0xe9, 0xa8),
+    (0x40, 0xc4, 0x63),
+    (0x30, 0xa1, 0x4e),
+    (0x21, 0x6e, 0x39),
+];
+
+/// Hits per day over the last year, in columns of weeks from Monday to Sunday
+pub struct Heatmap {
+    /// The Monday of the first week
+    start: NaiveDate,
+    /// Hits on each day from the start to today
+    counts: Vec<usize>,
+    title: String,
+}
+
+impl Heatmap {
+    /// Count the hits of one streak, or how many streaks were hit each day
+    pub fn new(entries: &[history::Entry], name: Option<&str>, today: NaiveDate) -> Self {
+        let start = today
+            - Duration::weeks(WEEKS - 1)
+            - Duration::days(today.weekday().num_days_from_monday().into());
+        let hits = history::hit_dates(entries);
+        let mut counts = vec![0; (today - start).num_days() as usize + 1];
+        for (hit_name, date) in hits.iter() {
+            if date < &start || date > &today || name.is_some_and(|name| name != hit_name) {
+                continue;
+            }
+            counts[(*date - start).num_days() as usize] += 1;
+        }
+        Self {
+            start,
+            counts,
+            title: match name {
+                Some(name) => tr!("hits of \"{}\" in the last year", name),
+                None => tr!("streaks hit in the last year"),
+            },
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        LEFT + WEEKS as usize * STEP
+    }
+
+    pub fn height(&self) -> usize {
+        TOP + 7 * STEP
+    }
+
+    /// The top left corner, date and color of each day's square
+    fn cells(&self) -> impl Iterator<Item = ((usize, usize), NaiveDate, (u8, u8, u8))> + '_ {
+        let max = self.counts.iter().copied().max().unwrap_or(0).max(1);
+        self.counts.iter().enumerate().map(move |(day, &count)| {
+            let level = (count * (COLORS.len() - 1)).div_ceil(max);
+            let position = (LEFT + day / 7 * STEP, TOP + day % 7 * STEP);
+            (
+                position,
+                self.start + Duration::days(day as i64),
+                COLORS[level],
+            )
+        })
+    }
+
+    /// The heatmap as a standalone SVG image, with a tooltip for each day
+    pub fn svg(&self) -> String {
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" font-family=\"sans-serif\" font-size=\"9\">\n<title>{}</title>\n",
+            self.width(),
+            self.height(),
+            xml_escape(&self.title)
+        );
+        for (row, label) in [(0, "Mon"), (2, "Wed"), (4, "Fri")] {
+            svg += &format!(
+                "<text x=\"0\" y=\"{}\">{}</text>\n",
+                TOP + row * STEP + CELL - 2,
+                label
+            );
+        }
+        for column in 0..WEEKS {
+            // label the first week starting in each month
+            let week = self.start + Duration::weeks(column);
+            if week.day() <= 7 {
+                svg += &format!(
+                    "<text x=\"{}\" y=\"{}\">{}</text>\n",
+                    LEFT + column as usize * STEP,
+                    TOP - 6,
+                    week.format("%b")
+                );
+            }
+        }
+        for ((x, y), date, (r, g, b)) in self.cells() {
+            let count = self.counts[(date - self.start).num_days() as usize];
+            svg += &format!(
+                "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" rx=\"2\" fill=\"#{:02x}{:02x}{:02x}\"><title>{}: {}</title></rect>\n",
+                x, y, CELL, CELL, r, g, b, date, count
+            );
+        }
+        svg + "</svg>\n"
+    }
+}
+
+pub fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(feature = "png")]
+fn write_png(heatmap: &Heatmap, path: &Path) -> Result<(), String> {
+    use plotters::prelude::*;
+    let size = (heatmap.width() as u32, heatmap.height() as u32);
+    let root = BitMapBackend::new(path, size).into_drawing_area();
+    root.fill(&WHITE).map_err(|err| err.to_string())?;
+    for ((x, y), _date, (r, g, b)) in heatmap.cells() {
+        let corners = [(x as i32, y as i32), ((x + CELL) as i32, (y + CELL) as i32)];
+        root.draw(&Rectangle::new(corners, RGBColor(r, g, b).filled()))
+            .map_err(|err| err.to_string())?;
+    }
+    root.present().map_err(|err| err.to_string())
+}
+
+#[cfg(not(feature = "png"))]
+fn write_png(_heatmap: &Heatmap, _path: &Path) -> Result<(), String> {
+    Err(tr!("streaks was built without the \"png\" feature"))
+}
+
+/// Write the heatmap of one or all streaks as an SVG or, with `png`, a PNG image
+pub fn export(name: Option<&str>, path: &Path, png: bool) -> Result<(), Error> {
+    let mut state = read_state()?;
+    let name = match name {
+        Some(name) => match state.lookup(name) {
+            Some(name) => Some(name),
+            None => {
+                state.not_found(name);
+                return Ok(());
+            }
+        },
+        None => None,
+    };
+    let entries = match history::read_history() {
+        Ok(entries) => entries,
+        Err(err) => {
+            eprintln!("{}", tr!("couldn't export history: {}", err));
+            return Err(Error::Failed);
+        }
+    };
+    let heatmap = Heatmap::new(&entries, name.as_deref(), current_day());
+    let result = if png {
+        write_png(&heatmap, path)
+    } else {
+        fs::write(path, heatmap.svg()).map_err(|err| err.to_string())
+    };
+    match result {
+        Ok(()) => {
+            println!("{}", tr!("exported heatmap to {}", path.display()));
+            Ok(())
+        }
+        Err(err) => {
+            eprintln!("{}", tr!("couldn't write heatmap: {}", err));
+            Err(Error::Failed)
+        }
+    }
+}
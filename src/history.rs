@@ -0,0 +1,663 @@
+use crate::{crypto, current_day, day_of, ensure_data_dir, index, tr, State, Streak};
+use chrono::{prelude::*, Duration};
+use itertools::Itertools;
+use std::{
+    collections::HashSet,
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, Read, Write},
+    path::PathBuf,
+};
+
+#[derive(Clone)]
+pub enum Event {
+    Hit,
+    Expired,
+    /// The streak reached the given count
+    Milestone(u32),
+    /// The streak was deliberately started over, for the given reason if any
+    Reset(Option<String>),
+    /// The streak was still pending at its reminder time
+    Reminded,
+    /// The streak was put off until later in the day, holding back reminders
+    Snoozed,
+    /// The day was deliberately let go without breaking the streak, unlike a miss
+    Skipped,
+}
+
+impl Event {
+    /// The name used to select an event in webhook and hook config
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Event::Hit => "hit",
+            Event::Expired => "expire",
+            Event::Milestone(_) => "milestone",
+            Event::Reset(_) => "reset",
+            Event::Reminded => "remind",
+            Event::Snoozed => "snooze",
+            Event::Skipped => "skip",
+        }
+    }
+
+    pub fn serialize(&self) -> String {
+        match self {
+            Event::Hit => "Hit".to_owned(),
+            Event::Expired => "Expired".to_owned(),
+            Event::Milestone(count) => format!("Milestone:{}", count),
+            Event::Reset(None) => "Reset".to_owned(),
+            Event::Reset(Some(reason)) => format!("Reset:{}", reason),
+            Event::Reminded => "Reminded".to_owned(),
+            Event::Snoozed => "Snoozed".to_owned(),
+            Event::Skipped => "Skipped".to_owned(),
+        }
+    }
+
+    pub fn deserialize(string: &str) -> Result<Self, String> {
+        match string.split_once(':') {
+            None if string == "Hit" => Ok(Event::Hit),
+            None if string == "Expired" => Ok(Event::Expired),
+            None if string == "Reset" => Ok(Event::Reset(None)),
+            None if string == "Reminded" => Ok(Event::Reminded),
+            None if string == "Snoozed" => Ok(Event::Snoozed),
+            None if string == "Skipped" => Ok(Event::Skipped),
+            Some(("Reset", reason)) => Ok(Event::Reset(Some(reason.to_owned()))),
+            Some(("Milestone", count)) => {
+                Ok(Event::Milestone(count.parse::<u32>().map_err(|err| {
+                    tr!("expected unsigned integer for milestone: {}", err)
+                })?))
+            }
+            _ => Err(tr!("unknown history event: \"{}\"", string)),
+        }
+    }
+}
+
+/// A single timestamped event in the life of a streak
+#[derive(Clone)]
+pub struct Entry {
+    pub name: String,
+    pub time: DateTime<Local>,
+    pub event: Event,
+}
+
+impl Entry {
+    pub fn new(name: &str, event: Event) -> Self {
+        Self {
+            name: name.to_owned(),
+            time: Local::now(),
+            event,
+        }
+    }
+
+    /// An event that happened at the given time rather than now
+    pub fn at(name: &str, time: DateTime<Local>, event: Event) -> Self {
+        Self {
+            name: name.to_owned(),
+            time,
+            event,
+        }
+    }
+
+    /// An event recorded after the fact, at noon on a past day or now for today
+    pub fn on(name: &str, date: NaiveDate, event: Event) -> Self {
+        let time = match Local
+            .from_local_datetime(&date.and_hms(12, 0, 0))
+            .earliest()
+        {
+            Some(time) if date < current_day() => time,
+            _ => Local::now(),
+        };
+        Self {
+            name: name.to_owned(),
+            time,
+            event,
+        }
+    }
+
+    /// The count the event is about, the milestone reached or else the streak's current count
+    pub fn count(&self, state: &State) -> u32 {
+        match self.event {
+            Event::Milestone(count) => count,
+            _ => state
+                .streaks
+                .get(&self.name)
+                .map_or(0, |streak| streak.current_count),
+        }
+    }
+
+    pub fn serialize(&self) -> String {
+        format!("{},{},{}", self.name, self.time, self.event.serialize())
+    }
+
+    fn deserialize(line: &str) -> Result<Self, String> {
+        // split from the right so that the name is left intact
+        let values: Vec<_> = line.rsplitn(3, ',').collect();
+        match values.as_slice() {
+            [event, time, name] => Ok(Self {
+                name: (*name).to_owned(),
+                time: time
+                    .parse::<DateTime<Local>>()
+                    .map_err(|err| tr!("expected local datetime for time: {}", err))?,
+                event: Event::deserialize(event)?,
+            }),
+            _ => Err(tr!(
+                "expected name, time and event for a history entry: \"{}\"",
+                line
+            )),
+        }
+    }
+}
+
+pub fn ensure_history_path() -> Result<PathBuf, String> {
+    let mut path = ensure_data_dir()?;
+    path.push("history.txt");
+    Ok(path)
+}
+
+/// Pass each line of the history file to `visit` with its index, one at a time so that a long
+/// history is never held in memory as a whole, unless it's encrypted
+fn for_each_line(mut visit: impl FnMut(usize, &str) -> Result<(), String>) -> Result<(), String> {
+    let path = ensure_history_path()?;
+    let file = match File::open(&path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(tr!("couldn't read history file: {}", err)),
+    };
+    let mut reader = BufReader::new(file);
+    let start = reader
+        .fill_buf()
+        .map_err(|err| tr!("couldn't read history file: {}", err))?;
+    if crypto::is_encrypted(start) {
+        let mut bytes = Vec::new();
+        reader
+            .read_to_end(&mut bytes)
+            .map_err(|err| tr!("couldn't read history file: {}", err))?;
+        let string =
+            crypto::decode(bytes).map_err(|err| tr!("couldn't decode history file: {}", err))?;
+        return string
+            .lines()
+            .enumerate()
+            .try_for_each(|(index, line)| visit(index, line));
+    }
+    for (index, line) in reader.lines().enumerate() {
+        let line = line.map_err(|err| tr!("couldn't read history file: {}", err))?;
+        visit(index, &line)?;
+    }
+    Ok(())
+}
+
+/// Pass each recorded history entry to `visit` in the order they were recorded, for working
+/// something out from the whole history without keeping all of it
+pub fn for_each_entry(mut visit: impl FnMut(Entry)) -> Result<(), String> {
+    for_each_line(|index, line| parse_line(index, line).map(&mut visit))
+}
+
+/// Read every recorded history entry in the order they were recorded
+pub fn read_history() -> Result<Vec<Entry>, String> {
+    let mut entries = Vec::new();
+    for_each_entry(|entry| entries.push(entry))?;
+    Ok(entries)
+}
+
+/// Read the entries of a single streak, looking its lines up in the index where there is one,
+/// or else skipping over the lines about the others without parsing them
+pub fn read_history_of(name: &str) -> Result<Vec<Entry>, String> {
+    if let Some(lines) = index::lines_of(name)? {
+        // a bad line is reported by the scan below, which knows its line number
+        if let Ok(entries) = lines.iter().map(|line| Entry::deserialize(line)).collect() {
+            return Ok(entries);
+        }
+    }
+    // names can't contain commas, so this can't match a longer name
+    let prefix = format!("{},", name);
+    let mut entries = Vec::new();
+    for_each_line(|index, line| {
+        if line.starts_with(&prefix) {
+            entries.push(parse_line(index, line)?);
+        }
+        Ok(())
+    })?;
+    Ok(entries)
+}
+
+fn parse_line(line_number: usize, line: &str) -> Result<Entry, String> {
+    Entry::deserialize(line).map_err(|err| {
+        tr!(
+            "failed to parse history on line {}: {}",
+            line_number + 1,
+            err
+        )
+    })
+}
+
+pub fn deserialize_history(string: &str) -> Result<Vec<Entry>, String> {
+    string
+        .lines()
+        .enumerate()
+        .map(|(line_number, line)| parse_line(line_number, line))
+        .collect()
+}
+
+/// Append new entries to the end of the history file
+pub fn append_history(entries: &[Entry]) {
+    if entries.is_empty() {
+        return;
+    }
+    if crypto::enabled() {
+        // the ciphertext can't be appended to, so the whole file has to be rewritten
+        match read_history() {
+            Ok(mut history) => {
+                history.extend(entries.iter().cloned());
+                write_history(&history);
+            }
+            Err(err) => eprintln!("{}", tr!("couldn't update history: {}", err)),
+        }
+        return;
+    }
+    let path = match ensure_history_path() {
+        Ok(path) => path,
+        Err(err) => {
+            eprintln!("{}", tr!("couldn't update history: {}", err));
+            return;
+        }
+    };
+    match OpenOptions::new().append(true).create(true).open(&path) {
+        Ok(mut file) => {
+            for entry in entries.iter() {
+                if let Err(err) = writeln!(file, "{}", entry.serialize()) {
+                    eprintln!("{}", tr!("couldn't write history file: {}", err));
+                    return;
+                }
+            }
+        }
+        Err(err) => eprintln!("{}", tr!("couldn't open history file: {}", err)),
+    }
+}
+
+/// Rename several streaks in one pass over the history
+pub fn rename_all_in_history(renames: &[(String, String)]) {
+    if renames.is_empty() {
+        return;
+    }
+    let mut entries = match read_history() {
+        Ok(entries) => entries,
+        Err(err) => {
+            eprintln!("{}", tr!("couldn't update history: {}", err));
+            return;
+        }
+    };
+    for entry in entries.iter_mut() {
+        if let Some((_name, new_name)) = renames.iter().find(|(name, _new)| *name == entry.name) {
+            entry.name = new_name.clone();
+        }
+    }
+    write_history(&entries);
+}
+
+pub fn serialize_history(entries: &[Entry]) -> String {
+    entries
+        .iter()
+        .map(|entry| entry.serialize() + "\n")
+        .collect()
+}
+
+/// Replace the whole history file with the given entries
+pub fn write_history(entries: &[Entry]) {
+    let path = match ensure_history_path() {
+        Ok(path) => path,
+        Err(err) => {
+            eprintln!("{}", tr!("couldn't update history: {}", err));
+            return;
+        }
+    };
+    index::invalidate();
+    match crypto::encode(&serialize_history(entries)) {
+        Ok(bytes) => {
+            // write next to the old history and move it over, so a crash never leaves half of it
+            let mut temporary = path.as_os_str().to_owned();
+            temporary.push(".new");
+            let temporary = PathBuf::from(temporary);
+            if let Err(err) =
+                fs::write(&temporary, bytes).and_then(|()| fs::rename(&temporary, &path))
+            {
+                eprintln!("{}", tr!("couldn't write history file: {}", err));
+            }
+        }
+        Err(err) => eprintln!("{}", tr!("couldn't encode history file: {}", err)),
+    }
+}
+
+/// Dates on which each streak was hit, as (name, date) pairs
+pub fn hit_dates(entries: &[Entry]) -> HashSet<(String, NaiveDate)> {
+    entries.iter().filter_map(hit_date).collect()
+}
+
+/// The streak and date of a hit, or none for any other event
+pub fn hit_date(entry: &Entry) -> Option<(String, NaiveDate)> {
+    matches!(entry.event, Event::Hit).then(|| (entry.name.clone(), day_of(entry.time)))
+}
+
+/// How many days of hits sparklines show by default
+pub const SPARKLINE_DAYS: i64 = 14;
+
+/// The streaks with an event of the given kind on a day, like those already reminded of today
+pub fn names_with_event<'a>(entries: &'a [Entry], kind: &str, date: NaiveDate) -> HashSet<&'a str> {
+    entries
+        .iter()
+        .filter(|entry| entry.event.kind() == kind && day_of(entry.time) == date)
+        .map(|entry| entry.name.as_str())
+        .collect()
+}
+
+/// One character per day for the given number of days up to today, taller on days with a hit
+pub fn sparkline(entries: &[Entry], name: &str, today: NaiveDate, days: i64) -> String {
+    let hit_dates: HashSet<_> = entries
+        .iter()
+        .filter(|entry| entry.name == name && matches!(entry.event, Event::Hit))
+        .map(|entry| day_of(entry.time))
+        .collect();
+    (0..days)
+        .rev()
+        .map(|offset| {
+            if hit_dates.contains(&(today - Duration::days(offset))) {
+                '█'
+            } else {
+                '▁'
+            }
+        })
+        .collect()
+}
+
+/// The last hit carried forward over each deadline before `date` which falls on a rest day or a
+/// holiday, as those pass as though the streak was hit on them, and over the rest while there
+/// are skip tokens to spend on them, like `cover_missed_days`
+fn carry_over(
+    streak: &Streak,
+    holidays: &HashSet<NaiveDate>,
+    tokens: &mut u32,
+    mut last_hit: NaiveDate,
+    date: NaiveDate,
+) -> NaiveDate {
+    while streak.schedule.missed(last_hit, date) {
+        let deadline = streak.schedule.next_deadline(last_hit);
+        let passes = holidays.contains(&deadline) || streak.rest.contains(&deadline.weekday());
+        if !passes {
+            if *tokens == 0 {
+                break;
+            }
+            *tokens -= 1;
+        }
+        last_hit = deadline;
+    }
+    last_hit
+}
+
+/// The first and last day and the length of the longest unbroken run of hits
+pub fn best_run(
+    entries: &[Entry],
+    name: &str,
+    streak: &Streak,
+    holidays: &HashSet<NaiveDate>,
+) -> Option<(NaiveDate, NaiveDate, u32)> {
+    let dates: Vec<_> = entries
+        .iter()
+        .filter(|entry| entry.name == name && matches!(entry.event, Event::Hit))
+        .map(|entry| day_of(entry.time))
+        .sorted()
+        .dedup()
+        .collect();
+    let mut best: Option<(NaiveDate, NaiveDate, u32)> = None;
+    let mut run: Option<(NaiveDate, NaiveDate, u32)> = None;
+    for date in dates {
+        let (start, length) = match run {
+            Some((start, end, length))
+                if !streak
+                    .schedule
+                    .missed(carry_over(streak, holidays, &mut 0, end, date), date) =>
+            {
+                (start, length + 1)
+            }
+            _ => (date, 1),
+        };
+        run = Some((start, date, length));
+        if best.is_none_or(|(_start, _end, best_length)| length > best_length) {
+            best = run;
+        }
+    }
+    best
+}
+
+/// Replay the hits, skips and resets of a streak since the history began the way hitting and
+/// updating it do, visiting each day up to `end` with the last hit and count of the run going
+/// at the end of it
+fn replay(
+    entries: &[Entry],
+    name: &str,
+    streak: &Streak,
+    holidays: &HashSet<NaiveDate>,
+    skip_every: Option<u32>,
+    end: NaiveDate,
+    mut visit: impl FnMut(NaiveDate, Option<(NaiveDate, u32)>),
+) {
+    let mut hits = HashSet::new();
+    let mut skips = HashSet::new();
+    let mut resets = HashSet::new();
+    for entry in entries.iter().filter(|entry| entry.name == name) {
+        let date = day_of(entry.time);
+        match entry.event {
+            Event::Hit => {
+                hits.insert(date);
+            }
+            Event::Skipped => {
+                skips.insert(date);
+            }
+            Event::Reset(_) => {
+                resets.insert(date);
+            }
+            _ => (),
+        }
+    }
+    let mut date = match hits.iter().chain(resets.iter()).min() {
+        Some(&first) => first,
+        None => return,
+    };
+    // earned tokens are kept through resets and expiries, so there's one balance throughout
+    let mut tokens = 0;
+    let mut run: Option<(NaiveDate, u32)> = None;
+    while date <= end {
+        if resets.contains(&date) {
+            run = None;
+        }
+        let alive = run
+            .map(|(last_hit, count)| {
+                (
+                    carry_over(streak, holidays, &mut tokens, last_hit, date),
+                    count,
+                )
+            })
+            .filter(|&(last_hit, _count)| !streak.schedule.missed(last_hit, date));
+        run = if hits.contains(&date) {
+            let count = alive.map_or(0, |(_last_hit, count)| count) + 1;
+            if skip_every.is_some_and(|every| count.is_multiple_of(every)) {
+                tokens += 1;
+            }
+            Some((date, count))
+        } else if skips.contains(&date) {
+            alive.map(|(_last_hit, count)| (date, count))
+        } else {
+            alive
+        };
+        visit(date, run);
+        date = date.succ();
+    }
+}
+
+/// The count of a streak at the end of each day from `start` to `end`, rebuilt from its hits,
+/// skips and resets since the history began
+pub fn daily_counts(
+    entries: &[Entry],
+    name: &str,
+    streak: &Streak,
+    holidays: &HashSet<NaiveDate>,
+    skip_every: Option<u32>,
+    start: NaiveDate,
+    end: NaiveDate,
+) -> Vec<u32> {
+    let days = end.signed_duration_since(start).num_days() + 1;
+    let mut counts = vec![0; days.max(0) as usize];
+    replay(
+        entries,
+        name,
+        streak,
+        holidays,
+        skip_every,
+        end,
+        |date, run| {
+            if date >= start {
+                counts[date.signed_duration_since(start).num_days() as usize] =
+                    run.map_or(0, |(_last_hit, count)| count);
+            }
+        },
+    );
+    counts
+}
+
+/// The last hit, moved past the days that passed without one, and the count of the run of a
+/// streak going at the end of a day, rebuilt from its history
+pub fn run_on(
+    entries: &[Entry],
+    name: &str,
+    streak: &Streak,
+    holidays: &HashSet<NaiveDate>,
+    skip_every: Option<u32>,
+    date: NaiveDate,
+) -> Option<(NaiveDate, u32)> {
+    let mut last = None;
+    replay(
+        entries,
+        name,
+        streak,
+        holidays,
+        skip_every,
+        date,
+        |_date, run| last = run,
+    );
+    last
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 4, day).unwrap()
+    }
+
+    fn hits(days: &[u32]) -> Vec<Entry> {
+        days.iter()
+            .map(|&day| Entry::on("a", date(day), Event::Hit))
+            .collect()
+    }
+
+    fn daily(rest: &[Weekday]) -> Streak {
+        Streak {
+            rest: rest.to_vec(),
+            ..Streak::new()
+        }
+    }
+
+    #[test]
+    fn holidays_pass_without_breaking_the_count() {
+        let entries = hits(&[1, 2, 4, 5]);
+        let holidays = [date(3)].iter().copied().collect();
+        let counts = daily_counts(
+            &entries,
+            "a",
+            &daily(&[]),
+            &holidays,
+            None,
+            date(1),
+            date(5),
+        );
+        assert_eq!(counts, [1, 2, 2, 3, 4]);
+        let without = daily_counts(
+            &entries,
+            "a",
+            &daily(&[]),
+            &HashSet::new(),
+            None,
+            date(5),
+            date(5),
+        );
+        assert_eq!(without, [2]);
+    }
+
+    #[test]
+    fn holidays_join_runs() {
+        let entries = hits(&[1, 2, 5, 6, 7]);
+        let holidays = [date(3), date(4)].iter().copied().collect();
+        assert_eq!(
+            best_run(&entries, "a", &daily(&[]), &holidays),
+            Some((date(1), date(7), 5))
+        );
+        assert_eq!(
+            best_run(
+                &entries,
+                "a",
+                &daily(&[]),
+                &[date(3)].iter().copied().collect()
+            ),
+            Some((date(5), date(7), 3))
+        );
+    }
+
+    #[test]
+    fn rest_days_pass_like_holidays() {
+        // the 6th of April 2024 is a Saturday
+        let entries = hits(&[4, 5, 7, 8]);
+        let none = HashSet::new();
+        let resting = daily(&[Weekday::Sat]);
+        let counts = daily_counts(&entries, "a", &resting, &none, None, date(4), date(8));
+        assert_eq!(counts, [1, 2, 2, 3, 4]);
+        assert_eq!(
+            best_run(&entries, "a", &resting, &none),
+            Some((date(4), date(8), 4))
+        );
+        assert_eq!(
+            best_run(&entries, "a", &daily(&[Weekday::Sun]), &none),
+            Some((date(4), date(5), 2))
+        );
+    }
+
+    #[test]
+    fn earned_skip_tokens_cover_missed_days() {
+        let entries = hits(&[1, 2, 4, 6]);
+        let none = HashSet::new();
+        // the token earned on the second hit covers the 3rd but not the 5th as well
+        let counts = daily_counts(&entries, "a", &daily(&[]), &none, Some(2), date(1), date(6));
+        assert_eq!(counts, [1, 2, 2, 3, 3, 1]);
+        assert_eq!(
+            run_on(&entries, "a", &daily(&[]), &none, Some(2), date(5)),
+            Some((date(4), 3))
+        );
+        assert_eq!(
+            run_on(&entries, "a", &daily(&[]), &none, None, date(5)),
+            Some((date(4), 1))
+        );
+    }
+
+    #[test]
+    fn skipped_days_and_resets_carry_through_the_replay() {
+        let mut entries = hits(&[1, 2, 4, 5]);
+        entries.push(Entry::on("a", date(3), Event::Skipped));
+        entries.push(Entry::on("a", date(5), Event::Reset(None)));
+        let counts = daily_counts(
+            &entries,
+            "a",
+            &daily(&[]),
+            &HashSet::new(),
+            None,
+            date(1),
+            date(5),
+        );
+        assert_eq!(counts, [1, 2, 2, 3, 1]);
+    }
+}
@@ -0,0 +1,48 @@
+use crate::{config, ensure_data_dir, history::Entry, tr, State};
+use std::process::Command;
+
+fn run_hook(command: &mut Command, description: &str) {
+    match command.status() {
+        Ok(status) if status.success() => (),
+        Ok(status) => eprintln!("{}", tr!("hook {} failed: {}", description, status)),
+        Err(err) => eprintln!("{}", tr!("couldn't run hook {}: {}", description, err)),
+    }
+}
+
+/// Run the `hooks/on-<event>` script in the data directory and the configured command for each
+/// event, passing the streak name and count as arguments
+pub fn run(state: &State, entries: &[Entry]) {
+    if entries.is_empty() {
+        return;
+    }
+    let hooks_dir = match ensure_data_dir() {
+        Ok(dir) => dir.join("hooks"),
+        Err(err) => {
+            eprintln!("{}", tr!("couldn't run hooks: {}", err));
+            return;
+        }
+    };
+    let commands = &config::read_config().hooks;
+    for entry in entries.iter() {
+        let kind = entry.event.kind();
+        let args = [entry.name.clone(), entry.count(state).to_string()];
+        let script = hooks_dir.join(format!("on-{}", kind));
+        if script.is_file() {
+            run_hook(
+                Command::new(&script).args(&args),
+                &script.display().to_string(),
+            );
+        }
+        if let Some(command) = commands.command(kind) {
+            // "$@" passes the arguments through without them being interpreted by the shell
+            run_hook(
+                Command::new("sh")
+                    .arg("-c")
+                    .arg(format!("{} \"$@\"", command))
+                    .arg("streaks")
+                    .args(&args),
+                command,
+            );
+        }
+    }
+}
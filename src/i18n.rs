@@ -0,0 +1,107 @@
+use crate::config;
+use std::{collections::HashMap, env, fmt::Display, fs, io, sync::OnceLock};
+use toml::Value;
+
+/// Translations of the English messages, keyed by the English text itself
+static CATALOG: OnceLock<HashMap<String, String>> = OnceLock::new();
+
+/// The configured language, or else the one from `LANG` without its encoding
+fn language() -> Option<String> {
+    config::read_config().language.clone().or_else(|| {
+        let lang = env::var("LANG").ok()?;
+        let lang = lang.split('.').next().unwrap_or_default();
+        (!lang.is_empty() && lang != "C" && lang != "POSIX").then(|| lang.to_owned())
+    })
+}
+
+/// Read `locales/<language>.toml` next to the config file, trying `de` when `de_AT` is missing
+fn load() -> HashMap<String, String> {
+    let language = match language() {
+        Some(language) => language,
+        None => return HashMap::new(),
+    };
+    let dir = match config::config_path() {
+        Some(path) => path.with_file_name("locales"),
+        None => return HashMap::new(),
+    };
+    let mut candidates = vec![language.clone()];
+    if let Some((base, _region)) = language.split_once('_') {
+        candidates.push(base.to_owned());
+    }
+    for candidate in candidates {
+        let path = dir.join(format!("{}.toml", candidate));
+        let string = match fs::read_to_string(&path) {
+            Ok(string) => string,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+            Err(err) => {
+                eprintln!("couldn't read {}: {}", path.display(), err);
+                break;
+            }
+        };
+        match string.parse::<Value>() {
+            Ok(Value::Table(table)) => {
+                return table
+                    .into_iter()
+                    .filter_map(|(message, translation)| {
+                        Some((message, translation.as_str()?.to_owned()))
+                    })
+                    .collect()
+            }
+            Ok(_) => eprintln!("expected a table of translations in {}", path.display()),
+            Err(err) => eprintln!("couldn't parse {}: {}", path.display(), err),
+        }
+        break;
+    }
+    HashMap::new()
+}
+
+/// Translate a message and fill in its placeholders, either `{}` for the next argument or
+/// `{0}`, `{1}` and so on so that translations can reorder them, with `{{` and `}}` as braces
+pub fn translate(message: &str, args: &[&dyn Display]) -> String {
+    let template = CATALOG
+        .get_or_init(load)
+        .get(message)
+        .map_or(message, String::as_str);
+    let mut output = String::new();
+    let mut next = 0;
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                output.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                output.push('}');
+            }
+            '{' => {
+                let placeholder: String = chars.by_ref().take_while(|&c| c != '}').collect();
+                let index = if placeholder.is_empty() {
+                    next += 1;
+                    Some(next - 1)
+                } else {
+                    placeholder.parse::<usize>().ok()
+                };
+                match index.and_then(|index| args.get(index)) {
+                    Some(arg) => output.push_str(&arg.to_string()),
+                    // keep a broken placeholder in a translation visible rather than dropping it
+                    None => output.push_str(&format!("{{{}}}", placeholder)),
+                }
+            }
+            c => output.push(c),
+        }
+    }
+    output
+}
+
+/// Translate a message with `format!`-like placeholders into the user's language
+#[macro_export]
+macro_rules! tr {
+    ($message:literal) => {
+        $crate::i18n::translate($message, &[])
+    };
+    ($message:literal, $($arg:expr),+ $(,)?) => {
+        $crate::i18n::translate($message, &[$(&$arg),+])
+    };
+}
@@ -0,0 +1,418 @@
+use crate::{
+    audit, current_day,
+    error::Error,
+    history::{self, Event},
+    tr, validate_name, yes_or_no, State, Streak, StreakState,
+};
+use chrono::prelude::*;
+use serde_json::Value;
+use std::{collections::HashSet, fs, path::Path};
+
+/// Split RFC 4180 CSV text into records of unquoted fields
+fn parse_csv(text: &str) -> Result<Vec<Vec<String>>, String> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (quoted, c) {
+            (true, '"') if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            (true, '"') => quoted = false,
+            (true, c) => field.push(c),
+            (false, '"') if field.is_empty() => quoted = true,
+            (false, ',') => record.push(std::mem::take(&mut field)),
+            (false, '\r') if chars.peek() == Some(&'\n') => (),
+            (false, '\n') => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            }
+            (false, c) => field.push(c),
+        }
+    }
+    if quoted {
+        return Err(tr!("unterminated quoted field"));
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    Ok(records)
+}
+
+/// Accept both RFC 3339 timestamps and bare dates, as spreadsheets tend to produce
+fn parse_time(string: &str) -> Result<DateTime<Local>, String> {
+    if let Ok(time) = string.parse::<DateTime<Local>>() {
+        return Ok(time);
+    }
+    string
+        .parse::<NaiveDate>()
+        .ok()
+        .and_then(|date| {
+            Local
+                .from_local_datetime(&date.and_hms(12, 0, 0))
+                .earliest()
+        })
+        .ok_or_else(|| tr!("expected a datetime or date: \"{}\"", string))
+}
+
+fn parse_count(string: &str, field: &str) -> Result<u32, String> {
+    string
+        .parse::<u32>()
+        .map_err(|err| tr!("expected unsigned integer for {}: {}", field, err))
+}
+
+/// Build a streak from optional fields, filling in sensible defaults
+fn make_streak(
+    current_count: Option<u32>,
+    max_count: Option<u32>,
+    last_hit: Option<DateTime<Local>>,
+    state: Option<StreakState>,
+) -> Streak {
+    let current_count = current_count.unwrap_or(0);
+    Streak {
+        current_count,
+        max_count: max_count.unwrap_or(0).max(current_count),
+        last_hit: last_hit.unwrap_or_else(Local::now),
+        state: state.unwrap_or(if current_count == 0 {
+            StreakState::New
+        } else {
+            StreakState::Pending
+        }),
+        ..Streak::new()
+    }
+}
+
+/// Streaks and history entries read from an import file
+#[derive(Default)]
+struct Imported {
+    streaks: Vec<(String, Streak)>,
+    entries: Vec<history::Entry>,
+}
+
+fn import_csv(text: &str) -> Result<Imported, String> {
+    let records = parse_csv(text)?;
+    let (header, rows) = match records.split_first() {
+        Some(split) => split,
+        None => return Ok(Imported::default()),
+    };
+    let column = |name: &str| header.iter().position(|field| field.trim() == name);
+    let name_column = column("name").ok_or_else(|| tr!("expected a \"name\" column"))?;
+    let mut imported = Imported::default();
+    for (row_number, row) in rows.iter().enumerate() {
+        let field = |index: Option<usize>| {
+            index
+                .and_then(|i| row.get(i))
+                .map(|value| value.trim())
+                .filter(|value| !value.is_empty())
+        };
+        let located = |err: String| tr!("failed to parse row {}: {}", row_number + 2, err);
+        let name = field(Some(name_column))
+            .ok_or_else(|| located("missing name".to_owned()))?
+            .to_owned();
+        if let Some(time) = field(column("time")) {
+            let event = field(column("event")).unwrap_or("Hit");
+            imported.entries.push(history::Entry {
+                name,
+                time: parse_time(time).map_err(located)?,
+                event: Event::deserialize(event).map_err(located)?,
+            });
+        } else {
+            let streak = make_streak(
+                field(column("current_count"))
+                    .map(|value| parse_count(value, "current_count"))
+                    .transpose()
+                    .map_err(located)?,
+                field(column("max_count"))
+                    .map(|value| parse_count(value, "max_count"))
+                    .transpose()
+                    .map_err(located)?,
+                field(column("last_hit"))
+                    .map(parse_time)
+                    .transpose()
+                    .map_err(located)?,
+                field(column("state"))
+                    .map(StreakState::deserialize)
+                    .transpose()
+                    .map_err(located)?,
+            );
+            imported.streaks.push((name, streak));
+        }
+    }
+    Ok(imported)
+}
+
+fn json_count(object: &Value, field: &str) -> Result<Option<u32>, String> {
+    match object.get(field) {
+        None | Some(Value::Null) => Ok(None),
+        Some(value) => value
+            .as_u64()
+            .map(|n| Some(n as u32))
+            .ok_or_else(|| tr!("expected unsigned integer for {}", field)),
+    }
+}
+
+fn json_str<'a>(object: &'a Value, field: &str) -> Result<Option<&'a str>, String> {
+    match object.get(field) {
+        None | Some(Value::Null) => Ok(None),
+        Some(value) => value
+            .as_str()
+            .map(Some)
+            .ok_or_else(|| tr!("expected string for {}", field)),
+    }
+}
+
+fn import_json(text: &str) -> Result<Imported, String> {
+    let root: Value = serde_json::from_str(text).map_err(|err| tr!("invalid json: {}", err))?;
+    let array = |key: &str| match root.get(key) {
+        None => Ok(Vec::new()),
+        Some(Value::Array(values)) => Ok(values.clone()),
+        Some(_) => Err(tr!("expected an array for \"{}\"", key)),
+    };
+    let mut imported = Imported::default();
+    for (index, object) in array("streaks")?.iter().enumerate() {
+        let located = |err: String| tr!("failed to parse streaks[{}]: {}", index, err);
+        let name = json_str(object, "name")
+            .map_err(located)?
+            .ok_or_else(|| located("missing name".to_owned()))?
+            .to_owned();
+        let streak = make_streak(
+            json_count(object, "current_count").map_err(located)?,
+            json_count(object, "max_count").map_err(located)?,
+            json_str(object, "last_hit")
+                .map_err(located)?
+                .map(parse_time)
+                .transpose()
+                .map_err(located)?,
+            json_str(object, "state")
+                .map_err(located)?
+                .map(StreakState::deserialize)
+                .transpose()
+                .map_err(located)?,
+        );
+        imported.streaks.push((name, streak));
+    }
+    for (index, object) in array("history")?.iter().enumerate() {
+        let located = |err: String| tr!("failed to parse history[{}]: {}", index, err);
+        let name = json_str(object, "name")
+            .map_err(located)?
+            .ok_or_else(|| located("missing name".to_owned()))?
+            .to_owned();
+        let time = json_str(object, "time")
+            .map_err(located)?
+            .ok_or_else(|| located("missing time".to_owned()))?;
+        let event = json_str(object, "event").map_err(located)?.unwrap_or("Hit");
+        imported.entries.push(history::Entry {
+            name,
+            time: parse_time(time).map_err(located)?,
+            event: Event::deserialize(event).map_err(located)?,
+        });
+    }
+    Ok(imported)
+}
+
+/// Read an import file, choosing the format from its extension or contents
+fn read_import(path: &Path) -> Result<Imported, String> {
+    let text = fs::read_to_string(path).map_err(|err| tr!("couldn't read file: {}", err))?;
+    let is_json = match path.extension().and_then(|ext| ext.to_str()) {
+        Some(ext) => ext.eq_ignore_ascii_case("json"),
+        None => text.trim_start().starts_with('{'),
+    };
+    if is_json {
+        import_json(&text)
+    } else {
+        import_csv(&text)
+    }
+}
+
+/// Checkmark values used by Loop Habit Tracker exports
+const LOOP_SKIP: i32 = 3;
+const LOOP_YES_MANUAL: i32 = 2;
+const LOOP_YES_AUTO: i32 = 1;
+
+/// Rebuild a streak from a Loop habit's checkmarks, given as (date, value) pairs
+fn loop_streak(checkmarks: &mut [(NaiveDate, i32)]) -> Streak {
+    checkmarks.sort_by_key(|pair| pair.0);
+    let (mut run, mut max_count) = (0, 0);
+    let mut last_hit = None;
+    let mut previous: Option<NaiveDate> = None;
+    for &(date, value) in checkmarks.iter() {
+        // only consecutive days are meaningful, a gap in the export is a miss
+        if previous.is_some_and(|prev| date.signed_duration_since(prev).num_days() > 1) {
+            run = 0;
+        }
+        previous = Some(date);
+        match value {
+            LOOP_YES_MANUAL => {
+                run += 1;
+                max_count = max_count.max(run);
+                last_hit = Some(date);
+            }
+            // automatic checkmarks and skips keep the chain intact without extending it
+            LOOP_YES_AUTO | LOOP_SKIP => (),
+            _ => run = 0,
+        }
+    }
+    let today = current_day();
+    let (current_count, state) =
+        match last_hit.map(|date| today.signed_duration_since(date).num_days()) {
+            None => (0, StreakState::New),
+            Some(0) => (run, StreakState::Done),
+            Some(1) => (run, StreakState::Pending),
+            Some(_) => (0, StreakState::Expired),
+        };
+    make_streak(
+        Some(current_count),
+        Some(max_count),
+        last_hit.and_then(|date| {
+            Local
+                .from_local_datetime(&date.and_hms(12, 0, 0))
+                .earliest()
+        }),
+        Some(state),
+    )
+}
+
+/// Read the Checkmarks.csv from an extracted Loop Habit Tracker export
+fn read_loop(path: &Path) -> Result<Imported, String> {
+    let path = if path.is_dir() {
+        path.join("Checkmarks.csv")
+    } else {
+        path.to_owned()
+    };
+    let text = fs::read_to_string(&path).map_err(|err| tr!("couldn't read file: {}", err))?;
+    let records = parse_csv(&text)?;
+    let (header, rows) = match records.split_first() {
+        Some((header, rows)) if header.first().map(|field| field.trim()) == Some("Date") => {
+            (header, rows)
+        }
+        _ => {
+            return Err(tr!(
+                "expected a \"Date\" column followed by one column per habit"
+            ))
+        }
+    };
+    let mut imported = Imported::default();
+    for (column, name) in header.iter().enumerate().skip(1) {
+        let name = name.trim();
+        if name.is_empty() {
+            continue;
+        }
+        let mut checkmarks = Vec::new();
+        for (row_number, row) in rows.iter().enumerate() {
+            let located = |err| tr!("failed to parse row {}: {}", row_number + 2, err);
+            let date = row[0]
+                .trim()
+                .parse::<NaiveDate>()
+                .map_err(|err| located(tr!("expected date: {}", err)))?;
+            let value = match row.get(column).map(|value| value.trim()) {
+                None | Some("") => continue,
+                Some(value) => value
+                    .parse::<i32>()
+                    .map_err(|err| located(tr!("expected checkmark value: {}", err)))?,
+            };
+            checkmarks.push((date, value));
+            if value == LOOP_YES_MANUAL {
+                imported.entries.push(history::Entry {
+                    name: name.to_owned(),
+                    time: Local
+                        .from_local_datetime(&date.and_hms(12, 0, 0))
+                        .earliest()
+                        .ok_or_else(|| located(tr!("nonexistent local date: {}", date)))?,
+                    event: Event::Hit,
+                });
+            }
+        }
+        imported
+            .streaks
+            .push((name.to_owned(), loop_streak(&mut checkmarks)));
+    }
+    Ok(imported)
+}
+
+/// Merge streaks and history from a file into the state, prompting on conflicts
+pub fn import(state: &mut State, path: &Path) -> Result<(), Error> {
+    import_with(state, path, read_import)
+}
+
+/// Convert the habits and checkmarks of a Loop Habit Tracker export into streaks
+pub fn import_loop(state: &mut State, path: &Path) -> Result<(), Error> {
+    import_with(state, path, read_loop)
+}
+
+fn import_with(
+    state: &mut State,
+    path: &Path,
+    reader: fn(&Path) -> Result<Imported, String>,
+) -> Result<(), Error> {
+    let imported = match reader(path) {
+        Ok(imported) => imported,
+        Err(err) => {
+            eprintln!("{}", tr!("couldn't import {}: {}", path.display(), err));
+            return Err(Error::Failed);
+        }
+    };
+    let mut added = 0;
+    for (name, streak) in imported.streaks {
+        if let Err(err) = validate_name(&name) {
+            eprintln!("{}", tr!("skipping imported streak: {}", err));
+            continue;
+        }
+        let mut detail = tr!("imported from {}", path.display());
+        if let Some(existing) = state.streaks.get(&name) {
+            let prompt = tr!(
+                "replace streak \"{}\" (at {}, max {}) with imported one (at {}, max {})?",
+                name,
+                existing.current_count,
+                existing.max_count,
+                streak.current_count,
+                streak.max_count
+            );
+            if !yes_or_no(&prompt) {
+                continue;
+            }
+            detail = tr!(
+                "replaced by one imported from {}, was at {}, max {}",
+                path.display(),
+                existing.current_count,
+                existing.max_count
+            );
+        }
+        state.audit.push(audit::Record::new("add", &name, detail));
+        state.streaks.insert(name, streak);
+        added += 1;
+    }
+    let known: HashSet<_> = match history::read_history() {
+        Ok(entries) => entries
+            .into_iter()
+            .map(|entry| (entry.name, entry.time))
+            .collect(),
+        Err(err) => {
+            eprintln!(
+                "{}",
+                tr!("couldn't read history, skipping imported history: {}", err)
+            );
+            println!("{}", tr!("imported {} streaks", added));
+            return Ok(());
+        }
+    };
+    let new_entries: Vec<_> = imported
+        .entries
+        .into_iter()
+        .filter(|entry| validate_name(&entry.name).is_ok())
+        .filter(|entry| !known.contains(&(entry.name.clone(), entry.time)))
+        .collect();
+    println!(
+        "{}",
+        tr!(
+            "imported {} streaks and {} history entries",
+            added,
+            new_entries.len()
+        )
+    );
+    state.events.extend(new_entries);
+    Ok(())
+}
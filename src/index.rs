@@ -0,0 +1,378 @@
+//! An index of where each streak's lines are in `history.txt`, so that commands about one streak
+//! can read its history without going through everyone else's.
+//!
+//! `history.idx` starts with a header giving how many bytes of the history it covers and a
+//! fingerprint of the end of them, then a table of `<name>,<first>,<count>` lines, a blank line
+//! and the byte offsets of every indexed history line as fixed width hex numbers, grouped by
+//! streak so that each one's offsets are the `count` starting at `first`. A lookup reads the
+//! table and then seeks straight to the offsets and the lines they point to.
+//!
+//! Appending to the history leaves the index alone, so hits stay as cheap as before. Lines added
+//! since the index was built are found by scanning just the part past it, and once that gets
+//! long the index is built again. Rewriting the history drops the index, and one that doesn't
+//! match the history it's read with, like after a sync, is ignored and rebuilt. An encrypted
+//! history has to be decrypted as a whole anyway, so it's never indexed.
+
+use crate::{crypto, debug, ensure_data_dir, eventlog::fingerprint, history, tr};
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{self, BufRead, BufReader, Read, Seek, SeekFrom},
+    path::PathBuf,
+};
+
+const INDEX_HEADER: &str = "# streaks history index ";
+/// How much can be appended to the history past the index before it's built again, since that
+/// part is scanned on every lookup
+const UNINDEXED_LIMIT: u64 = 64 * 1024;
+/// Bytes taken by each offset, as 16 hex digits and a newline
+const OFFSET_WIDTH: u64 = 17;
+/// How much of the end of the indexed history the fingerprint covers
+const CHECKED_BYTES: u64 = 64;
+
+fn index_path() -> Result<PathBuf, String> {
+    let mut path = ensure_data_dir()?;
+    path.push("history.idx");
+    Ok(path)
+}
+
+/// The fingerprint of the last bytes before `covered`, which tells a history that was only
+/// appended to from one that was replaced
+fn tail_fingerprint(history: &mut File, covered: u64) -> io::Result<u64> {
+    let start = covered.saturating_sub(CHECKED_BYTES);
+    history.seek(SeekFrom::Start(start))?;
+    let mut bytes = Vec::new();
+    history.take(covered - start).read_to_end(&mut bytes)?;
+    Ok(fingerprint(&bytes))
+}
+
+struct Index {
+    covered: u64,
+    check: u64,
+    /// The position of the first and the number of offsets of each streak
+    table: BTreeMap<String, (u64, u64)>,
+    /// Where the offsets start in the index file
+    start: u64,
+}
+
+fn read_index(reader: &mut BufReader<File>) -> Result<Option<Index>, String> {
+    let mut line = String::new();
+    let mut start = 0;
+    let mut next_line = |line: &mut String| -> Result<usize, String> {
+        line.clear();
+        let read = reader
+            .read_line(line)
+            .map_err(|err| tr!("couldn't read history index: {}", err))?;
+        start += read as u64;
+        Ok(read)
+    };
+    next_line(&mut line)?;
+    let header = match line.trim_end().strip_prefix(INDEX_HEADER) {
+        Some(header) => header.split_once(' '),
+        None => return Ok(None),
+    };
+    let (covered, check) = match header.and_then(|(covered, check)| {
+        Some((covered.parse().ok()?, u64::from_str_radix(check, 16).ok()?))
+    }) {
+        Some(header) => header,
+        None => return Ok(None),
+    };
+    let mut table = BTreeMap::new();
+    loop {
+        if next_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let entry = line.trim_end_matches('\n');
+        if entry.is_empty() {
+            break;
+        }
+        let mut fields = entry.rsplitn(3, ',');
+        let parsed = (|| {
+            let count = fields.next()?.parse().ok()?;
+            let first = fields.next()?.parse().ok()?;
+            Some((fields.next()?.to_owned(), (first, count)))
+        })();
+        match parsed {
+            Some((name, position)) => {
+                table.insert(name, position);
+            }
+            None => return Ok(None),
+        }
+    }
+    Ok(Some(Index {
+        covered,
+        check,
+        table,
+        start,
+    }))
+}
+
+impl Index {
+    /// Index every complete line of the history
+    fn build(history: &mut File) -> io::Result<(Self, Vec<u64>)> {
+        history.seek(SeekFrom::Start(0))?;
+        let mut reader = BufReader::new(history);
+        let mut lines: BTreeMap<String, Vec<u64>> = BTreeMap::new();
+        let mut covered = 0;
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            let read = reader.read_until(b'\n', &mut line)? as u64;
+            // a line still being written is left for the scan past the index
+            if read == 0 || line.last() != Some(&b'\n') {
+                break;
+            }
+            if let Some(name) = line.split(|&byte| byte == b',').next() {
+                lines
+                    .entry(String::from_utf8_lossy(name).into_owned())
+                    .or_default()
+                    .push(covered);
+            }
+            covered += read;
+        }
+        let history = reader.into_inner();
+        let check = tail_fingerprint(history, covered)?;
+        let mut table = BTreeMap::new();
+        let mut offsets = Vec::new();
+        for (name, positions) in lines {
+            table.insert(name, (offsets.len() as u64, positions.len() as u64));
+            offsets.extend(positions);
+        }
+        let index = Self {
+            covered,
+            check,
+            table,
+            start: 0,
+        };
+        Ok((index, offsets))
+    }
+
+    fn serialize(&self, offsets: &[u64]) -> String {
+        let mut string = format!("{}{} {:016x}\n", INDEX_HEADER, self.covered, self.check);
+        for (name, (first, count)) in self.table.iter() {
+            string += &format!("{},{},{}\n", name, first, count);
+        }
+        string.push('\n');
+        for offset in offsets {
+            string += &format!("{:016x}\n", offset);
+        }
+        string
+    }
+
+    /// The offsets of a streak's lines in the history, read from the index file, or nothing if
+    /// they can't be
+    fn offsets_of(&self, reader: &mut BufReader<File>, name: &str) -> Option<Vec<u64>> {
+        let (first, count) = match self.table.get(name) {
+            Some(&position) => position,
+            None => return Some(Vec::new()),
+        };
+        reader
+            .seek(SeekFrom::Start(self.start + first * OFFSET_WIDTH))
+            .ok()?;
+        let mut bytes = vec![0; (count * OFFSET_WIDTH) as usize];
+        reader.read_exact(&mut bytes).ok()?;
+        bytes
+            .chunks(OFFSET_WIDTH as usize)
+            .map(|chunk| {
+                let hex = std::str::from_utf8(chunk.strip_suffix(b"\n")?).ok()?;
+                u64::from_str_radix(hex, 16).ok()
+            })
+            .collect()
+    }
+}
+
+/// Write out a freshly built index, which only speeds up later lookups, so failing to is no
+/// reason to fail the command
+fn save(index: &Index, offsets: &[u64]) {
+    let written = index_path().and_then(|path| {
+        let temporary = path.with_extension("idx.tmp");
+        fs::write(&temporary, index.serialize(offsets))
+            .and_then(|()| fs::rename(&temporary, &path))
+            .map_err(|err| err.to_string())
+    });
+    match written {
+        Ok(()) => debug!("indexed {} bytes of history", index.covered),
+        Err(err) => debug!("couldn't write history index: {}", err),
+    }
+}
+
+/// Drop the index after the history is rewritten, so it's built again for the new one
+pub fn invalidate() {
+    if let Ok(path) = index_path() {
+        if let Err(err) = fs::remove_file(path) {
+            if err.kind() != io::ErrorKind::NotFound {
+                eprintln!("{}", tr!("couldn't remove history index: {}", err));
+            }
+        }
+    }
+}
+
+/// Read the history line starting at an offset, if it's there and complete
+fn line_at(history: &mut BufReader<File>, offset: u64) -> io::Result<Option<String>> {
+    history.seek(SeekFrom::Start(offset))?;
+    let mut line = String::new();
+    history.read_line(&mut line)?;
+    Ok(line.strip_suffix('\n').map(str::to_owned))
+}
+
+/// The history lines of a streak in the order they were recorded, found through the index, or
+/// nothing if the history can't be indexed and has to be scanned instead
+pub fn lines_of(name: &str) -> Result<Option<Vec<String>>, String> {
+    let read_error = |err: io::Error| tr!("couldn't read history file: {}", err);
+    let mut history = match File::open(history::ensure_history_path()?) {
+        Ok(file) => file,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Some(Vec::new())),
+        Err(err) => return Err(read_error(err)),
+    };
+    let length = history.metadata().map_err(read_error)?.len();
+    let mut start = [0; 8];
+    let peeked = history.read(&mut start).map_err(read_error)?;
+    if crypto::is_encrypted(&start[..peeked]) {
+        return Ok(None);
+    }
+    let mut saved = match File::open(index_path()?) {
+        Ok(file) => {
+            let mut reader = BufReader::new(file);
+            read_index(&mut reader)?.map(|index| (index, reader))
+        }
+        Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+        Err(err) => return Err(tr!("couldn't read history index: {}", err)),
+    };
+    if let Some((index, _reader)) = &saved {
+        let current = index.covered <= length
+            && length - index.covered <= UNINDEXED_LIMIT
+            && tail_fingerprint(&mut history, index.covered).map_err(read_error)? == index.check;
+        if !current {
+            debug!("the history index is out of date");
+            saved = None;
+        }
+    }
+    let (covered, offsets) = match saved {
+        Some((index, mut reader)) => match index.offsets_of(&mut reader, name) {
+            Some(offsets) => (index.covered, offsets),
+            None => {
+                debug!("the history index is damaged");
+                invalidate();
+                return Ok(None);
+            }
+        },
+        None => {
+            let (index, offsets) = Index::build(&mut history).map_err(read_error)?;
+            save(&index, &offsets);
+            let own = match index.table.get(name) {
+                Some(&(first, count)) => offsets[first as usize..(first + count) as usize].to_vec(),
+                None => Vec::new(),
+            };
+            (index.covered, own)
+        }
+    };
+    // names can't contain commas, so this can't match a longer name
+    let prefix = format!("{},", name);
+    let mut reader = BufReader::new(history);
+    let mut lines = Vec::new();
+    for offset in offsets {
+        match line_at(&mut reader, offset).map_err(read_error)? {
+            Some(line) if line.starts_with(&prefix) => lines.push(line),
+            _ => {
+                debug!("the history index doesn't match the history");
+                invalidate();
+                return Ok(None);
+            }
+        }
+    }
+    reader.seek(SeekFrom::Start(covered)).map_err(read_error)?;
+    for line in reader.lines() {
+        let line = line.map_err(read_error)?;
+        if line.starts_with(&prefix) {
+            lines.push(line);
+        }
+    }
+    Ok(Some(lines))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, process};
+
+    /// A scratch file of its own for each test, as they run in parallel
+    fn scratch(name: &str, contents: &str) -> (PathBuf, File) {
+        let path = env::temp_dir().join(format!("streaks-{}-{}", process::id(), name));
+        fs::write(&path, contents).unwrap();
+        let file = File::open(&path).unwrap();
+        (path, file)
+    }
+
+    const HISTORY: &str = "a,2024-03-01 12:00:00 +00:00,Hit
+b,2024-03-01 13:00:00 +00:00,Hit
+a,2024-03-02 12:00:00 +00:00,Hit
+ab,2024-03-02 13:00:00 +00:00,Hit
+a,2024-03-03 12:00:00 +00:00,Reset
+b,2024-03-03";
+
+    #[test]
+    fn saved_index_finds_each_streaks_lines() {
+        let (history_path, mut history) = scratch("history", HISTORY);
+        let (index, offsets) = Index::build(&mut history).unwrap();
+        // the unfinished last line is left for the scan past the index
+        assert_eq!(index.covered, HISTORY.rfind('\n').unwrap() as u64 + 1);
+        let (index_path, file) = scratch("index", &index.serialize(&offsets));
+        let mut reader = BufReader::new(file);
+        let read = read_index(&mut reader).unwrap().unwrap();
+        assert_eq!(read.covered, index.covered);
+        assert_eq!(read.check, index.check);
+        let indexed = |name: &str| -> Vec<&str> {
+            let mut reader = BufReader::new(reader.get_ref().try_clone().unwrap());
+            read.offsets_of(&mut reader, name)
+                .unwrap()
+                .into_iter()
+                .map(|offset| HISTORY[offset as usize..].lines().next().unwrap())
+                .collect()
+        };
+        assert_eq!(
+            indexed("a"),
+            [
+                "a,2024-03-01 12:00:00 +00:00,Hit",
+                "a,2024-03-02 12:00:00 +00:00,Hit",
+                "a,2024-03-03 12:00:00 +00:00,Reset"
+            ]
+        );
+        assert_eq!(indexed("ab"), ["ab,2024-03-02 13:00:00 +00:00,Hit"]);
+        assert_eq!(indexed("b"), ["b,2024-03-01 13:00:00 +00:00,Hit"]);
+        assert!(indexed("c").is_empty());
+        fs::remove_file(history_path).unwrap();
+        fs::remove_file(index_path).unwrap();
+    }
+
+    #[test]
+    fn replaced_histories_fail_the_check() {
+        let (path, mut history) = scratch("replaced", HISTORY);
+        let (index, _offsets) = Index::build(&mut history).unwrap();
+        assert_eq!(
+            tail_fingerprint(&mut history, index.covered).unwrap(),
+            index.check
+        );
+        fs::write(&path, HISTORY.replace("Reset", "Hit  ")).unwrap();
+        let mut replaced = File::open(&path).unwrap();
+        assert_ne!(
+            tail_fingerprint(&mut replaced, index.covered).unwrap(),
+            index.check
+        );
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn damaged_indexes_are_not_used() {
+        let (path, file) = scratch("damaged", "# streaks history index 12 zz\n\n");
+        assert!(read_index(&mut BufReader::new(file)).unwrap().is_none());
+        fs::write(
+            &path,
+            "# streaks history index 12 0\na,0,5\n\n0000000000000000\n",
+        )
+        .unwrap();
+        let mut reader = BufReader::new(File::open(&path).unwrap());
+        let index = read_index(&mut reader).unwrap().unwrap();
+        assert!(index.offsets_of(&mut reader, "a").is_none());
+        fs::remove_file(path).unwrap();
+    }
+}
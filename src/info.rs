@@ -0,0 +1,105 @@
+use crate::{config, current_day, error::Error, history, read_state, tr, Priority};
+use chrono::prelude::*;
+use itertools::Itertools;
+
+/// Print everything known about one streak
+pub fn print_info(name: &str) -> Result<(), Error> {
+    let mut state = read_state()?;
+    let streak = match state.streaks.get(name) {
+        Some(streak) => streak,
+        None => {
+            state.not_found(name);
+            return Ok(());
+        }
+    };
+    let entries = history::read_history_of(name).unwrap_or_else(|err| {
+        eprintln!(
+            "{}",
+            tr!(
+                "couldn't read history, some details will be missing: {}",
+                err
+            )
+        );
+        Vec::new()
+    });
+    let today = current_day();
+    println!("{}", name);
+    if let Some(note) = &streak.note {
+        println!("{}", tr!("  description: {}", note));
+    }
+    println!("{}", tr!("  state: {}", streak.state.serialize()));
+    println!(
+        "{}",
+        tr!(
+            "  count: {} (max {})",
+            streak.current_count,
+            streak.max_count
+        )
+    );
+    println!("{}", tr!("  schedule: {}", streak.schedule.describe()));
+    if streak.target > 1 {
+        println!("{}", tr!("  target: {} hits a day", streak.target));
+    }
+    if !streak.tags.is_empty() {
+        println!("{}", tr!("  tags: {}", streak.tags.join(" ")));
+    }
+    if streak.priority != Priority::Normal {
+        println!("{}", tr!("  priority: {}", streak.priority.serialize()));
+    }
+    if !streak.rest.is_empty() {
+        println!(
+            "{}",
+            tr!(
+                "  rest days: {}",
+                streak.rest.iter().map(Weekday::to_string).join(", ")
+            )
+        );
+    }
+    if let Some(dependency) = &streak.depends_on {
+        println!("{}", tr!("  after: {}", dependency));
+    }
+    let created = streak.created.or_else(|| {
+        // older streaks didn't record this, but their history goes back to it
+        entries
+            .iter()
+            .find(|entry| entry.name == name)
+            .map(|entry| entry.time)
+    });
+    if let Some(created) = created {
+        println!("{}", tr!("  created: {}", created.format("%Y-%m-%d")));
+    }
+    println!(
+        "{}",
+        tr!("  last hit: {}", streak.last_hit.format("%Y-%m-%d %H:%M"))
+    );
+    if let Some(challenge) = streak.challenge {
+        println!(
+            "{}",
+            tr!(
+                "  challenge: {} of {} days left",
+                challenge.days_left(today),
+                challenge.days
+            )
+        );
+    }
+    println!(
+        "{}",
+        tr!(
+            "  last {} days: {}",
+            history::SPARKLINE_DAYS,
+            history::sparkline(&entries, name, today, history::SPARKLINE_DAYS)
+        )
+    );
+    if let Some((start, end, length)) = history::best_run(
+        &entries,
+        name,
+        streak,
+        &config::read_config().holidays.all(),
+    ) {
+        println!(
+            "{}",
+            tr!("  best run: {} hits from {} to {}", length, start, end)
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,296 @@
+//! A write-ahead journal, so that a write cut short by a crash or power cut is finished the next
+//! time streaks runs instead of losing the change.
+//!
+//! Before the state is written, what's about to be written is put in `journal.txt`, ending with
+//! a marker once it's complete. That's the changes about to be logged, or the whole state when
+//! it's about to be snapshotted instead, then the history and audit records going with them and
+//! the streaks whose history is following them to a new name. Once everything has been written
+//! the journal is removed. A journal still around at startup with its end marker is replayed,
+//! which is safe since changes already logged and records already written aren't written again,
+//! and a streak is never renamed to one that already exists, so renaming its history twice
+//! changes nothing. One without the marker never got as far as changing anything, so it's
+//! dropped.
+
+use crate::{
+    audit, crypto, ensure_data_dir,
+    error::Error,
+    eventlog::{self, Pending},
+    history, tr, StateLock,
+};
+use std::{
+    fs::{self, File},
+    io::{self, Write},
+    path::PathBuf,
+};
+
+const CHANGES_MARKER: &str = "-- changes";
+const SNAPSHOT_MARKER: &str = "-- snapshot";
+const HISTORY_MARKER: &str = "-- history";
+const AUDIT_MARKER: &str = "-- audit";
+const RENAMES_MARKER: &str = "-- renames";
+const END_MARKER: &str = "-- end";
+
+fn journal_path() -> Result<PathBuf, Error> {
+    let mut path = ensure_data_dir()?;
+    path.push("journal.txt");
+    Ok(path)
+}
+
+/// What a write changes besides the state
+type Effects = (
+    Vec<history::Entry>,
+    Vec<audit::Record>,
+    Vec<(String, String)>,
+);
+
+fn serialize(
+    pending: &Pending,
+    entries: &[history::Entry],
+    records: &[audit::Record],
+    renames: &[(String, String)],
+) -> String {
+    let mut lines = match pending {
+        Pending::Changes(changes) => {
+            let mut lines = vec![CHANGES_MARKER.to_owned()];
+            lines.extend(changes.iter().cloned());
+            lines
+        }
+        Pending::Snapshot(serialized) => vec![SNAPSHOT_MARKER.to_owned(), serialized.clone()],
+    };
+    lines.push(HISTORY_MARKER.to_owned());
+    lines.extend(entries.iter().map(history::Entry::serialize));
+    lines.push(AUDIT_MARKER.to_owned());
+    lines.extend(records.iter().map(audit::Record::serialize));
+    lines.push(RENAMES_MARKER.to_owned());
+    lines.extend(
+        renames
+            .iter()
+            .map(|(name, new_name)| format!("{},{}", name, new_name)),
+    );
+    lines.push(END_MARKER.to_owned());
+    lines.join("\n") + "\n"
+}
+
+/// Record what's about to be written, only returning once it's on disk
+pub fn begin(
+    pending: &Pending,
+    entries: &[history::Entry],
+    records: &[audit::Record],
+    renames: &[(String, String)],
+) -> Result<(), Error> {
+    if matches!(pending, Pending::Changes(changes) if changes.is_empty())
+        && entries.is_empty()
+        && records.is_empty()
+        && renames.is_empty()
+    {
+        return Ok(());
+    }
+    let path = journal_path()?;
+    let bytes = crypto::encode(&serialize(pending, entries, records, renames))
+        .map_err(|err| Error::WriteState(path.clone(), err))?;
+    File::create(&path)
+        .and_then(|mut file| {
+            file.write_all(&bytes)?;
+            file.sync_data()
+        })
+        .map_err(|err| Error::WriteState(path, err.to_string()))
+}
+
+/// Mark the write as finished
+pub fn finish() {
+    match journal_path().map(fs::remove_file) {
+        Ok(Ok(())) => (),
+        Ok(Err(err)) if err.kind() == io::ErrorKind::NotFound => (),
+        Ok(Err(err)) => eprintln!("{}", tr!("couldn't remove journal: {}", err)),
+        Err(err) => eprintln!("{}", tr!("couldn't remove journal: {}", err)),
+    }
+}
+
+/// Split a complete journal into the write, history and audit records and renames it holds
+fn parse(string: &str) -> Option<(Pending, Effects)> {
+    let body = string.strip_suffix(&format!("{}\n", END_MARKER))?;
+    let markers = [
+        CHANGES_MARKER,
+        SNAPSHOT_MARKER,
+        HISTORY_MARKER,
+        AUDIT_MARKER,
+        RENAMES_MARKER,
+    ];
+    let mut sections = Vec::new();
+    for line in body.lines() {
+        if markers.contains(&line) {
+            sections.push((line, Vec::new()));
+        } else {
+            sections.last_mut()?.1.push(line);
+        }
+    }
+    // journals from before renames were journaled have no section for them
+    let (sections, renames) = match sections.as_slice() {
+        [sections @ .., (RENAMES_MARKER, renames)] => (sections, renames.as_slice()),
+        sections => (sections, &[][..]),
+    };
+    let (pending, entries, records) = match sections {
+        [(CHANGES_MARKER, changes), (HISTORY_MARKER, entries), (AUDIT_MARKER, records)] => (
+            Pending::Changes(changes.iter().map(|&line| line.to_owned()).collect()),
+            entries,
+            records,
+        ),
+        [(SNAPSHOT_MARKER, state), (HISTORY_MARKER, entries), (AUDIT_MARKER, records)] => {
+            (Pending::Snapshot(state.join("\n")), entries, records)
+        }
+        _ => return None,
+    };
+    let entries = history::deserialize_history(&entries.join("\n")).ok()?;
+    let records = records
+        .iter()
+        .map(|&line| audit::Record::deserialize(line))
+        .collect::<Result<_, _>>()
+        .ok()?;
+    let renames = renames
+        .iter()
+        .map(|&line| {
+            let (name, new_name) = line.split_once(',')?;
+            Some((name.to_owned(), new_name.to_owned()))
+        })
+        .collect::<Option<_>>()?;
+    Some((pending, (entries, records, renames)))
+}
+
+/// Finish or drop a write left unfinished by an earlier run
+pub fn recover() -> Result<(), Error> {
+    let path = journal_path()?;
+    if !path.exists() {
+        return Ok(());
+    }
+    let _lock = StateLock::acquire()?;
+    let bytes = match fs::read(&path) {
+        Ok(bytes) => bytes,
+        // another process recovered it while we waited for the lock
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(Error::ReadState(path, err)),
+    };
+    match crypto::decode(bytes).ok().as_deref().and_then(parse) {
+        Some((pending, (entries, records, renames))) => {
+            match &pending {
+                Pending::Changes(changes) => eventlog::replay(changes)?,
+                Pending::Snapshot(serialized) => eventlog::store(serialized)?,
+            }
+            // the history and audit records may have been written before it was interrupted,
+            // and appending them without knowing would count them twice, so the journal is kept
+            // for next time if they can't be read
+            let written = |recorded: Vec<String>, new: Vec<String>| recorded.ends_with(&new);
+            let serialized = |entries: &[history::Entry]| {
+                entries.iter().map(history::Entry::serialize).collect()
+            };
+            history::rename_all_in_history(&renames);
+            let history = history::read_history().map_err(|err| {
+                eprintln!(
+                    "{}",
+                    tr!(
+                        "couldn't finish a write that was interrupted last time: {}",
+                        err
+                    )
+                );
+                Error::Failed
+            })?;
+            if !written(serialized(&history), serialized(&entries)) {
+                history::append_history(&entries);
+            }
+            let serialized =
+                |records: &[audit::Record]| records.iter().map(audit::Record::serialize).collect();
+            let audit = audit::read_audit().map_err(|err| {
+                eprintln!(
+                    "{}",
+                    tr!(
+                        "couldn't finish a write that was interrupted last time: {}",
+                        err
+                    )
+                );
+                Error::Failed
+            })?;
+            if !written(serialized(&audit), serialized(&records)) {
+                audit::append(&records);
+            }
+            eprintln!("{}", tr!("finished a write that was interrupted last time"));
+        }
+        None => eprintln!(
+            "{}",
+            tr!("warning: dropping a write that was interrupted before it changed anything")
+        ),
+    }
+    finish();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::prelude::*;
+
+    fn entries() -> Vec<history::Entry> {
+        let date = NaiveDate::from_ymd_opt(2024, 3, 1).unwrap();
+        vec![history::Entry::on("gym", date, history::Event::Hit)]
+    }
+
+    #[test]
+    fn changes_round_trip() {
+        let changes = vec![
+            "2024-03-01T12:00:00+00:00 put streak gym,1,1".to_owned(),
+            "2024-03-01T12:00:00+00:00 delete alias g".to_owned(),
+        ];
+        let journal = serialize(&Pending::Changes(changes.clone()), &entries(), &[], &[]);
+        match parse(&journal) {
+            Some((Pending::Changes(parsed), (parsed_entries, records, renames))) => {
+                assert_eq!(parsed, changes);
+                assert_eq!(parsed_entries.len(), 1);
+                assert!(records.is_empty());
+                assert!(renames.is_empty());
+            }
+            _ => panic!("couldn't parse the journal"),
+        }
+        let empty = serialize(&Pending::Changes(Vec::new()), &entries(), &[], &[]);
+        assert!(
+            matches!(parse(&empty), Some((Pending::Changes(changes), _)) if changes.is_empty())
+        );
+    }
+
+    #[test]
+    fn snapshots_round_trip_with_markers_inside_lines() {
+        let state = "# streaks state v16\ngym,0,0,a note ending -- history\n\ng,gym".to_owned();
+        let journal = serialize(&Pending::Snapshot(state.clone()), &[], &[], &[]);
+        match parse(&journal) {
+            Some((Pending::Snapshot(parsed), (entries, _records, _renames))) => {
+                assert_eq!(parsed, state);
+                assert!(entries.is_empty());
+            }
+            _ => panic!("couldn't parse the journal"),
+        }
+    }
+
+    #[test]
+    fn renames_round_trip() {
+        let renames = vec![
+            ("gym".to_owned(), "lift".to_owned()),
+            ("run".to_owned(), "jog".to_owned()),
+        ];
+        let journal = serialize(&Pending::Changes(Vec::new()), &[], &[], &renames);
+        match parse(&journal) {
+            Some((_pending, (_entries, _records, parsed))) => assert_eq!(parsed, renames),
+            _ => panic!("couldn't parse the journal"),
+        }
+        // a journal written before renames were journaled is still finished
+        let old = journal.replace("-- renames\ngym,lift\nrun,jog\n", "");
+        assert!(matches!(
+            parse(&old),
+            Some((_pending, (_entries, _records, renames))) if renames.is_empty()
+        ));
+    }
+
+    #[test]
+    fn journals_cut_short_are_dropped() {
+        let journal = serialize(&Pending::Changes(Vec::new()), &entries(), &[], &[]);
+        let cut = &journal[..journal.len() - END_MARKER.len() - 1];
+        assert!(parse(cut).is_none());
+        assert!(parse(&journal[..journal.len() / 2]).is_none());
+    }
+}
@@ -0,0 +1,32 @@
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// How much to log, raised by each `-v`
+static VERBOSITY: AtomicU8 = AtomicU8::new(0);
+
+pub fn set_verbosity(level: u8) {
+    VERBOSITY.store(level, Ordering::Relaxed);
+}
+
+pub fn enabled(level: u8) -> bool {
+    VERBOSITY.load(Ordering::Relaxed) >= level
+}
+
+/// Log what streaks is doing to stderr when run with `-v`
+#[macro_export]
+macro_rules! debug {
+    ($($arg:tt)+) => {
+        if $crate::log::enabled(1) {
+            eprintln!("debug: {}", format_args!($($arg)+));
+        }
+    };
+}
+
+/// Log the details of each decision to stderr when run with `-vv`
+#[macro_export]
+macro_rules! trace {
+    ($($arg:tt)+) => {
+        if $crate::log::enabled(2) {
+            eprintln!("trace: {}", format_args!($($arg)+));
+        }
+    };
+}
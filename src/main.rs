@@ -1,43 +1,209 @@
-use chrono::prelude::*;
+use chrono::{prelude::*, Duration};
+use error::Error;
 use itertools::Itertools;
 use std::{
-    collections::HashMap,
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
     env, fmt,
-    fs::{self, File, OpenOptions},
-    io::{self, BufRead, Read, Write},
-    path::PathBuf,
+    fs::{self, File, OpenOptions, TryLockError},
+    io::{self, BufRead, IsTerminal, Write},
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicBool, AtomicU32, Ordering},
+    thread, time,
 };
+use unicode_width::UnicodeWidthStr;
 
-/// Levenshtein distance
-fn lev(a: &str, b: &str) -> usize {
-    if b.is_empty() {
-        a.len()
-    } else if a.is_empty() {
-        b.len()
-    } else if a.chars().next() == b.chars().next() {
-        lev(&a[1..], &b[1..])
+mod achievements;
+mod audit;
+mod backup;
+mod config;
+mod crypto;
+mod dbus;
+mod digest;
+mod display;
+mod doctor;
+mod edit;
+mod error;
+mod eventlog;
+mod export;
+mod habitica;
+mod heatmap;
+mod history;
+mod hooks;
+mod i18n;
+mod import;
+mod index;
+mod info;
+mod journal;
+mod log;
+mod mqtt;
+mod report;
+mod select;
+mod server;
+mod shared;
+mod stats;
+mod status;
+mod sync;
+mod telegram;
+mod templates;
+mod views;
+mod webhooks;
+
+/// Levenshtein distance between two strings, or `None` if it is more than `limit`
+fn lev(a: &str, b: &str, limit: usize) -> Option<usize> {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    if a.len().max(b.len()) - a.len().min(b.len()) > limit {
+        return None;
+    }
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+    for (i, a_char) in a.iter().enumerate() {
+        current[0] = i + 1;
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + (a_char != b_char) as usize;
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        // distances never decrease down the matrix, so give up once a whole row is too far
+        if current.iter().all(|&distance| distance > limit) {
+            return None;
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    Some(previous[b.len()]).filter(|&distance| distance <= limit)
+}
+
+/// Edit distance between two names if it's small enough to be a typo
+fn typo_distance(a: &str, b: &str) -> Option<usize> {
+    let shortest = a.chars().count().min(b.chars().count());
+    lev(a, b, usize::min(shortest / 2, 3))
+}
+
+/// Check that a name can be stored, since the data files are line based and comma separated
+fn validate_name(name: &str) -> Result<(), String> {
+    if name.trim().is_empty() {
+        Err(tr!("streak names can't be empty"))
+    } else if name.contains(',') {
+        Err(tr!("streak name \"{}\" can't contain commas", name))
+    } else if name.contains(char::is_control) {
+        Err(tr!(
+            "streak name \"{}\" can't contain newlines or control characters",
+            name.escape_debug()
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// Check that a tag can be stored, since tags are kept space separated
+fn validate_tag(tag: &str) -> Result<(), String> {
+    if tag.is_empty() || tag.contains(|c: char| c == ',' || c.is_whitespace() || c.is_control()) {
+        Err(tr!(
+            "tag \"{}\" must be non-empty without commas or whitespace",
+            tag.escape_debug()
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// Reasons end up last in history entries, which are split on commas from the right
+fn validate_reason(reason: &str) -> Result<(), String> {
+    if reason.contains(|c: char| c == ',' || c.is_control()) {
+        Err(tr!("reasons can't contain commas or control characters"))
+    } else {
+        Ok(())
+    }
+}
+
+fn validate_note(note: &str) -> Result<(), String> {
+    if note.contains(char::is_control) {
+        Err(tr!("notes can't contain newlines or control characters"))
+    } else {
+        Ok(())
+    }
+}
+
+/// How a stored name matches what was typed, better matches comparing as smaller
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum NameMatch {
+    Exact,
+    IgnoringCase,
+    Prefix,
+    Substring,
+    Typo(usize),
+}
+
+fn match_name(query: &str, name: &str) -> Option<NameMatch> {
+    if name == query {
+        return Some(NameMatch::Exact);
+    }
+    let (query, name) = (query.to_lowercase(), name.to_lowercase());
+    if name == query {
+        Some(NameMatch::IgnoringCase)
+    } else if name.starts_with(&query) {
+        Some(NameMatch::Prefix)
+    } else if name.contains(&query) {
+        Some(NameMatch::Substring)
     } else {
-        1 + lev(&a[1..], b)
-            .min(lev(a, &b[1..]))
-            .min(lev(&a[1..], &b[1..]))
+        typo_distance(&name, &query).map(NameMatch::Typo)
     }
 }
 
-fn close_match(a: &str, b: &str) -> bool {
-    lev(a, b) <= usize::min(usize::min(a.len(), b.len()) / 2, 3)
+/// Set by `--yes` to accept every prompt
+static ASSUME_YES: AtomicBool = AtomicBool::new(false);
+/// Set by `--no-input` to decline every prompt
+static NO_INPUT: AtomicBool = AtomicBool::new(false);
+/// Set once a streak, alias or group named on the command line turns out not to exist, so the
+/// command can go on with the other names and still exit with `Error::NotFound`
+static NOT_FOUND: AtomicBool = AtomicBool::new(false);
+
+/// The answer to give without asking, if prompts shouldn't block
+fn assumed_answer() -> Option<bool> {
+    if ASSUME_YES.load(Ordering::Relaxed) {
+        Some(true)
+    } else if NO_INPUT.load(Ordering::Relaxed) || !io::stdin().is_terminal() {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+/// Read a line typed in answer to a prompt, treating the end of input as declining
+fn read_answer() -> Option<String> {
+    if let Err(err) = io::stdout().flush() {
+        eprintln!("{}", tr!("couldn't show prompt: {}", err));
+    }
+    match io::stdin().lock().lines().next() {
+        Some(Ok(answer)) => Some(answer),
+        Some(Err(err)) => {
+            eprintln!("{}", tr!("couldn't read answer: {}", err));
+            None
+        }
+        None => {
+            println!();
+            None
+        }
+    }
 }
 
 /// Prompt for a yes/no answer
 fn yes_or_no(prompt: &str) -> bool {
+    if let Some(answer) = assumed_answer() {
+        println!("{} [y/n] :{}", prompt, if answer { "y" } else { "n" });
+        return answer;
+    }
     loop {
         print!("{} [y/n] :", prompt);
-        io::stdout().flush().unwrap();
-        let stdin = io::stdin();
-        let answer = stdin.lock().lines().next().unwrap().unwrap();
-        if answer.starts_with(|c: char| c.to_ascii_lowercase() == 'y') {
+        let answer = match read_answer() {
+            Some(answer) => answer,
+            None => return false,
+        };
+        if answer.starts_with(|c: char| c.eq_ignore_ascii_case(&'y')) {
             println!();
             return true;
-        } else if answer.starts_with(|c: char| c.to_ascii_lowercase() == 'n') {
+        } else if answer.starts_with(|c: char| c.eq_ignore_ascii_case(&'n')) {
             println!();
             return false;
         } else {
@@ -46,7 +212,148 @@ fn yes_or_no(prompt: &str) -> bool {
     }
 }
 
-type ParseError = String;
+/// Prompt to pick one of several options by number, returning `None` if none are wanted
+fn choose(prompt: &str, options: &[String]) -> Option<usize> {
+    println!("{}", prompt);
+    for (i, option) in options.iter().enumerate() {
+        println!("  {}) {}", i + 1, option);
+    }
+    if let Some(answer) = assumed_answer() {
+        // accepting means taking the first, best ranked, option
+        println!(
+            "[1-{}/n] :{}",
+            options.len(),
+            if answer { "1" } else { "n" }
+        );
+        return answer.then_some(0);
+    }
+    loop {
+        print!("[1-{}/n] :", options.len());
+        let answer = read_answer()?;
+        let answer = answer.trim();
+        if answer.starts_with(|c: char| c.eq_ignore_ascii_case(&'n')) {
+            println!();
+            return None;
+        } else if let Some(n) = answer
+            .parse::<usize>()
+            .ok()
+            .filter(|n| (1..=options.len()).contains(n))
+        {
+            println!();
+            return Some(n - 1);
+        }
+    }
+}
+
+/// Why the state file couldn't be parsed, with the 1-based line and comma-separated field
+#[derive(Debug, PartialEq, Eq)]
+pub enum ParseError {
+    BadFieldCount {
+        line: usize,
+        expected: usize,
+        found: usize,
+    },
+    BadCount {
+        line: usize,
+        column: usize,
+        value: String,
+    },
+    BadTimestamp {
+        line: usize,
+        column: usize,
+        value: String,
+    },
+    UnknownState {
+        line: usize,
+        column: usize,
+        value: String,
+    },
+    /// Any other malformed field, like a schedule
+    BadValue {
+        line: usize,
+        column: usize,
+        value: String,
+        reason: String,
+    },
+    /// A line too long to be anything but damage, which isn't parsed at all
+    LineTooLong {
+        line: usize,
+        length: usize,
+    },
+    BadVersion(String),
+    UnsupportedVersion(u32),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let message = match self {
+            ParseError::BadFieldCount {
+                line,
+                expected,
+                found,
+            } => tr!(
+                "line {}: expected at least {} comma-separated fields, got {}",
+                line,
+                expected,
+                found
+            ),
+            ParseError::BadCount {
+                line,
+                column,
+                value,
+            } => tr!(
+                "line {}, field {}: expected unsigned integer, got \"{}\"",
+                line,
+                column,
+                value
+            ),
+            ParseError::BadTimestamp {
+                line,
+                column,
+                value,
+            } => tr!(
+                "line {}, field {}: expected local datetime, got \"{}\"",
+                line,
+                column,
+                value
+            ),
+            ParseError::UnknownState {
+                line,
+                column,
+                value,
+            } => tr!(
+                "line {}, field {}: unknown streak state \"{}\"",
+                line,
+                column,
+                value
+            ),
+            ParseError::BadValue {
+                line,
+                column,
+                reason,
+                ..
+            } => tr!("line {}, field {}: {}", line, column, reason),
+            ParseError::LineTooLong { line, length } => tr!(
+                "line {}: {} bytes long, more than the {} a line can be",
+                line,
+                length,
+                MAX_LINE_LENGTH
+            ),
+            ParseError::BadVersion(version) => {
+                tr!("invalid state format version \"{}\"", version)
+            }
+            ParseError::UnsupportedVersion(version) => tr!(
+                "state file has format version {} but only up to {} is supported",
+                version,
+                STATE_VERSION
+            ),
+        };
+        write!(f, "{}", message)
+    }
+}
+
+/// Streak counts worth celebrating, unless configured otherwise
+const MILESTONES: [u32; 4] = [7, 30, 100, 365];
 
 enum StreakState {
     Done,
@@ -65,14 +372,265 @@ impl StreakState {
         }
     }
 
-    fn deserialize(string: &str) -> Result<Self, ParseError> {
+    fn deserialize(string: &str) -> Result<Self, String> {
         match string {
             "Done" => Ok(StreakState::Done),
             "Pending" => Ok(StreakState::Pending),
             "Expired" => Ok(StreakState::Expired),
             "New" => Ok(StreakState::New),
-            _ => Err(format!("unknown streak state: \"{}\"", string)),
+            _ => Err(tr!("unknown streak state: \"{}\"", string)),
+        }
+    }
+}
+
+/// How much a streak matters, putting it ahead of others when sorting by risk
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Priority {
+    Low,
+    Normal,
+    High,
+}
+
+impl Priority {
+    fn serialize(&self) -> &'static str {
+        match self {
+            Priority::Low => "low",
+            Priority::Normal => "normal",
+            Priority::High => "high",
+        }
+    }
+
+    fn deserialize(string: &str) -> Result<Self, String> {
+        match string {
+            "low" => Ok(Priority::Low),
+            "normal" => Ok(Priority::Normal),
+            "high" => Ok(Priority::High),
+            _ => Err(tr!(
+                "unknown priority \"{}\", expected low, normal or high",
+                string
+            )),
+        }
+    }
+}
+
+/// Parse a time like `18:30`
+fn parse_time_of_day(string: &str) -> Result<NaiveTime, String> {
+    NaiveTime::parse_from_str(string, "%H:%M")
+        .map_err(|_| tr!("expected a time like 18:30: \"{}\"", string))
+}
+
+/// Hour at which one day turns into the next, set from the config at startup so that a late
+/// night can still count towards the day before
+static DAY_START_HOUR: AtomicU32 = AtomicU32::new(0);
+
+/// The day a moment counts towards. Days are told apart by their date on the local calendar,
+/// never by how much time passed, which is an hour off across a DST change
+fn day_of(time: DateTime<Local>) -> NaiveDate {
+    day_starting_at(time, DAY_START_HOUR.load(Ordering::Relaxed))
+}
+
+/// The day a moment in any time zone counts towards when days start at the given hour
+fn day_starting_at<Tz: TimeZone>(time: DateTime<Tz>, hour: u32) -> NaiveDate {
+    (time.naive_local() - Duration::hours(hour.into())).date()
+}
+
+/// The day it is now
+fn current_day() -> NaiveDate {
+    day_of(Local::now())
+}
+
+/// A local time as a moment, the earlier one if the clocks went back over it and an hour later
+/// if they skipped it
+fn localize(naive: NaiveDateTime) -> DateTime<Local> {
+    Local
+        .from_local_datetime(&naive)
+        .earliest()
+        .or_else(|| {
+            Local
+                .from_local_datetime(&(naive + Duration::hours(1)))
+                .earliest()
+        })
+        .unwrap_or_else(|| Local.from_utc_datetime(&naive))
+}
+
+/// When a day starts
+fn start_of(date: NaiveDate) -> DateTime<Local> {
+    let start = Duration::hours(DAY_START_HOUR.load(Ordering::Relaxed).into());
+    localize(date.and_hms(0, 0, 0) + start)
+}
+
+/// The same time of day a number of days later, which is only that many 24 hours later when
+/// the clocks don't change in between
+fn shift_days(time: DateTime<Local>, days: i64) -> DateTime<Local> {
+    localize(time.naive_local() + Duration::days(days))
+}
+
+/// Parse when something happened, either a time like `22:15`, meaning the last time it was
+/// that time of day, or a full `2024-03-01 22:15`
+fn parse_past_time(string: &str) -> Result<DateTime<Local>, String> {
+    let now = Local::now();
+    let naive = match parse_time_of_day(string) {
+        Ok(time) => {
+            let today = now.naive_local().date().and_time(time);
+            if today > now.naive_local() {
+                today - Duration::days(1)
+            } else {
+                today
+            }
+        }
+        Err(_) => ["%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M"]
+            .iter()
+            .find_map(|format| NaiveDateTime::parse_from_str(string, format).ok())
+            .ok_or_else(|| {
+                tr!(
+                    "expected a time like 22:15 or 2024-03-01 22:15: \"{}\"",
+                    string
+                )
+            })?,
+    };
+    let time = localize(naive);
+    if time > now {
+        return Err(tr!("\"{}\" hasn't happened yet", string));
+    }
+    Ok(time)
+}
+
+/// Parse a date like `2024-03-01`, or `today` or `yesterday`
+fn parse_date(string: &str) -> Result<NaiveDate, String> {
+    let today = current_day();
+    match string {
+        "today" => Ok(today),
+        "yesterday" => Ok(today.pred()),
+        _ => string
+            .parse::<NaiveDate>()
+            .map_err(|_| tr!("expected a date like 2024-03-01: \"{}\"", string)),
+    }
+}
+
+fn parse_weekday(string: &str) -> Result<Weekday, String> {
+    string
+        .parse::<Weekday>()
+        .map_err(|_| tr!("unknown weekday: \"{}\"", string))
+}
+
+/// How often a streak needs hitting to stay alive
+#[derive(Clone)]
+enum Schedule {
+    Daily,
+    /// At least once in every so many days
+    Every(u32),
+    /// On each of these weekdays
+    Days(Vec<Weekday>),
+}
+
+impl Schedule {
+    /// Whether a due day passed without a hit between the last hit and today
+    fn missed(&self, last_hit: NaiveDate, today: NaiveDate) -> bool {
+        let days_between = today.signed_duration_since(last_hit).num_days();
+        match self {
+            Schedule::Daily => days_between > 1,
+            Schedule::Every(days) => days_between > i64::from(*days),
+            Schedule::Days(days) => (1..days_between)
+                .take(7)
+                .any(|offset| days.contains(&(last_hit + Duration::days(offset)).weekday())),
+        }
+    }
+
+    /// The day after the last hit that ends the streak if it passes without a hit
+    fn next_deadline(&self, last_hit: NaiveDate) -> NaiveDate {
+        (1..)
+            .map(|offset| last_hit + Duration::days(offset))
+            .find(|&date| self.missed(last_hit, date.succ()))
+            .unwrap()
+    }
+
+    /// Whether the date has to be hit on specifically, which is never the case when the streak
+    /// only needs hitting some time within a number of days
+    fn due_on(&self, date: NaiveDate) -> bool {
+        match self {
+            Schedule::Daily => true,
+            Schedule::Every(_) => false,
+            Schedule::Days(days) => days.contains(&date.weekday()),
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            Schedule::Daily => "daily".to_owned(),
+            Schedule::Every(days) => tr!("every {} days", days),
+            Schedule::Days(days) => days.iter().join(", "),
+        }
+    }
+
+    fn serialize(&self) -> String {
+        match self {
+            Schedule::Daily => "daily".to_owned(),
+            Schedule::Every(days) => format!("every:{}", days),
+            Schedule::Days(days) => format!(
+                "days:{}",
+                days.iter()
+                    .map(|day| day.to_string().to_lowercase())
+                    .join("+")
+            ),
+        }
+    }
+
+    fn deserialize(string: &str) -> Result<Self, String> {
+        match string.split_once(':') {
+            None if string == "daily" => Ok(Schedule::Daily),
+            Some(("every", days)) => days
+                .parse::<u32>()
+                .ok()
+                .filter(|&days| days > 0)
+                .map(Schedule::Every)
+                .ok_or_else(|| tr!("expected positive number of days: \"{}\"", days)),
+            Some(("days", days)) => days
+                .split('+')
+                .map(parse_weekday)
+                .collect::<Result<_, _>>()
+                .map(Schedule::Days),
+            _ => Err(tr!("unknown schedule: \"{}\"", string)),
+        }
+    }
+}
+
+/// A commitment to keep a streak alive for a fixed number of days
+#[derive(Clone, Copy)]
+struct Challenge {
+    start: NaiveDate,
+    days: u32,
+}
+
+impl Challenge {
+    fn end(&self) -> NaiveDate {
+        self.start + Duration::days(self.days.into())
+    }
+
+    fn days_left(&self, today: NaiveDate) -> i64 {
+        self.end().signed_duration_since(today).num_days().max(0)
+    }
+
+    fn serialize(&self) -> String {
+        format!("{}:{}", self.start, self.days)
+    }
+
+    fn deserialize(string: &str) -> Result<Self, String> {
+        let (start, days) = string
+            .split_once(':')
+            .ok_or_else(|| tr!("malformed challenge: \"{}\"", string))?;
+        let challenge = Self {
+            start: start
+                .parse::<NaiveDate>()
+                .map_err(|err| tr!("expected date for challenge start: {}", err))?,
+            days: days
+                .parse::<u32>()
+                .map_err(|err| tr!("expected unsigned integer for challenge days: {}", err))?,
+        };
+        // the end is worked out from these, which has to stay within the dates chrono supports
+        if challenge.start.year() > 9999 || challenge.days > 100 * 366 {
+            return Err(tr!("challenge \"{}\" goes on too long", string));
         }
+        Ok(challenge)
     }
 }
 
@@ -81,6 +639,28 @@ struct Streak {
     max_count: u32,
     last_hit: DateTime<Local>,
     state: StreakState,
+    schedule: Schedule,
+    /// Hits needed in a day for it to count
+    target: u32,
+    /// Hits so far on a day whose target isn't reached yet
+    progress: Option<(NaiveDate, u32)>,
+    tags: Vec<String>,
+    challenge: Option<Challenge>,
+    /// Unknown for streaks created before this was recorded
+    created: Option<DateTime<Local>>,
+    /// Earned tokens which each cover a missed day instead of the streak expiring
+    skips: u32,
+    /// Shown in front of the name in glyph mode
+    icon: Option<String>,
+    /// Time of day to be reminded at if the streak is still pending
+    reminder: Option<NaiveTime>,
+    /// The streak that should be done first each day
+    depends_on: Option<String>,
+    priority: Priority,
+    /// Weekdays off, which pass as though the streak was hit on them
+    rest: Vec<Weekday>,
+    /// A longer description of the habit
+    note: Option<String>,
 }
 
 impl Streak {
@@ -90,7 +670,100 @@ impl Streak {
             max_count: 0,
             last_hit: Local::now(),
             state: StreakState::New,
+            schedule: Schedule::Daily,
+            target: 1,
+            progress: None,
+            tags: Vec::new(),
+            challenge: None,
+            created: Some(Local::now()),
+            skips: 0,
+            icon: None,
+            reminder: None,
+            depends_on: None,
+            priority: Priority::Normal,
+            rest: Vec::new(),
+            note: None,
+        }
+    }
+
+    /// Whether the streak still needs hitting today to avoid missing a due day
+    fn due_today(&self, today: NaiveDate) -> bool {
+        let last_hit = day_of(self.last_hit);
+        let done_today = matches!(self.state, StreakState::Done) && last_hit == today;
+        let resting = self.rest.contains(&today.weekday());
+        !done_today && !resting && self.schedule.missed(last_hit, today.succ())
+    }
+
+    /// When the streak expires if it isn't hit before then
+    fn expires_at(&self, now: DateTime<Local>) -> Option<DateTime<Local>> {
+        let last_hit = day_of(self.last_hit);
+        let today = day_of(now);
+        let expiry = (1..=366)
+            .map(|offset| today + Duration::days(offset))
+            .find(|&date| self.schedule.missed(last_hit, date))?;
+        Some(start_of(expiry))
+    }
+
+    /// Cover missed days for as long as possible by moving the last hit forward to each day
+    /// that would otherwise end the streak, letting holidays pass and spending skip tokens on
+    /// the rest while there are enough of them
+    fn cover_missed_days(&mut self, name: &str, holidays: &HashSet<NaiveDate>, today: NaiveDate) {
+        let mut spent = 0;
+        while self.schedule.missed(day_of(self.last_hit), today) {
+            let deadline = self.schedule.next_deadline(day_of(self.last_hit));
+            if holidays.contains(&deadline) {
+                trace!("\"{}\" wasn't due on {}, a holiday", name, deadline);
+            } else if self.rest.contains(&deadline.weekday()) {
+                trace!("\"{}\" wasn't due on {}, a rest day", name, deadline);
+            } else if self.skips > 0 {
+                self.skips -= 1;
+                spent += 1;
+            } else {
+                break;
+            }
+            self.last_hit = shift_days(
+                self.last_hit,
+                deadline
+                    .signed_duration_since(day_of(self.last_hit))
+                    .num_days(),
+            );
+        }
+        if spent > 0 {
+            println!(
+                "{}",
+                tr!(
+                    "spent {} skip token{} to keep \"{}\" alive, {} left",
+                    spent,
+                    if spent == 1 { "" } else { "s" },
+                    name,
+                    self.skips
+                )
+            );
+        }
+    }
+
+    /// Count a hit towards the target of the day it was made on, returning whether it's now
+    /// reached
+    fn reach_target(&mut self, disambiguator: Option<&str>, day: NaiveDate) -> bool {
+        let hits = match self.progress {
+            Some((date, hits)) if date == day => hits + 1,
+            _ => 1,
+        };
+        if hits >= self.target {
+            self.progress = None;
+            return true;
         }
+        self.progress = Some((day, hits));
+        println!(
+            "{}",
+            tr!(
+                "{}{}/{} hits today",
+                disambiguator.unwrap_or_default(),
+                hits,
+                self.target
+            )
+        );
+        false
     }
 
     fn update_count<F: FnOnce(u32) -> u32>(&mut self, action: F) {
@@ -98,25 +771,75 @@ impl Streak {
         self.max_count = self.max_count.max(self.current_count);
     }
 
-    /// Returns the new streak count if it updated
-    fn hit(&mut self, disambiguator: Option<String>) -> Option<u32> {
+    /// Hit the streak at the given time, now unless it's logged late, returning the new count
+    /// if it updated
+    fn hit(&mut self, disambiguator: Option<String>, time: DateTime<Local>) -> Option<u32> {
+        let prefix = disambiguator.as_deref().unwrap_or_default();
+        if !matches!(self.state, StreakState::New) && time < self.last_hit {
+            eprintln!(
+                "{}",
+                tr!(
+                    "{}already hit later, at {}",
+                    prefix,
+                    self.last_hit.format("%Y-%m-%d %H:%M")
+                )
+            );
+            return None;
+        }
+        if matches!(self.state, StreakState::Pending) {
+            let last_hit = day_of(self.last_hit);
+            if day_of(time) <= last_hit {
+                eprintln!("{}", tr!("{}already counted a hit on {}", prefix, last_hit));
+                return None;
+            }
+            if self.schedule.missed(last_hit, day_of(time)) {
+                // --at past the deadline the previous hit set
+                eprintln!(
+                    "{}",
+                    tr!(
+                        "{}would have expired by {}, as it was last hit on {}",
+                        prefix,
+                        day_of(time),
+                        last_hit
+                    )
+                );
+                return None;
+            }
+        }
+        if matches!(self.state, StreakState::Expired) && day_of(time) < current_day() {
+            // the day it was hit on may be the one it expired for missing
+            eprintln!(
+                "{}",
+                tr!(
+                    "{}expired since, `streaks repair` can count a hit on {}",
+                    prefix,
+                    day_of(time)
+                )
+            );
+            return None;
+        }
+        if !matches!(self.state, StreakState::Done)
+            && !self.reach_target(disambiguator.as_deref(), day_of(time))
+        {
+            return None;
+        }
         match self.state {
             StreakState::Done => {
                 if let Some(s) = disambiguator {
                     eprint!("{}", s);
                 }
-                eprintln!("streak already completed today");
+                eprintln!("{}", tr!("streak already completed today"));
                 None
             }
             StreakState::Expired | StreakState::New => {
                 self.state = StreakState::Done;
-                self.last_hit = Local::now();
+                self.last_hit = time;
                 self.update_count(|_old_count| 1);
                 Some(self.current_count)
             }
             StreakState::Pending => {
                 self.state = StreakState::Done;
-                self.last_hit = Local::now();
+                self.last_hit = time;
                 self.update_count(|old_count| old_count + 1);
                 Some(self.current_count)
             }
@@ -125,342 +848,3944 @@ impl Streak {
 
     fn serialize(&self) -> String {
         format!(
-            "{},{},{},{}",
+            "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
             self.current_count,
             self.max_count,
             self.last_hit,
-            self.state.serialize()
+            self.state.serialize(),
+            self.schedule.serialize(),
+            self.target,
+            self.progress
+                .map(|(date, hits)| format!("{}:{}", date, hits))
+                .unwrap_or_default(),
+            self.tags.join(" "),
+            self.challenge
+                .map(|challenge| challenge.serialize())
+                .unwrap_or_default(),
+            self.created
+                .map(|created| created.to_string())
+                .unwrap_or_default(),
+            self.skips,
+            self.icon.as_deref().unwrap_or_default(),
+            self.reminder
+                .map(|reminder| reminder.format("%H:%M").to_string())
+                .unwrap_or_default(),
+            self.depends_on.as_deref().unwrap_or_default(),
+            self.priority.serialize(),
+            self.rest
+                .iter()
+                .map(|day| day.to_string().to_lowercase())
+                .join("+"),
+            self.note.as_deref().unwrap_or_default()
         )
     }
 
-    fn deserialize(values: &[&str]) -> Result<Self, ParseError> {
-        match values.len() {
-            4 => Ok(Self {
-                current_count: values[0].parse::<u32>().map_err(|err| {
-                    format!("expected unsigned integer for current_count: {}", err)
-                })?,
-                max_count: values[1]
-                    .parse::<u32>()
-                    .map_err(|err| format!("expected unsigned integer for max_count: {}", err))?,
-                last_hit: values[2]
-                    .parse::<DateTime<Local>>()
-                    .map_err(|err| format!("expected local datetime for last_hit: {}", err))?,
-                state: StreakState::deserialize(values[3])?,
-            }),
-            _ => Err(format!(
-                "expected 4 comma-separated values for a streak description, got {}: \"{}\"",
-                values.len(),
-                values.join(",")
-            )),
+    /// Parse the fields of a streak, the first of them being the given field of the line
+    fn deserialize(values: &[&str], line: usize, first_column: usize) -> Result<Self, ParseError> {
+        if values.len() < 17 {
+            return Err(ParseError::BadFieldCount {
+                line,
+                expected: first_column + 16,
+                found: first_column - 1 + values.len(),
+            });
+        }
+        let column = |i| first_column + i;
+        let count = |i: usize| {
+            values[i].parse::<u32>().map_err(|_| ParseError::BadCount {
+                line,
+                column: column(i),
+                value: values[i].to_owned(),
+            })
+        };
+        let timestamp = |i: usize| {
+            parse_timestamp(values[i]).ok_or_else(|| ParseError::BadTimestamp {
+                line,
+                column: column(i),
+                value: values[i].to_owned(),
+            })
+        };
+        let bad_value = |i: usize, reason| ParseError::BadValue {
+            line,
+            column: column(i),
+            value: values[i].to_owned(),
+            reason,
+        };
+        Ok(Self {
+            current_count: count(0)?,
+            max_count: count(1)?,
+            last_hit: timestamp(2)?,
+            state: StreakState::deserialize(values[3]).map_err(|_| ParseError::UnknownState {
+                line,
+                column: column(3),
+                value: values[3].to_owned(),
+            })?,
+            schedule: Schedule::deserialize(values[4]).map_err(|reason| bad_value(4, reason))?,
+            target: count(5)?,
+            progress: match values[6].split_once(':') {
+                _ if values[6].is_empty() => None,
+                Some((date, hits)) => match (date.parse::<NaiveDate>(), hits.parse::<u32>()) {
+                    (Ok(date), Ok(hits)) => Some((date, hits)),
+                    _ => return Err(bad_value(6, tr!("expected <date>:<hits> for progress"))),
+                },
+                None => return Err(bad_value(6, tr!("expected <date>:<hits> for progress"))),
+            },
+            tags: values[7].split_whitespace().map(str::to_owned).collect(),
+            challenge: match values[8] {
+                "" => None,
+                challenge => {
+                    Some(Challenge::deserialize(challenge).map_err(|reason| bad_value(8, reason))?)
+                }
+            },
+            created: match values[9] {
+                "" => None,
+                _ => Some(timestamp(9)?),
+            },
+            skips: count(10)?,
+            icon: Some(values[11].to_owned()).filter(|icon| !icon.is_empty()),
+            reminder: match values[12] {
+                "" => None,
+                reminder => {
+                    Some(parse_time_of_day(reminder).map_err(|reason| bad_value(12, reason))?)
+                }
+            },
+            depends_on: Some(values[13].to_owned()).filter(|name| !name.is_empty()),
+            priority: Priority::deserialize(values[14]).map_err(|reason| bad_value(14, reason))?,
+            rest: values[15]
+                .split('+')
+                .filter(|day| !day.is_empty())
+                .map(parse_weekday)
+                .collect::<Result<_, _>>()
+                .map_err(|reason| bad_value(15, reason))?,
+            // the note is last so it can contain commas
+            note: Some(values[16..].join(",")).filter(|note| !note.is_empty()),
+        })
+    }
+}
+
+/// Longest line of the state file that's parsed, far longer than any real one, so damage like a
+/// file of zeros doesn't end up in huge streak fields and error messages
+const MAX_LINE_LENGTH: usize = 64 * 1024;
+
+fn check_length(text: &str, line: usize) -> Result<(), ParseError> {
+    if text.len() > MAX_LINE_LENGTH {
+        Err(ParseError::LineTooLong {
+            line,
+            length: text.len(),
+        })
+    } else {
+        Ok(())
+    }
+}
+
+/// Parse a timestamp of the state file, within years that dates can safely be worked out
+/// around without overflowing
+fn parse_timestamp(string: &str) -> Option<DateTime<Local>> {
+    string
+        .parse::<DateTime<Local>>()
+        .ok()
+        .filter(|time| (1..=9999).contains(&time.year()))
+}
+
+/// Version of the state file format written by this build
+const STATE_VERSION: u32 = 16;
+const STATE_HEADER: &str = "# streaks state v";
+
+/// Add a field to each streak line in front of the note, which has to stay last
+fn insert_before_note(body: &str, fields_before: usize, value: &str) -> String {
+    body.lines()
+        .map(|line| {
+            let fields: Vec<_> = line.splitn(fields_before + 1, ',').collect();
+            let (fields, note) = fields.split_at(fields.len().min(fields_before));
+            format!("{},{},{}", fields.join(","), value, note.join(","))
+        })
+        .join("\n")
+}
+
+/// Like `insert_before_note` for files with sections, where removed streaks have their removal
+/// time as an extra field and the aliases have no streak fields at all
+fn insert_in_sections(body: &str, fields_before: usize, value: &str) -> String {
+    let mut section = 0;
+    body.lines()
+        .map(|line| {
+            if line.is_empty() {
+                section += 1;
+                return String::new();
+            }
+            match section {
+                0 => insert_before_note(line, fields_before, value),
+                1 => insert_before_note(line, fields_before + 1, value),
+                _ => line.to_owned(),
+            }
+        })
+        .join("\n")
+}
+
+type Migration = fn(&str) -> Result<String, ParseError>;
+
+/// Upgrades of the state file body, the one at index `i` going from version `i + 1` to `i + 2`
+const MIGRATIONS: [Migration; 15] = [
+    // version 1 had no header but the same streak lines
+    |body| Ok(body.to_owned()),
+    // version 3 added the schedule, target, progress towards it and tags
+    |body| {
+        Ok(body
+            .lines()
+            .map(|line| format!("{},daily,1,,", line))
+            .join("\n"))
+    },
+    // version 4 added a note
+    |body| Ok(body.lines().map(|line| format!("{},", line)).join("\n")),
+    // version 5 added a challenge before the note
+    |body| Ok(insert_before_note(body, 9, "")),
+    // version 6 added the creation time before the note
+    |body| Ok(insert_before_note(body, 10, "")),
+    // version 7 added the balance of skip tokens before the note
+    |body| Ok(insert_before_note(body, 11, "0")),
+    // version 8 added removed streaks after a blank line, which older versions would choke on
+    |body| Ok(body.to_owned()),
+    // version 9 added aliases after another blank line
+    |body| Ok(body.to_owned()),
+    // version 10 added an icon before the note
+    |body| Ok(insert_in_sections(body, 12, "")),
+    // version 11 added a reminder time before the note
+    |body| Ok(insert_in_sections(body, 13, "")),
+    // version 12 added the streak depended on before the note
+    |body| Ok(insert_in_sections(body, 14, "")),
+    // version 13 added a section of groups, which older versions would take for aliases
+    |body| Ok(body.to_owned()),
+    // version 14 added a priority before the note
+    |body| Ok(insert_in_sections(body, 15, "normal")),
+    // version 15 added a section with the order pinned streaks are listed in
+    |body| Ok(body.to_owned()),
+    // version 16 added rest days before the note
+    |body| Ok(insert_in_sections(body, 16, "")),
+];
+
+/// Split off the version header and bring the rest of the file up to the current format
+fn migrate(string: &str) -> Result<String, ParseError> {
+    let (version, body) = match string.strip_prefix(STATE_HEADER) {
+        Some(rest) => {
+            let (version, body) = rest.split_once('\n').unwrap_or((rest, ""));
+            let version = version
+                .trim()
+                .parse::<u32>()
+                .map_err(|_| ParseError::BadVersion(version.trim().to_owned()))?;
+            (version, body)
+        }
+        None => (1, string),
+    };
+    if version == 0 || version > STATE_VERSION {
+        return Err(ParseError::UnsupportedVersion(version));
+    }
+    if version < STATE_VERSION {
+        debug!(
+            "migrating state file from version {} to {}",
+            version, STATE_VERSION
+        );
+    }
+    let mut body = body.to_owned();
+    for migration in MIGRATIONS[version as usize - 1..].iter() {
+        body = migration(&body)?;
+    }
+    Ok(body)
+}
+
+/// A removed streak kept around so it can be undeleted
+struct Trashed {
+    name: String,
+    removed: DateTime<Local>,
+    streak: Streak,
+}
+
+impl Trashed {
+    fn deserialize(text: &str, line: usize) -> Result<Self, ParseError> {
+        // removed streaks have their name and removal time before the streak fields
+        let values: Vec<_> = text.split(',').collect();
+        if values.len() < 2 {
+            return Err(ParseError::BadFieldCount {
+                line,
+                expected: 19,
+                found: values.len(),
+            });
         }
+        validate_name(values[0]).map_err(|reason| ParseError::BadValue {
+            line,
+            column: 1,
+            value: values[0].to_owned(),
+            reason,
+        })?;
+        Ok(Self {
+            name: values[0].to_owned(),
+            removed: parse_timestamp(values[1]).ok_or_else(|| ParseError::BadTimestamp {
+                line,
+                column: 2,
+                value: values[1].to_owned(),
+            })?,
+            streak: Streak::deserialize(&values[2..], line, 3)?,
+        })
     }
 }
 
 struct State {
     streaks: HashMap<String, Streak>,
+    /// Removed streaks, oldest first
+    trash: Vec<Trashed>,
+    /// Short names standing in for the streaks they map to
+    aliases: HashMap<String, String>,
+    /// Named sets of streaks, selected with `--group`
+    groups: HashMap<String, Vec<String>>,
+    /// Pinned streaks, listed first in this order before the rest by name
+    order: Vec<String>,
+    /// Lines that couldn't be parsed with the section they were in, written back as they were
+    /// so that nothing is lost
+    unreadable: Vec<(usize, String)>,
+    /// New history entries to be appended when the state is written
+    events: Vec<history::Entry>,
+    /// New audit records to be appended when the state is written
+    audit: Vec<audit::Record>,
+    /// Old and new names of streaks whose history follows them when the state is written
+    renames: Vec<(String, String)>,
 }
 
 impl State {
+    /// The stored name of a streak, which can differ in case from the given one unless that's
+    /// turned off or more than one streak would match
+    fn lookup(&self, name: &str) -> Option<String> {
+        if self.streaks.contains_key(name) {
+            return Some(name.to_owned());
+        }
+        if !config::read_config().ignore_case {
+            return None;
+        }
+        let lowercase = name.to_lowercase();
+        match self
+            .streaks
+            .keys()
+            .filter(|key| key.to_lowercase() == lowercase)
+            .collect::<Vec<_>>()
+            .as_slice()
+        {
+            [key] => Some((*key).clone()),
+            _ => None,
+        }
+    }
+
+    /// The streak a name or alias stands for, without guessing at loose matches
+    fn lookup_or_alias(&self, name: &str) -> Option<String> {
+        self.lookup(name).or_else(|| {
+            self.aliases
+                .get(name)
+                .filter(|target| self.streaks.contains_key(*target))
+                .cloned()
+        })
+    }
+
+    /// The streak meant by a name, offering the loose matches if there's none by that name, and
+    /// taking the only one without asking if `fuzzy`
+    fn find_loosely(&mut self, name: &str, fuzzy: bool) -> Option<String> {
+        if let Some(found) = self.lookup(name) {
+            return Some(found);
+        }
+        let candidates = self.ranked_matches(name);
+        let chosen = match candidates.as_slice() {
+            [] => None,
+            [candidate] if fuzzy => {
+                println!("{}", tr!("taking \"{}\" for \"{}\"", candidate, name));
+                Some(candidate)
+            }
+            [candidate] => yes_or_no(&tr!("did you mean \"{}\"?", candidate)).then_some(candidate),
+            _ => choose(
+                &tr!("which streak did you mean by \"{}\"?", name),
+                &candidates,
+            )
+            .map(|i| &candidates[i]),
+        };
+        match chosen {
+            Some(chosen) => Some(chosen.clone()),
+            None if candidates.is_empty() => {
+                self.not_found(name);
+                None
+            }
+            None => {
+                eprintln!("{}", tr!("streak \"{}\" not found", name));
+                NOT_FOUND.store(true, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+
     fn not_found(&mut self, name: &str) {
-        eprint!("streak \"{}\" not found", name);
-        if let Some(alt_name) = self.streaks.keys().find(|n| close_match(n, name)) {
-            eprintln!(", maybe you meant \"{}\"?", alt_name);
+        NOT_FOUND.store(true, Ordering::Relaxed);
+        eprint!("{}", tr!("streak \"{}\" not found", name));
+        if let Some(alt_name) = self.ranked_matches(name).first() {
+            eprintln!("{}", tr!(", maybe you meant \"{}\"?", alt_name));
         } else {
             eprintln!();
         }
     }
 
+    /// Streaks last hit on a later day than today, which only happens when the clock went back
+    fn hit_in_future(&self, now: DateTime<Local>) -> Vec<String> {
+        self.streaks
+            .iter()
+            .filter(|(_, streak)| day_of(streak.last_hit) > day_of(now))
+            .map(|(name, _)| name.clone())
+            .sorted()
+            .collect()
+    }
+
     fn update(&mut self) {
+        self.purge_trash();
         let now = Local::now();
-        for (_, streak) in self.streaks.iter_mut() {
-            let days_between = now.num_days_from_ce() - streak.last_hit.num_days_from_ce();
+        if let Some(last_run) = record_run(now) {
+            if now + Duration::minutes(CLOCK_TOLERANCE_MINUTES) < last_run {
+                eprintln!(
+                    "{}",
+                    tr!(
+                        "warning: the clock is behind the last run at {}, it may have been set back",
+                        last_run.format("%Y-%m-%d %H:%M")
+                    )
+                );
+            }
+        }
+        let skewed = self.hit_in_future(now);
+        if !skewed.is_empty() {
+            eprintln!(
+                "{}",
+                tr!(
+                    "warning: \"{}\" was last hit after today, leaving it as it is until the clock is right or `streaks update` resets it",
+                    skewed.join("\", \"")
+                )
+            );
+        }
+        let holidays = config::read_config().holidays.all();
+        for (name, streak) in self.streaks.iter_mut() {
+            if skewed.contains(name) {
+                continue;
+            }
+            if !matches!(streak.state, StreakState::Expired | StreakState::New) {
+                streak.cover_missed_days(name, &holidays, day_of(now));
+            }
+            let days_between = day_of(now)
+                .signed_duration_since(day_of(streak.last_hit))
+                .num_days();
+            let missed = streak.schedule.missed(day_of(streak.last_hit), day_of(now));
+            let before = streak.state.serialize();
             match days_between {
                 0 => (),
-                1 => {
+                n if n > 0 && !missed => {
                     streak.state = StreakState::Pending;
                 }
-                n if n > 1 => {
+                n if n > 0 => {
+                    if !matches!(streak.state, StreakState::Expired | StreakState::New) {
+                        self.events
+                            .push(history::Entry::new(name, history::Event::Expired));
+                    }
                     streak.state = StreakState::Expired;
                     streak.update_count(|_old_count| 0);
                 }
-                _ => {
-                    eprintln!("corrupted time state");
-                    streak.state = StreakState::Pending;
-                    streak.update_count(|_old_count| 0);
-                }
+                // streaks last hit after today were skipped above
+                _ => (),
             };
+            if before != streak.state.serialize() {
+                debug!(
+                    "\"{}\" went from {} to {}: last hit {}, {} days ago, schedule {}, {}",
+                    name,
+                    before,
+                    streak.state.serialize(),
+                    streak.last_hit.format("%Y-%m-%d %H:%M"),
+                    days_between,
+                    streak.schedule.serialize(),
+                    if missed {
+                        "a due day was missed"
+                    } else {
+                        "no due day was missed"
+                    }
+                );
+            } else {
+                trace!(
+                    "\"{}\" stays {}, last hit {} days ago",
+                    name,
+                    before,
+                    days_between
+                );
+            }
+            if let Some(challenge) = streak.challenge {
+                let today = day_of(now);
+                // the challenge starts as though the streak was hit the day before
+                let since = day_of(streak.last_hit).max(challenge.start.pred());
+                if streak.schedule.missed(since, today) {
+                    println!(
+                        "{}",
+                        tr!(
+                            "challenge \"{}\" failed with {} of {} days left, the streak expired",
+                            name,
+                            challenge.days_left(today),
+                            challenge.days
+                        )
+                    );
+                    streak.challenge = None;
+                } else if today >= challenge.end() {
+                    println!(
+                        "{}",
+                        tr!(
+                            "challenge \"{}\" completed: kept up for {} days, now at {} (max {})",
+                            name,
+                            challenge.days,
+                            streak.current_count,
+                            streak.max_count
+                        )
+                    );
+                    streak.challenge = None;
+                }
+            }
         }
     }
 
-    fn add_streaks(&mut self, names: &[String]) {
-        // TODO: guard this more
-        for name in names.iter() {
-            if self.streaks.insert(name.clone(), Streak::new()).is_some() {
-                eprintln!("warning: reset old version of streak \"{}\"", name);
+    /// Offer to start over the streaks last hit after today, keeping their best counts
+    fn reset_hit_in_future(&mut self) {
+        let now = Local::now();
+        let skewed = self.hit_in_future(now);
+        if skewed.is_empty() {
+            return;
+        }
+        let prompt = tr!(
+            "reset the counts of \"{}\" as though they were never hit?",
+            skewed.join("\", \"")
+        );
+        if !yes_or_no(&prompt) {
+            return;
+        }
+        let reason = tr!("last hit after today, the clock went back");
+        for name in skewed {
+            self.reset_streak(&name, false, Some(reason.clone()));
+            // otherwise it would still look hit after today
+            if let Some(streak) = self.streaks.get_mut(&name) {
+                streak.last_hit = now;
             }
         }
     }
 
-    fn remove_streaks(&mut self, names: &[String]) {
-        for name in names.iter() {
-            if self.streaks.remove(name).is_none() {
+    /// Set or with an empty string clear the note of a streak, returning whether it was found
+    fn describe_streak(&mut self, name: &str, note: &str) -> bool {
+        match self.streaks.get_mut(name) {
+            Some(streak) => {
+                streak.note = Some(note.to_owned()).filter(|note| !note.is_empty());
+                true
+            }
+            None => {
                 self.not_found(name);
+                false
             }
         }
     }
 
-    fn rename_streak(&mut self, name: &str, new_name: &str) {
-        if let Some(streak) = self.streaks.remove(name) {
-            self.streaks.insert(new_name.to_owned(), streak);
-        } else {
-            self.not_found(name);
-        }
-    }
-
-    /// Returns the name of the updated streak and the new count
-    fn hit_streak(&mut self, name: &str, one_of_many: bool) -> Option<(String, u32)> {
-        let disambiguator = one_of_many.then(|| format!("\"{name}\": "));
-        if let Some(streak) = self.streaks.get_mut(name) {
-            return streak.hit(disambiguator).map(|n| (name.to_owned(), n));
-        }
-        if let Some((name, streak)) = self.streaks.iter_mut().find(|kv| close_match(kv.0, name)) {
-            if yes_or_no(&format!("hit \"{name}\"?")) {
-                return streak.hit(disambiguator).map(|n| (name.to_owned(), n));
+    fn start_challenge(&mut self, name: &str, days: u32) -> bool {
+        match self.streaks.get_mut(name) {
+            Some(streak) => {
+                streak.challenge = Some(Challenge {
+                    start: current_day(),
+                    days,
+                });
+                true
             }
-        }
-        if let Some((name, streak)) = self.streaks.iter_mut().find(|s| s.0.starts_with(name)) {
-            if yes_or_no(&format!("hit \"{name}\"?")) {
-                return streak.hit(disambiguator).map(|n| (name.to_owned(), n));
+            None => {
+                self.not_found(name);
+                false
             }
         }
-        yes_or_no(&format!("create new streak \"{name}\"?"))
-            .then(|| {
-                self.streaks
-                    .entry(name.to_owned())
-                    .or_insert_with(Streak::new)
-                    .hit(disambiguator)
-                    .map(|n| (name.to_owned(), n))
-            })
-            .flatten()
     }
 
-    fn serialize(&self) -> String {
-        let mut lines = Vec::new();
-        for (name, streak) in self.streaks.iter().sorted_by_key(|pair| pair.0) {
-            lines.push(format!("{},{}", name, streak.serialize()));
-        }
-        lines.join("\n")
+    /// Returns the names which were valid and so added
+    fn add_streaks(&mut self, names: &[String], template: &templates::Template) -> Vec<String> {
+        let mut added = Vec::new();
+        for name in names.iter() {
+            if let Err(err) = validate_name(name) {
+                eprintln!("{}", err);
+                continue;
+            }
+            if let Some((similar, quality)) = self.close_match(name) {
+                eprintln!(
+                    "{}",
+                    if quality == NameMatch::IgnoringCase {
+                        tr!(
+                            "warning: \"{}\" only differs in case from existing streak \"{}\"",
+                            name,
+                            similar
+                        )
+                    } else {
+                        tr!(
+                            "warning: \"{}\" is a lot like existing streak \"{}\"",
+                            name,
+                            similar
+                        )
+                    }
+                );
+                // scripts adding streaks get them added as before, only people are asked
+                let prompt = tr!(
+                    "did you mean \"{}\" instead of adding \"{}\"?",
+                    similar,
+                    name
+                );
+                if assumed_answer().is_none() && yes_or_no(&prompt) {
+                    continue;
+                }
+            }
+            if self
+                .streaks
+                .insert(name.clone(), template.streak())
+                .is_some()
+            {
+                eprintln!(
+                    "{}",
+                    tr!("warning: reset old version of streak \"{}\"", name)
+                );
+            }
+            self.audit
+                .push(audit::Record::new("add", name, String::new()));
+            added.push(name.clone());
+        }
+        added
     }
 
-    fn deserialize(string: &str) -> Result<Self, ParseError> {
-        let mut streaks = HashMap::new();
-        for (line_number, line) in string.lines().enumerate() {
-            let values: Vec<_> = line.split(',').collect();
-            if values.len() < 2 {
-                return Err(format!(
-                    "expected name and state for streak on line {}: \"{}\"",
-                    line_number + 1,
-                    line
+    /// Returns the names which were found and confirmed and so removed
+    fn remove_streaks(&mut self, names: &[String], force: bool, fuzzy: bool) -> Vec<String> {
+        let mut removed = Vec::new();
+        for name in names.iter() {
+            let name = match self.find_loosely(name, fuzzy) {
+                Some(name) => name,
+                None => continue,
+            };
+            let streak = &self.streaks[&name];
+            let streak_count = (streak.current_count, streak.max_count);
+            let prompt = tr!(
+                "remove streak \"{}\" (at {}, max {})?",
+                name,
+                streak_count.0,
+                streak_count.1
+            );
+            if force || yes_or_no(&prompt) {
+                self.trash.push(Trashed {
+                    name: name.clone(),
+                    removed: Local::now(),
+                    streak: self.streaks.remove(&name).unwrap(),
+                });
+                self.audit.push(audit::Record::new(
+                    "remove",
+                    &name,
+                    format!("was at {}, max {}", streak_count.0, streak_count.1),
                 ));
+                removed.push(name);
             }
-            streaks.insert(
-                values[0].to_owned(),
-                Streak::deserialize(&values[1..]).map_err(|err| {
-                    format!(
-                        "failed to parse streak on line {}: {}",
-                        line_number + 1,
-                        err
+        }
+        self.purge_trash();
+        removed
+    }
+
+    /// Start a streak over on purpose, returning whether it was found
+    fn reset_streak(&mut self, name: &str, max: bool, reason: Option<String>) -> bool {
+        let streak = match self.streaks.get_mut(name) {
+            Some(streak) => streak,
+            None => {
+                self.not_found(name);
+                return false;
+            }
+        };
+        streak.current_count = 0;
+        if max {
+            streak.max_count = 0;
+        }
+        streak.state = StreakState::New;
+        streak.progress = None;
+        self.audit.push(audit::Record::new(
+            "reset",
+            name,
+            reason.clone().unwrap_or_default(),
+        ));
+        self.events
+            .push(history::Entry::new(name, history::Event::Reset(reason)));
+        true
+    }
+
+    /// Set or clear the time to be reminded of a streak at, returning whether it was found
+    fn set_reminder(&mut self, name: &str, reminder: Option<NaiveTime>) -> bool {
+        match self.streaks.get_mut(name) {
+            Some(streak) => {
+                streak.reminder = reminder;
+                true
+            }
+            None => {
+                self.not_found(name);
+                false
+            }
+        }
+    }
+
+    /// Record a reminder for each streak still due today once its reminder time has passed,
+    /// unless one was already sent or it was snoozed today, returning the names of the streaks
+    /// reminded of
+    fn send_reminders(&mut self, entries: &[history::Entry], now: DateTime<Local>) -> Vec<String> {
+        let today = day_of(now);
+        let mut reminded = history::names_with_event(entries, "remind", today);
+        reminded.extend(history::names_with_event(entries, "snooze", today));
+        let due: Vec<_> = self
+            .streaks
+            .iter()
+            .filter(|(name, streak)| {
+                matches!(streak.state, StreakState::Pending)
+                    && streak.due_today(today)
+                    && streak
+                        .reminder
+                        .is_some_and(|reminder| reminder <= now.time())
+                    && !reminded.contains(name.as_str())
+            })
+            .map(|(name, _streak)| name.clone())
+            .sorted()
+            .collect();
+        for name in due.iter() {
+            self.events
+                .push(history::Entry::new(name, history::Event::Reminded));
+        }
+        due
+    }
+
+    /// Let a day go without breaking a streak by moving its last hit forward to it, as a skip
+    /// token would, returning its name if it was skipped
+    fn skip_day(&mut self, name: &str, date: NaiveDate) -> Option<String> {
+        let name = match self.lookup(name) {
+            Some(name) => name,
+            None => {
+                self.not_found(name);
+                return None;
+            }
+        };
+        let today = current_day();
+        let streak = self.streaks.get_mut(&name).unwrap();
+        let last_hit = day_of(streak.last_hit);
+        if date > today {
+            eprintln!("{}", tr!("can't skip a day in the future"));
+            return None;
+        }
+        if matches!(streak.state, StreakState::Expired | StreakState::New) {
+            eprintln!("{}", tr!("\"{}\" has no streak going to keep", name));
+            return None;
+        }
+        if date <= last_hit {
+            eprintln!("{}", tr!("\"{}\" is already covered on {}", name, date));
+            return None;
+        }
+        // a skip only covers its own day, not any missed before it
+        if streak.schedule.missed(last_hit, date) {
+            eprintln!(
+                "{}",
+                tr!(
+                    "\"{}\" already missed a day before {}, skip that first",
+                    name,
+                    date
+                )
+            );
+            return None;
+        }
+        streak.last_hit = shift_days(
+            streak.last_hit,
+            date.signed_duration_since(last_hit).num_days(),
+        );
+        self.events
+            .push(history::Entry::on(&name, date, history::Event::Skipped));
+        Some(name)
+    }
+
+    /// Record a forgotten hit on a past day after confirming, and recompute the streak from the
+    /// history with it, returning the new count if it was repaired
+    fn repair(&mut self, name: &str, date: NaiveDate, entries: &[history::Entry]) -> Option<u32> {
+        let streak = match self.streaks.get_mut(name) {
+            Some(streak) => streak,
+            None => {
+                self.not_found(name);
+                return None;
+            }
+        };
+        let today = current_day();
+        if date >= today {
+            eprintln!("{}", tr!("only past days can be repaired, hit it instead"));
+            return None;
+        }
+        let covered = |entry: &history::Entry| {
+            entry.name == name
+                && matches!(entry.event, history::Event::Hit | history::Event::Skipped)
+        };
+        if entries
+            .iter()
+            .any(|entry| covered(entry) && day_of(entry.time) == date)
+        {
+            eprintln!("{}", tr!("\"{}\" is already covered on {}", name, date));
+            return None;
+        }
+        let repaired = history::Entry::on(name, date, history::Event::Hit);
+        let mut entries = entries.to_vec();
+        entries.push(repaired.clone());
+        // replay the whole history rather than counting back from today, so that holidays, rest
+        // days and skip tokens keep the run going just as they did when it was live
+        let config = config::read_config();
+        let holidays = config.holidays.all();
+        let run = history::run_on(&entries, name, streak, &holidays, config.skip_every, today);
+        let (last_covered, count) = match run {
+            Some(run) => run,
+            None => {
+                eprintln!(
+                    "{}",
+                    tr!(
+                        "\"{}\" would still be broken by another missed day since {}",
+                        name,
+                        date
                     )
-                })?,
+                );
+                return None;
+            }
+        };
+        let prompt = tr!(
+            "mark \"{}\" as hit on {}, making it {} instead of {}?",
+            name,
+            date,
+            count,
+            streak.current_count
+        );
+        if !yes_or_no(&prompt) {
+            return None;
+        }
+        streak.update_count(|_old_count| count);
+        if last_covered > day_of(streak.last_hit) {
+            streak.last_hit = shift_days(
+                repaired.time,
+                last_covered.signed_duration_since(date).num_days(),
             );
         }
-        Ok(Self { streaks })
+        streak.state = if last_covered == today {
+            StreakState::Done
+        } else {
+            StreakState::Pending
+        };
+        self.events.push(repaired);
+        self.audit.push(audit::Record::new(
+            "repair",
+            name,
+            format!("hit on {}, now at {}", date, count),
+        ));
+        Some(count)
+    }
+
+    /// Hold back reminders of a streak still due today until tomorrow, returning its name if it
+    /// was snoozed
+    fn snooze(&mut self, name: &str) -> Option<String> {
+        let name = match self.lookup(name) {
+            Some(name) => name,
+            None => {
+                self.not_found(name);
+                return None;
+            }
+        };
+        let today = current_day();
+        if !self.streaks[&name].due_today(today) {
+            eprintln!("{}", tr!("\"{}\" isn't due today", name));
+            return None;
+        }
+        self.events
+            .push(history::Entry::new(&name, history::Event::Snoozed));
+        Some(name)
+    }
+
+    /// Overwrite the counts of a streak after confirming, returning whether they were changed
+    fn set_count(&mut self, name: &str, count: u32, max: Option<u32>) -> bool {
+        let streak = match self.streaks.get_mut(name) {
+            Some(streak) => streak,
+            None => {
+                self.not_found(name);
+                return false;
+            }
+        };
+        let max = max.unwrap_or_else(|| streak.max_count.max(count));
+        if max < count {
+            eprintln!(
+                "{}",
+                tr!("max count {} can't be less than the count {}", max, count)
+            );
+            return false;
+        }
+        let prompt = tr!(
+            "set \"{}\" from {} (max {}) to {} (max {})?",
+            name,
+            streak.current_count,
+            streak.max_count,
+            count,
+            max
+        );
+        if !yes_or_no(&prompt) {
+            return false;
+        }
+        let before = (streak.current_count, streak.max_count);
+        streak.current_count = count;
+        streak.max_count = max;
+        if count > 0 && matches!(streak.state, StreakState::Expired | StreakState::New) {
+            // carry on from the count as though it was last hit yesterday
+            streak.state = StreakState::Pending;
+            streak.last_hit = shift_days(Local::now(), -1);
+        }
+        self.audit
+            .push(audit::Record::set(name, before, (count, max)));
+        true
+    }
+
+    /// Bring back the most recently removed streak with this name, returning whether it was
+    fn undelete_streak(&mut self, name: &str) -> bool {
+        if self.streaks.contains_key(name) {
+            eprintln!(
+                "{}",
+                tr!("streak \"{}\" already exists, rename it first", name)
+            );
+            return false;
+        }
+        match self.trash.iter().rposition(|trashed| trashed.name == name) {
+            Some(i) => {
+                let trashed = self.trash.remove(i);
+                self.audit.push(audit::Record::new(
+                    "add",
+                    name,
+                    tr!("restored from the trash"),
+                ));
+                self.streaks.insert(trashed.name, trashed.streak);
+                true
+            }
+            None => {
+                eprintln!("{}", tr!("no removed streak \"{}\" in the trash", name));
+                false
+            }
+        }
+    }
+
+    /// Forget removed streaks older than the configured retention period
+    fn purge_trash(&mut self) {
+        let keep = Duration::days(config::read_config().keep_trash_days.into());
+        let now = Local::now();
+        self.trash
+            .retain(|trashed| now.signed_duration_since(trashed.removed) < keep);
+    }
+
+    /// Returns the old name of the streak if it was found and renamed
+    fn rename_streak(&mut self, name: &str, new_name: &str, fuzzy: bool) -> Option<String> {
+        if let Err(err) = validate_name(new_name) {
+            eprintln!("{}", err);
+            return None;
+        }
+        let name = self.find_loosely(name, fuzzy)?;
+        let streak = self.streaks.remove(&name).unwrap();
+        self.streaks.insert(new_name.to_owned(), streak);
+        self.retarget_aliases(&name, new_name);
+        self.retarget_dependencies(&name, new_name);
+        self.retarget_groups(&name, new_name);
+        self.retarget_order(&name, new_name);
+        self.audit.push(audit::Record::new(
+            "rename",
+            &name,
+            format!("to {}", new_name),
+        ));
+        self.renames.push((name.clone(), new_name.to_owned()));
+        Some(name)
+    }
+
+    /// Rename every streak whose name the pattern changes, showing the new names and asking
+    /// first unless `force`, and returning the old and new names
+    fn rename_matching(&mut self, pattern: &select::Rename, force: bool) -> Vec<(String, String)> {
+        let renames: Vec<_> = self
+            .streaks
+            .keys()
+            .filter_map(|name| pattern.apply(name).map(|new_name| (name.clone(), new_name)))
+            .collect();
+        if renames.is_empty() {
+            eprintln!("{}", tr!("the pattern doesn't change any streak names"));
+            return Vec::new();
+        }
+        let mut problems = Vec::new();
+        for (i, (name, new_name)) in renames.iter().enumerate() {
+            if let Err(err) = validate_name(new_name) {
+                problems.push(err);
+            } else if self.streaks.contains_key(new_name) {
+                problems.push(tr!(
+                    "\"{}\" would become \"{}\", which is already taken",
+                    name,
+                    new_name
+                ));
+            } else if renames[..i].iter().any(|(_name, other)| other == new_name) {
+                problems.push(tr!("more than one streak would become \"{}\"", new_name));
+            }
+        }
+        if !problems.is_empty() {
+            // all or nothing, so a naming scheme is never left half changed
+            for problem in problems {
+                eprintln!("{}", problem);
+            }
+            return Vec::new();
+        }
+        for (name, new_name) in renames.iter() {
+            println!("{} -> {}", name, new_name);
+        }
+        let prompt = tr!(
+            "rename {} streak{}?",
+            renames.len(),
+            if renames.len() == 1 { "" } else { "s" }
+        );
+        if !force && !yes_or_no(&prompt) {
+            return Vec::new();
+        }
+        for (name, new_name) in renames.iter() {
+            self.rename_streak(name, new_name, false);
+        }
+        renames
+    }
+
+    fn retarget_aliases(&mut self, name: &str, new_name: &str) {
+        for target in self.aliases.values_mut().filter(|target| *target == name) {
+            *target = new_name.to_owned();
+        }
+    }
+
+    fn retarget_dependencies(&mut self, name: &str, new_name: &str) {
+        for (dependent, streak) in self.streaks.iter_mut() {
+            if streak.depends_on.as_deref() == Some(name) {
+                // a streak merged into the one it depended on no longer depends on anything
+                streak.depends_on = Some(new_name.to_owned()).filter(|_| dependent != new_name);
+            }
+        }
+    }
+
+    fn retarget_groups(&mut self, name: &str, new_name: &str) {
+        for members in self.groups.values_mut() {
+            if members.iter().any(|member| member == name) {
+                for member in members.iter_mut().filter(|member| *member == name) {
+                    *member = new_name.to_owned();
+                }
+                // merging can leave a streak in a group twice
+                *members = members.iter().unique().cloned().collect();
+            }
+        }
+    }
+
+    fn retarget_order(&mut self, name: &str, new_name: &str) {
+        if self.order.iter().any(|pinned| pinned == name) {
+            for pinned in self.order.iter_mut().filter(|pinned| *pinned == name) {
+                *pinned = new_name.to_owned();
+            }
+            self.order = self.order.iter().unique().cloned().collect();
+        }
+    }
+
+    /// How streaks are listed: pinned ones first in their order, then the rest by name
+    fn sort_key<'a>(&self, name: &'a str) -> (usize, &'a str) {
+        let position = self.order.iter().position(|pinned| pinned == name);
+        (position.unwrap_or(usize::MAX), name)
+    }
+
+    /// Pin streaks below those already pinned, or move them to those places if `order` is the
+    /// whole new order, returning whether they were all found
+    fn pin(&mut self, names: &[String], order: bool) -> bool {
+        let mut found = Vec::new();
+        for name in names.iter() {
+            match self.lookup(name) {
+                Some(name) => found.push(name),
+                None => {
+                    self.not_found(name);
+                    return false;
+                }
+            }
+        }
+        if order {
+            self.order.clear();
+        }
+        for name in found {
+            if !self.order.contains(&name) {
+                self.order.push(name);
+            }
+        }
+        true
+    }
+
+    /// Create a group or add streaks to one, returning whether they were all found
+    fn add_to_group(&mut self, group: &str, names: &[String]) -> bool {
+        if let Err(err) = validate_name(group) {
+            eprintln!("{}", err);
+            return false;
+        }
+        let mut found = Vec::new();
+        for name in names.iter() {
+            match self.lookup(name) {
+                Some(name) => found.push(name),
+                None => {
+                    self.not_found(name);
+                    return false;
+                }
+            }
+        }
+        let members = self.groups.entry(group.to_owned()).or_default();
+        for name in found {
+            if !members.contains(&name) {
+                members.push(name);
+            }
+        }
+        true
+    }
+
+    /// Take streaks out of a group, dropping the group once it's empty, returning whether it
+    /// was found
+    fn remove_from_group(&mut self, group: &str, names: &[String]) -> bool {
+        let members = match self.groups.get_mut(group) {
+            Some(members) => members,
+            None => {
+                eprintln!("{}", tr!("group \"{}\" not found", group));
+                NOT_FOUND.store(true, Ordering::Relaxed);
+                return false;
+            }
+        };
+        members.retain(|member| !names.contains(member));
+        if members.is_empty() {
+            self.groups.remove(group);
+        }
+        true
+    }
+
+    /// The streaks a streak depends on, directly first, stopping at a cycle or a removed streak
+    fn dependency_chain(&self, name: &str) -> Vec<String> {
+        let mut chain: Vec<String> = Vec::new();
+        let mut next = self
+            .streaks
+            .get(name)
+            .and_then(|streak| streak.depends_on.as_ref());
+        while let Some(dependency) = next {
+            if dependency == name || chain.contains(dependency) {
+                break;
+            }
+            chain.push(dependency.clone());
+            next = self
+                .streaks
+                .get(dependency)
+                .and_then(|streak| streak.depends_on.as_ref());
+        }
+        chain
+    }
+
+    /// Make a streak depend on another, or on nothing, returning whether it was set
+    fn set_dependency(&mut self, name: &str, dependency: Option<&str>) -> bool {
+        let name = match self.lookup(name) {
+            Some(name) => name,
+            None => {
+                self.not_found(name);
+                return false;
+            }
+        };
+        let dependency = match dependency {
+            Some(dependency) => match self.lookup(dependency) {
+                Some(dependency) => Some(dependency),
+                None => {
+                    self.not_found(dependency);
+                    return false;
+                }
+            },
+            None => None,
+        };
+        if let Some(dependency) = &dependency {
+            if *dependency == name || self.dependency_chain(dependency).contains(&name) {
+                eprintln!(
+                    "{}",
+                    tr!(
+                        "\"{}\" can't depend on \"{}\", which already depends on it",
+                        name,
+                        dependency
+                    )
+                );
+                return false;
+            }
+        }
+        self.streaks.get_mut(&name).unwrap().depends_on = dependency;
+        true
+    }
+
+    /// Whether the streak a streak depends on is done today, or the hit should go ahead anyway
+    fn dependency_done(&self, name: &str) -> bool {
+        let dependency = match &self.streaks[name].depends_on {
+            Some(dependency) => dependency,
+            None => return true,
+        };
+        let today = current_day();
+        let done = self.streaks.get(dependency).is_none_or(|streak| {
+            matches!(streak.state, StreakState::Done) && day_of(streak.last_hit) == today
+        });
+        done || yes_or_no(&tr!(
+            "\"{}\" comes after \"{}\", which isn't done today, hit it anyway?",
+            name,
+            dependency
+        ))
+    }
+
+    /// Hit a streak looked up by name once what it depends on is done
+    fn hit_found(
+        &mut self,
+        name: String,
+        disambiguator: Option<String>,
+        time: DateTime<Local>,
+    ) -> Option<(String, u32)> {
+        if !self.dependency_done(&name) {
+            return None;
+        }
+        let streak = self.streaks.get_mut(&name)?;
+        streak.hit(disambiguator, time).map(|n| (name, n))
+    }
+
+    /// Point an alias at a streak, returning whether it was set
+    fn set_alias(&mut self, alias: &str, name: &str) -> bool {
+        if let Err(err) = validate_name(alias) {
+            eprintln!("{}", err);
+            return false;
+        }
+        if self.streaks.contains_key(alias) {
+            eprintln!("{}", tr!("\"{}\" is already the name of a streak", alias));
+            return false;
+        }
+        if !self.streaks.contains_key(name) {
+            self.not_found(name);
+            return false;
+        }
+        self.aliases.insert(alias.to_owned(), name.to_owned());
+        true
+    }
+
+    /// Fold one streak into another, returning whether both were found
+    fn merge_streaks(&mut self, name: &str, into: &str) -> bool {
+        if name == into {
+            eprintln!("{}", tr!("can't merge a streak into itself"));
+            return false;
+        }
+        if !self.streaks.contains_key(into) {
+            self.not_found(into);
+            return false;
+        }
+        let streak = match self.streaks.remove(name) {
+            Some(streak) => streak,
+            None => {
+                self.not_found(name);
+                return false;
+            }
+        };
+        let target = self.streaks.get_mut(into).unwrap();
+        let (current_count, max_count) = (
+            target.current_count.max(streak.current_count),
+            target.max_count.max(streak.max_count),
+        );
+        if streak.last_hit > target.last_hit {
+            target.last_hit = streak.last_hit;
+            target.state = streak.state;
+        }
+        target.current_count = current_count;
+        target.max_count = max_count;
+        target.created = match (target.created, streak.created) {
+            (Some(ours), Some(theirs)) => Some(ours.min(theirs)),
+            (ours, theirs) => ours.or(theirs),
+        };
+        self.retarget_aliases(name, into);
+        self.retarget_dependencies(name, into);
+        self.retarget_groups(name, into);
+        self.retarget_order(name, into);
+        // the history of both now belongs to the remaining streak, so its earliest entry is still
+        // the older of the two start dates
+        self.renames.push((name.to_owned(), into.to_owned()));
+        self.audit.push(audit::Record::new(
+            "merge",
+            name,
+            format!(
+                "into {}, was at {}, max {}",
+                into, streak.current_count, streak.max_count
+            ),
+        ));
+        true
+    }
+
+    /// Returns the name of the updated streak and the new count, or an error if no streak was
+    /// chosen and a new one wasn't created
+    fn hit_streak(
+        &mut self,
+        name: &str,
+        one_of_many: bool,
+        create: bool,
+        time: DateTime<Local>,
+    ) -> Result<Option<(String, u32)>, String> {
+        let hit = self.find_and_hit(name, one_of_many, create, time)?;
+        if let Some((name, count)) = &hit {
+            self.record_hit(name, *count, time);
+        }
+        Ok(hit)
+    }
+
+    /// Hit the streak with exactly this name, without prompting
+    fn hit_exact(&mut self, name: &str) -> Option<u32> {
+        let now = Local::now();
+        let count = self.streaks.get_mut(name)?.hit(None, now)?;
+        self.record_hit(name, count, now);
+        Some(count)
+    }
+
+    fn record_hit(&mut self, name: &str, count: u32, time: DateTime<Local>) {
+        self.events
+            .push(history::Entry::at(name, time, history::Event::Hit));
+        self.audit
+            .push(audit::Record::new("hit", name, format!("now at {}", count)));
+        let config = config::read_config();
+        if config
+            .skip_every
+            .is_some_and(|every| count.is_multiple_of(every))
+        {
+            let streak = self.streaks.get_mut(name).unwrap();
+            streak.skips += 1;
+            println!(
+                "{}",
+                tr!(
+                    "earned a skip token for \"{}\", {} saved up",
+                    name,
+                    streak.skips
+                )
+            );
+        }
+        if config.milestones.contains(&count) {
+            println!(
+                "{}",
+                tr!(
+                    "milestone reached: \"{}\" is at {} days, keep it up!",
+                    name,
+                    count
+                )
+            );
+            self.events
+                .push(history::Entry::new(name, history::Event::Milestone(count)));
+        }
+    }
+
+    /// The existing streak most like a new name, and how, if it's close enough that the new one
+    /// might be a mistake, which leaves out names merely containing it
+    fn close_match(&self, name: &str) -> Option<(String, NameMatch)> {
+        self.streaks
+            .keys()
+            .filter_map(|existing| match_name(name, existing).map(|quality| (quality, existing)))
+            .filter(|(quality, _existing)| {
+                !matches!(quality, NameMatch::Exact | NameMatch::Substring)
+            })
+            .min()
+            .map(|(quality, existing)| (existing.clone(), quality))
+    }
+
+    /// Names of the streaks loosely matching a query, best first, leaving out an exact match
+    fn ranked_matches(&self, query: &str) -> Vec<String> {
+        self.streaks
+            .keys()
+            .filter_map(|name| match_name(query, name).map(|quality| (quality, name)))
+            .filter(|(quality, _name)| *quality != NameMatch::Exact)
+            .sorted()
+            .map(|(quality, name)| {
+                trace!("\"{}\" matches \"{}\" by {:?}", query, name, quality);
+                name.clone()
+            })
+            .collect()
+    }
+
+    fn find_and_hit(
+        &mut self,
+        name: &str,
+        one_of_many: bool,
+        create: bool,
+        time: DateTime<Local>,
+    ) -> Result<Option<(String, u32)>, String> {
+        let disambiguator = one_of_many.then(|| format!("\"{name}\": "));
+        if let Some(found) = self.lookup(name) {
+            debug!("\"{}\" names streak \"{}\"", name, found);
+            return Ok(self.hit_found(found, disambiguator, time));
+        }
+        if let Some(target) = self.aliases.get(name) {
+            debug!("\"{}\" is an alias of \"{}\"", name, target);
+            if self.streaks.contains_key(target) {
+                return Ok(self.hit_found(target.clone(), disambiguator, time));
+            }
+        }
+        let candidates = self.ranked_matches(name);
+        debug!(
+            "no streak is called \"{}\", {} loosely match",
+            name,
+            candidates.len()
+        );
+        let chosen = match candidates.as_slice() {
+            [] => None,
+            [_, _, ..] if assumed_answer() == Some(false) => {
+                return Err(tr!(
+                    "\"{}\" is ambiguous, it could be any of \"{}\"",
+                    name,
+                    candidates.join("\", \"")
+                ))
+            }
+            [candidate] => yes_or_no(&tr!("hit \"{}\"?", candidate)).then_some(candidate),
+            _ => choose(
+                &tr!("which streak did you mean by \"{}\"?", name),
+                &candidates,
+            )
+            .map(|i| &candidates[i]),
+        };
+        if let Some(chosen) = chosen {
+            debug!("hitting \"{}\" for \"{}\"", chosen, name);
+            return Ok(self.hit_found(chosen.clone(), disambiguator, time));
+        }
+        validate_name(name)?;
+        if !create && !yes_or_no(&tr!("create new streak \"{}\"?", name)) {
+            NOT_FOUND.store(true, Ordering::Relaxed);
+            return Err(tr!(
+                "streak \"{}\" not found, pass --create to start it",
+                name
+            ));
+        }
+        if !self.streaks.contains_key(name) {
+            self.streaks.insert(name.to_owned(), Streak::new());
+            self.audit
+                .push(audit::Record::new("add", name, String::new()));
+        }
+        Ok(self
+            .streaks
+            .get_mut(name)
+            .and_then(|streak| streak.hit(disambiguator, time))
+            .map(|n| (name.to_owned(), n)))
+    }
+
+    fn serialize(&self) -> String {
+        let mut sections = vec![
+            self.streaks
+                .iter()
+                .sorted_by_key(|pair| pair.0)
+                .map(|(name, streak)| format!("{},{}", name, streak.serialize()))
+                .collect(),
+            self.trash
+                .iter()
+                .map(|trashed| {
+                    format!(
+                        "{},{},{}",
+                        trashed.name,
+                        trashed.removed,
+                        trashed.streak.serialize()
+                    )
+                })
+                .collect(),
+            self.aliases
+                .iter()
+                .sorted()
+                .map(|(alias, name)| format!("{},{}", alias, name))
+                .collect(),
+            self.groups
+                .iter()
+                .sorted_by_key(|pair| pair.0)
+                .map(|(group, members)| format!("{},{}", group, members.join(",")))
+                .collect::<Vec<_>>(),
+            Some(self.order.join(","))
+                .filter(|order| !order.is_empty())
+                .into_iter()
+                .collect(),
+        ];
+        for (section, line) in self.unreadable.iter() {
+            let last = sections.len() - 1;
+            sections[(*section).min(last)].push(line.clone());
+        }
+        // no streak line is empty, so blank lines separate the removed streaks, the aliases, the
+        // groups and the order, and trailing empty sections can be left out
+        while sections.last().is_some_and(Vec::is_empty) {
+            sections.pop();
+        }
+        let mut lines = vec![format!("{}{}", STATE_HEADER, STATE_VERSION)];
+        for (i, section) in sections.into_iter().enumerate() {
+            if i > 0 {
+                lines.push(String::new());
+            }
+            lines.extend(section);
+        }
+        lines.join("\n")
+    }
+
+    /// Parse the state strictly, failing on the first unreadable line
+    fn deserialize(string: &str) -> Result<Self, ParseError> {
+        let (state, errors) = Self::deserialize_lenient(string)?;
+        match errors.into_iter().next() {
+            Some(err) => Err(err),
+            None => Ok(state),
+        }
+    }
+
+    /// Parse the state, keeping lines that can't be parsed aside along with why, so that a
+    /// single bad line doesn't make every command unusable
+    fn deserialize_lenient(string: &str) -> Result<(Self, Vec<ParseError>), ParseError> {
+        let mut streaks = HashMap::new();
+        let mut trash = Vec::new();
+        let mut aliases = HashMap::new();
+        let mut groups = HashMap::new();
+        let mut order = Vec::new();
+        let mut unreadable = Vec::new();
+        let mut errors = Vec::new();
+        // streaks, then removed streaks, then aliases, then groups, then the order
+        let mut section = 0;
+        // migrations keep the lines in place, so only the header shifts them
+        let first_line = if string.starts_with(STATE_HEADER) {
+            2
+        } else {
+            1
+        };
+        for (index, text) in migrate(string)?.lines().enumerate() {
+            let line = first_line + index;
+            if text.is_empty() {
+                section += 1;
+                continue;
+            }
+            if let Err(err) = check_length(text, line) {
+                unreadable.push((section, text.to_owned()));
+                errors.push(err);
+                continue;
+            }
+            if section >= 4 {
+                order.extend(text.split(',').map(str::to_owned));
+                continue;
+            }
+            if section == 3 {
+                let mut values = text.split(',');
+                let group = values.next().unwrap_or_default();
+                groups.insert(group.to_owned(), values.map(str::to_owned).collect());
+                continue;
+            }
+            let parsed = if section == 2 {
+                text.split_once(',')
+                    .map(|(alias, name)| {
+                        aliases.insert(alias.to_owned(), name.to_owned());
+                    })
+                    .ok_or(ParseError::BadFieldCount {
+                        line,
+                        expected: 2,
+                        found: 1,
+                    })
+            } else if section == 1 {
+                Trashed::deserialize(text, line).map(|trashed| trash.push(trashed))
+            } else {
+                let values: Vec<_> = text.split(',').collect();
+                validate_name(values[0])
+                    .map_err(|reason| ParseError::BadValue {
+                        line,
+                        column: 1,
+                        value: values[0].to_owned(),
+                        reason,
+                    })
+                    .and_then(|()| Streak::deserialize(&values[1..], line, 2))
+                    .map(|streak| {
+                        streaks.insert(values[0].to_owned(), streak);
+                    })
+            };
+            if let Err(err) = parsed {
+                unreadable.push((section, text.to_owned()));
+                errors.push(err);
+            }
+        }
+        let state = Self {
+            streaks,
+            trash,
+            aliases,
+            groups,
+            order,
+            unreadable,
+            events: Vec::new(),
+            audit: Vec::new(),
+            renames: Vec::new(),
+        };
+        Ok((state, errors))
+    }
+}
+
+/// A rough duration like `5h` for showing deadlines
+fn format_remaining(remaining: Duration) -> String {
+    if remaining.num_hours() >= 48 {
+        format!("{}d", remaining.num_days())
+    } else if remaining.num_hours() >= 1 {
+        format!("{}h", remaining.num_hours())
+    } else {
+        format!("{}m", remaining.num_minutes().max(0))
     }
 }
 
-fn write_table(f: &mut fmt::Formatter, table: Vec<[String; 4]>) -> fmt::Result {
-    let max_widths: Vec<_> = (0..4)
-        .map(|i| table.iter().map(|arr| arr[i].len()).max().unwrap())
+fn write_table(f: &mut fmt::Formatter, columns: &[Column], table: Vec<Vec<String>>) -> fmt::Result {
+    let max_widths: Vec<_> = (0..columns.len())
+        .map(|i| table.iter().map(|row| row[i].width()).max().unwrap_or(0))
         .collect();
     for row in table {
-        writeln!(
-            f,
-            "{:<width0$} {:>width1$} {:>width2$} {:>width3$}",
-            row[0],
-            row[1],
-            row[2],
-            row[3],
-            width0 = max_widths[0],
-            width1 = max_widths[1],
-            width2 = max_widths[2],
-            width3 = max_widths[3]
-        )?;
+        let cells: Vec<_> = row
+            .iter()
+            .zip(columns)
+            .enumerate()
+            .map(|(i, (cell, column))| {
+                let padding = " ".repeat(max_widths[i] - cell.width());
+                if column.left_aligned() {
+                    format!("{}{}", cell, padding)
+                } else {
+                    format!("{}{}", padding, cell)
+                }
+            })
+            .collect();
+        writeln!(f, "{}", cells.join(" ").trim_end())?;
     }
     Ok(())
 }
 
+/// A column of the table shown by `display`
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Column {
+    Name,
+    Count,
+    Max,
+    State,
+    LastHit,
+    Tags,
+    /// The last day the streak can be hit on before it expires
+    Deadline,
+    /// The hits of the last couple of weeks
+    Sparkline,
+    /// The chain of streaks to be done before this one
+    Depends,
+}
+
+const DEFAULT_COLUMNS: [Column; 4] = [Column::Name, Column::Count, Column::Max, Column::State];
+
+impl Column {
+    fn deserialize(string: &str) -> Result<Self, String> {
+        match string {
+            "name" => Ok(Column::Name),
+            "count" => Ok(Column::Count),
+            "max" => Ok(Column::Max),
+            "state" => Ok(Column::State),
+            "last_hit" => Ok(Column::LastHit),
+            "tags" => Ok(Column::Tags),
+            "deadline" => Ok(Column::Deadline),
+            "sparkline" => Ok(Column::Sparkline),
+            "depends" => Ok(Column::Depends),
+            _ => Err(tr!(
+                "unknown column \"{}\", expected name, count, max, state, last_hit, tags, deadline, sparkline or depends",
+                string
+            )),
+        }
+    }
+
+    fn parse_list<S: AsRef<str>>(strings: &[S]) -> Result<Vec<Self>, String> {
+        let columns = strings
+            .iter()
+            .map(|string| Column::deserialize(string.as_ref().trim()))
+            .collect::<Result<Vec<_>, _>>()?;
+        if columns.is_empty() {
+            Err(tr!("expected at least one column"))
+        } else {
+            Ok(columns)
+        }
+    }
+
+    fn left_aligned(&self) -> bool {
+        matches!(self, Column::Name | Column::Tags | Column::Depends)
+    }
+}
+
 impl fmt::Display for State {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        if !self.streaks.is_empty() {
-            let table: Vec<_> = self
-                .streaks
-                .iter()
-                .sorted_by_key(|pair| pair.0)
-                .map(|pair| {
-                    let (name, streak) = pair;
-                    [
-                        format!("- {}:", name),
-                        format!("{}", streak.current_count),
-                        format!("(max {})", streak.max_count),
-                        streak.state.serialize().to_owned(),
-                    ]
-                })
-                .collect();
-            write_table(f, table)?;
+        let table = Table {
+            state: self,
+            columns: &DEFAULT_COLUMNS,
+            entries: None,
+            glyphs: None,
+            by_risk: false,
+        };
+        write!(f, "{}", table)
+    }
+}
+
+/// The table shown by `display`
+struct Table<'a> {
+    state: &'a State,
+    columns: &'a [Column],
+    /// The history to show sparklines from, which are left empty without it
+    entries: Option<&'a [history::Entry]>,
+    /// Show states and icons as glyphs
+    glyphs: Option<&'a config::Glyphs>,
+    /// Order by risk instead of by name
+    by_risk: bool,
+}
+
+impl Table<'_> {
+    /// The state of a streak with when it expires, challenge days and skips added
+    fn state_cell(&self, streak: &Streak) -> String {
+        let mut state = match self.glyphs {
+            Some(glyphs) => match streak.state {
+                StreakState::Done => glyphs.done.clone(),
+                StreakState::Pending => glyphs.pending.clone(),
+                StreakState::Expired => glyphs.expired.clone(),
+                StreakState::New => glyphs.new.clone(),
+            },
+            None => i18n::translate(streak.state.serialize(), &[]),
+        };
+        if matches!(streak.state, StreakState::Pending) {
+            let now = Local::now();
+            if let Some(expiry) = streak.expires_at(now) {
+                state += &tr!(
+                    " (expires in {})",
+                    format_remaining(expiry.signed_duration_since(now))
+                );
+            }
+        }
+        if let Some(challenge) = streak.challenge {
+            let today = current_day();
+            state += &tr!(" [{} days left]", challenge.days_left(today));
+        }
+        if streak.skips > 0 {
+            state += &if streak.skips == 1 {
+                tr!(" (1 skip)")
+            } else {
+                tr!(" ({} skips)", streak.skips)
+            };
+        }
+        state
+    }
+
+    fn cell(&self, column: Column, name: &str, streak: &Streak) -> String {
+        let hit = !matches!(streak.state, StreakState::New);
+        match column {
+            Column::Name => {
+                let bullet = match (self.glyphs, &streak.icon) {
+                    (Some(_), Some(icon)) => icon.as_str(),
+                    _ => "-",
+                };
+                format!("{} {}:", bullet, name)
+            }
+            Column::Count => streak.current_count.to_string(),
+            Column::Max => tr!("(max {})", streak.max_count),
+            Column::State => self.state_cell(streak),
+            Column::LastHit if hit => streak.last_hit.format("%Y-%m-%d").to_string(),
+            Column::Tags => streak.tags.join(" "),
+            Column::Deadline if hit && !matches!(streak.state, StreakState::Expired) => streak
+                .schedule
+                .next_deadline(day_of(streak.last_hit))
+                .format("%Y-%m-%d")
+                .to_string(),
+            Column::Sparkline => match self.entries {
+                Some(entries) => {
+                    let today = current_day();
+                    history::sparkline(entries, name, today, history::SPARKLINE_DAYS)
+                }
+                None => String::new(),
+            },
+            Column::Depends => self.state.dependency_chain(name).join(" ← "),
+            Column::LastHit | Column::Deadline => String::new(),
         }
-        Ok(())
+    }
+}
+
+/// A line of totals for under the table, like `4 done · 2 pending · best active streak: 42 (gym)`
+fn summary(state: &State) -> Option<String> {
+    if state.streaks.is_empty() {
+        return None;
+    }
+    let mut counts = [0; 4];
+    for streak in state.streaks.values() {
+        counts[match streak.state {
+            StreakState::Done => 0,
+            StreakState::Pending => 1,
+            StreakState::Expired => 2,
+            StreakState::New => 3,
+        }] += 1;
+    }
+    let mut parts = vec![
+        tr!("{} done", counts[0]),
+        tr!("{} pending", counts[1]),
+        tr!("{} expired", counts[2]),
+    ];
+    if counts[3] > 0 {
+        parts.push(tr!("{} new", counts[3]));
+    }
+    let best = state
+        .streaks
+        .iter()
+        .filter(|(_name, streak)| {
+            streak.current_count > 0 && !matches!(streak.state, StreakState::Expired)
+        })
+        // the first name alphabetically wins a tie
+        .max_by_key(|(name, streak)| (streak.current_count, Reverse(*name)));
+    if let Some((name, streak)) = best {
+        parts.push(tr!(
+            "best active streak: {} ({})",
+            streak.current_count,
+            name
+        ));
+    }
+    Some(parts.join(" · "))
+}
+
+impl fmt::Display for Table<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.state.streaks.is_empty() {
+            return Ok(());
+        }
+        let now = Local::now();
+        let table = self
+            .state
+            .streaks
+            .iter()
+            .sorted_by_key(|(name, streak)| {
+                // pending streaks first, the most important then soonest to expire at the top
+                let risk = self.by_risk.then(|| {
+                    let pending = matches!(streak.state, StreakState::Pending);
+                    (!pending, Reverse(streak.priority), streak.expires_at(now))
+                });
+                (risk, self.state.sort_key(name))
+            })
+            .map(|(name, streak)| {
+                self.columns
+                    .iter()
+                    .map(|&column| self.cell(column, name, streak))
+                    .collect()
+            })
+            .collect();
+        write_table(f, self.columns, table)
     }
 }
 
 fn print_usage(path: &str) {
-    println!("usage: {} <command> [args...]", path);
+    println!("{}", tr!("usage: {} [options] <command> [args...]", path));
     println!();
-    println!("supported commands:");
+    println!("{}", tr!("options:"));
     println!();
-    println!("    display - Output a list of streaks with information about their state.");
-    println!("    update - Check the date and update pending/expired state of streaks.");
-    println!("    hit <streak name> - Hit a streak with the given name.");
-    println!("    add <streak name> - Start tracking a new streak with the given name.");
-    println!("    remove <streak name> - Stop tracking the streak with the given name.");
-    println!("    rename <streak name> <new name> - Change the name of an existing streak.");
+    println!(
+        "    {}",
+        tr!("-v, --verbose - Explain what's happening on stderr, -vv for more detail.")
+    );
+    println!("    {}", tr!("-y, --yes - Answer yes to every prompt."));
+    println!(
+        "    {}",
+        tr!("--no-input - Answer no to every prompt, as when stdin isn't a terminal.")
+    );
+    println!(
+        "    {}",
+        tr!("--profile <name> - Use a separate set of streaks, also set by STREAKS_PROFILE.")
+    );
+    println!(
+        "    {}",
+        tr!("A streak name of - for hit or add reads one name per line from stdin.")
+    );
+    println!(
+        "    {}",
+        tr!("Streak names for hit, remove and display can be globs like 'gym*', --regex <re> or --group <group>.")
+    );
+    println!();
+    println!("{}", tr!("supported commands:"));
+    println!();
+    println!(
+        "    {}",
+        tr!(
+            "display [streak names] - Output a list of streaks with information about their state."
+        )
+    );
+    println!(
+        "        {}",
+        tr!("--format <format> - Print a line per streak like \"{{name}}: {{count}}/{{max}}\".")
+    );
+    println!(
+        "        {}",
+        tr!("--sparkline - Add a column with the hits of the last 14 days.")
+    );
+    println!(
+        "        {}",
+        tr!("--columns <column,...> - Choose the columns from name, count, max, state, last_hit, tags, deadline, sparkline and depends.")
+    );
+    println!(
+        "        {}",
+        tr!("--glyphs - Show states as symbols and streaks with their icons.")
+    );
+    println!(
+        "        {}",
+        tr!("--watch [secs] - Redraw every few seconds, 5 unless given.")
+    );
+    println!(
+        "        {}",
+        tr!("--by-risk - Put pending streaks first, by priority then time left.")
+    );
+    println!(
+        "        {}",
+        tr!("--hide-done - Leave out streaks already done, or --show-done to keep them.")
+    );
+    println!(
+        "        {}",
+        tr!("--no-summary - Leave out the line of totals under the table.")
+    );
+    println!(
+        "    {}",
+        tr!("today - List the streaks still to hit today.")
+    );
+    println!(
+        "    {}",
+        tr!("skip <streak name> [--date <date>] - Take a day off, today unless given, without breaking the streak.")
+    );
+    println!(
+        "    {}",
+        tr!("repair <streak name> <date> - Count a forgotten hit on a past day, recomputing the streak from the history.")
+    );
+    println!(
+        "    {}",
+        tr!("snooze <streak name> - Leave a streak due today out of today and its reminders.")
+    );
+    println!(
+        "    {}",
+        tr!("status --short [--color] - Print a one line summary for shell prompts.")
+    );
+    println!(
+        "        {}",
+        tr!("--plain - Print done, pending and expired counts like ●3 ○1 ✗1 for tmux.")
+    );
+    println!(
+        "    {}",
+        tr!("statusbar - Print today's progress as JSON for a waybar custom module.")
+    );
+    println!(
+        "    {}",
+        tr!("update - Check the date and update pending/expired state of streaks.")
+    );
+    println!(
+        "        {}",
+        tr!("Asks before resetting streaks last hit after today, as after the clock went back.")
+    );
+    println!(
+        "    {}",
+        tr!("hit [--create] [--at <time>] <streak name> - Hit a streak, creating it if asked to.")
+    );
+    println!(
+        "        {}",
+        tr!("--at <time> - When it was done, like 22:15 for the last 22:15 or 2024-03-01 22:15.")
+    );
+    println!(
+        "    {}",
+        tr!("alias [<alias> <streak name>] - List aliases or add one to hit a streak by.")
+    );
+    println!("        {}", tr!("--remove <alias> - Remove an alias."));
+    println!(
+        "    {}",
+        tr!("add [options] <streak name> - Start tracking a new streak, with options")
+    );
+    println!(
+        "        {}",
+        tr!("--template <name> - Start from one of the listed templates.")
+    );
+    println!(
+        "        {}",
+        tr!("--interactive - Ask for the name, schedule, target, tags and reminder in turn.")
+    );
+    println!(
+        "        {}",
+        tr!("--every <n> - Hit at least once every n days instead of daily.")
+    );
+    println!(
+        "        {}",
+        tr!("--days <mon,wed,...> - Hit on each of these weekdays instead of daily.")
+    );
+    println!(
+        "        {}",
+        tr!("--target <n> - Hit n times a day for the day to count.")
+    );
+    println!(
+        "        {}",
+        tr!("--tag <tag> - Tag the streak, can be repeated.")
+    );
+    println!("        {}", tr!("--note <text> - Attach a short note."));
+    println!(
+        "    {}",
+        tr!("stats - Show completion rates, their trend, and the next milestone.")
+    );
+    println!(
+        "    {}",
+        tr!("week - Show a grid of hits over the last 7 days.")
+    );
+    println!(
+        "    {}",
+        tr!("graph <streak name> [--months <n>] - Chart the streak's count, over 3 months by default.")
+    );
+    println!(
+        "    {}",
+        tr!("month [streak name] - Show this month's hits, or the share of streaks hit.")
+    );
+    println!(
+        "    {}",
+        tr!("info <streak name> - Show everything about one streak.")
+    );
+    println!(
+        "    {}",
+        tr!("describe <streak name> [text] - Show or set a longer description of a streak.")
+    );
+    println!(
+        "    {}",
+        tr!("edit <streak name> - Change a streak's fields in $EDITOR.")
+    );
+    println!(
+        "        {}",
+        tr!("--set <key>=<value> - Change schedule, target, tags, note, icon, priority, rest, current or max.")
+    );
+    println!(
+        "    {}",
+        tr!("challenge <streak name> --days <n> - Commit to keeping a streak up for n days.")
+    );
+    println!(
+        "    {}",
+        tr!("profiles - List the profiles, marking the one in use.")
+    );
+    println!(
+        "    {}",
+        tr!("templates - List the templates available to add.")
+    );
+    println!(
+        "    {}",
+        tr!("milestones - List the milestones each streak has reached.")
+    );
+    println!(
+        "    {}",
+        tr!("badges - List the achievements earned so far and those still to earn.")
+    );
+    println!(
+        "    {}",
+        tr!("remove <streak name> - Stop tracking the streak with the given name.")
+    );
+    println!(
+        "        {}",
+        tr!("--force - Don't ask for confirmation first.")
+    );
+    println!(
+        "        {}",
+        tr!("--fuzzy - Take the only loosely matching streak without asking.")
+    );
+    println!(
+        "    {}",
+        tr!("undelete [streak name] - Restore a removed streak, or list the removed ones.")
+    );
+    println!(
+        "    {}",
+        tr!("doctor - Check the state file for problems and offer to fix them.")
+    );
+    println!(
+        "    {}",
+        tr!("log [-n <count>] [streak name] - Show every add, remove, rename, hit and reset, newest first.")
+    );
+    println!(
+        "    {}",
+        tr!("set-count <streak name> <count> [--max <count>] - Carry over an existing streak.")
+    );
+    println!(
+        "    {}",
+        tr!("depend <streak name> <streak name>|--clear - Make hitting the first streak ask whether the second is done today.")
+    );
+    println!(
+        "    {}",
+        tr!("pin <streak names> - List streaks first, below any already pinned.")
+    );
+    println!(
+        "    {}",
+        tr!("unpin <streak names> - Stop listing streaks first.")
+    );
+    println!(
+        "    {}",
+        tr!("order [<streak names>|--clear] - Show the pinned streaks, or pin exactly these in this order.")
+    );
+    println!(
+        "    {}",
+        tr!("group [create|add|remove <group> <streak names>|delete <group>] - List groups with how much of each is done today, or change them.")
+    );
+    println!(
+        "    {}",
+        tr!("remind [<streak name> <HH:MM>|<streak name> --clear] - Set when to be reminded of a streak, or list the reminders.")
+    );
+    println!(
+        "        {}",
+        tr!("--check - Send the reminders of streaks still due today, e.g. every few minutes from cron.")
+    );
+    println!(
+        "    {}",
+        tr!("rename <streak name> <new name> - Change the name of an existing streak.")
+    );
+    println!(
+        "        {}",
+        tr!("--pattern <s/regex/replacement/|from/to> - Rename every matching streak at once instead.")
+    );
+    println!(
+        "        {}",
+        tr!("--fuzzy - Take the only loosely matching streak without asking.")
+    );
+    println!(
+        "    {}",
+        tr!("merge <streak name> <into> - Fold a streak and its history into another.")
+    );
+    println!(
+        "    {}",
+        tr!("export --ical <file> - Write history and pending streaks to an iCalendar file.")
+    );
+    println!(
+        "    {}",
+        tr!("export --svg <file> [streak name] - Draw the last year of hits as a heatmap.")
+    );
+    println!(
+        "        {}",
+        tr!("--png <file> - Draw it as a PNG instead, if built with the png feature.")
+    );
+    println!(
+        "    {}",
+        tr!("export --csv <dir> - Write streaks.csv and history.csv for use in spreadsheets.")
+    );
+    println!(
+        "    {}",
+        tr!("import <file> - Merge streaks and history from a CSV or JSON file.")
+    );
+    println!(
+        "    {}",
+        tr!("import --loop <export> - Convert habits from a Loop Habit Tracker CSV export.")
+    );
+    println!(
+        "        {}",
+        tr!("--dry-run - Show what remove, rename, merge or import would change.")
+    );
+    println!(
+        "    {}",
+        tr!("habitica pull - Create or update streaks from your Habitica dailies.")
+    );
+    println!(
+        "    {}",
+        tr!("habitica push - Complete Habitica dailies for streaks hit today.")
+    );
+    println!(
+        "    {}",
+        tr!("report --markdown [--period day|week|month] - Print a summary for a review note.")
+    );
+    println!(
+        "    {}",
+        tr!("report --html <dir> - Write a web page with heatmaps, stats and trend charts.")
+    );
+    println!(
+        "    {}",
+        tr!("digest --email [--period day|week|month] - Print a summary as an email for sendmail.")
+    );
+    println!(
+        "        {}",
+        tr!("--send - Send it through the SMTP server in the config instead.")
+    );
+    println!(
+        "    {}",
+        tr!("review --year <year> - Summarize a year of history for a retrospective.")
+    );
+    println!(
+        "    {}",
+        tr!("serve --port <port> - Serve a REST API and Prometheus metrics for streaks.")
+    );
+    println!(
+        "    {}",
+        tr!("daemon - Serve streaks over D-Bus for desktop widgets, if built with it.")
+    );
+    println!(
+        "    {}",
+        tr!("telegram --poll - Answer /hit <name> messages sent to the Telegram bot.")
+    );
+    println!(
+        "        {}",
+        tr!("--once - Only answer the messages already waiting, e.g. from cron.")
+    );
+    println!(
+        "    {}",
+        tr!("backup [path] - Copy the data directory, by default into its backups folder.")
+    );
+    println!(
+        "    {}",
+        tr!("restore <path> - Check a backup and replace the data directory with it.")
+    );
+    println!(
+        "    {}",
+        tr!("sync - Merge the state and history with the configured git or WebDAV remote.")
+    );
+}
+
+/// Selects a profile, also set by the `--profile` flag so that hooks inherit it
+const PROFILE_VAR: &str = "STREAKS_PROFILE";
+
+fn validate_profile(name: &str) -> Result<(), String> {
+    if name.is_empty() || name == "." || name == ".." || name.contains(['/', '\\']) {
+        Err(tr!("invalid profile name \"{}\"", name))
+    } else {
+        Ok(())
+    }
 }
 
-fn ensure_state_path() -> PathBuf {
-    let mut path = dirs::data_dir().expect("couldn't locate directory to store data");
+fn profiles_dir() -> Result<PathBuf, Error> {
+    let mut path = dirs::data_dir().ok_or(Error::NoDirectory("data"))?;
     path.push("streaks");
-    if let Err(err) = fs::create_dir_all(&path) {
-        panic!("couldn't create directory for storing state data: {}", err);
+    path.push("profiles");
+    Ok(path)
+}
+
+/// The data directory of the current profile, the default one living at the top level
+fn ensure_data_dir() -> Result<PathBuf, Error> {
+    let mut path = dirs::data_dir().ok_or(Error::NoDirectory("data"))?;
+    path.push("streaks");
+    if let Some(profile) = env::var_os(PROFILE_VAR) {
+        let profile = profile.to_string_lossy();
+        validate_profile(&profile).map_err(Error::InvalidProfile)?;
+        path = profiles_dir()?.join(profile.as_ref());
+    }
+    fs::create_dir_all(&path).map_err(|err| Error::CreateDataDir(path.clone(), err))?;
+    Ok(path)
+}
+
+fn list_profiles() -> Result<(), Error> {
+    let current = env::var(PROFILE_VAR).ok();
+    let mut profiles = vec!["default".to_owned()];
+    if let Ok(entries) = fs::read_dir(profiles_dir()?) {
+        profiles.extend(
+            entries
+                .filter_map(Result::ok)
+                .filter(|entry| entry.path().is_dir())
+                .map(|entry| entry.file_name().to_string_lossy().into_owned())
+                .sorted(),
+        );
     }
+    for profile in profiles {
+        let active = match &current {
+            Some(current) => *current == profile,
+            None => profile == "default",
+        };
+        println!("{} {}", if active { "*" } else { " " }, profile);
+    }
+    Ok(())
+}
+
+fn ensure_state_path() -> Result<PathBuf, Error> {
+    let mut path = ensure_data_dir()?;
     path.push("state.txt");
-    path
-}
-
-fn read_string(mut file: File) -> io::Result<String> {
-    let mut buffer = String::new();
-    file.read_to_string(&mut buffer)?;
-    Ok(buffer)
-}
-
-fn read_state() -> State {
-    let path = ensure_state_path();
-    match OpenOptions::new()
-        .read(true)
-        // we need write(true) for create(true) to work
-        .write(true)
-        .truncate(false)
-        .create(true)
-        .open(&path)
-    {
-        Ok(file) => match read_string(file) {
-            Ok(string) => match State::deserialize(&string) {
-                Ok(state) => state,
-                Err(err) => panic!("couldn't parse state file: {}", err),
-            },
-            Err(err) => panic!("couldn't read state file: {}", err),
+    Ok(path)
+}
+
+/// How far the clock can go back between runs before it's worth a warning, leaving room for
+/// small corrections
+const CLOCK_TOLERANCE_MINUTES: i64 = 5;
+
+/// Record the time of this run, returning the time of the one before if it's known
+fn record_run(now: DateTime<Local>) -> Option<DateTime<Local>> {
+    let path = match ensure_data_dir() {
+        Ok(dir) => dir.join("last_run.txt"),
+        Err(err) => {
+            eprintln!("{}", tr!("couldn't record the time of this run: {}", err));
+            return None;
+        }
+    };
+    let last_run = fs::read_to_string(&path)
+        .ok()
+        .and_then(|string| parse_timestamp(string.trim()));
+    if let Err(err) = fs::write(&path, now.to_string()) {
+        eprintln!("{}", tr!("couldn't record the time of this run: {}", err));
+    }
+    last_run
+}
+
+fn read_state() -> Result<State, Error> {
+    let path = ensure_state_path()?;
+    let string = eventlog::load()?;
+    debug!(
+        "read {} bytes of state from {}",
+        string.len(),
+        path.display()
+    );
+    let (state, errors) =
+        State::deserialize_lenient(&string).map_err(|err| Error::ParseState(path.clone(), err))?;
+    for err in errors.iter() {
+        eprintln!("{}", tr!("warning: skipping unreadable state: {}", err));
+    }
+    if !errors.is_empty() {
+        eprintln!(
+            "{}",
+            tr!(
+                "the unreadable lines are kept as they are in {}, run `streaks doctor` to fix them",
+                path.display()
+            )
+        );
+    }
+    debug!(
+        "parsed {} streaks, {} removed streaks and {} aliases",
+        state.streaks.len(),
+        state.trash.len(),
+        state.aliases.len()
+    );
+    Ok(state)
+}
+
+/// Shift `state.txt.1` to `state.txt.2` and so on, then copy the current state to `state.txt.1`
+fn rotate_backups(path: &Path, keep: u32) -> io::Result<()> {
+    if keep == 0 || !path.exists() {
+        return Ok(());
+    }
+    let numbered = |n: u32| {
+        let mut name = path.as_os_str().to_owned();
+        name.push(format!(".{}", n));
+        PathBuf::from(name)
+    };
+    for n in (1..keep).rev() {
+        if numbered(n).exists() {
+            fs::rename(numbered(n), numbered(n + 1))?;
+        }
+    }
+    fs::copy(path, numbered(1))?;
+    Ok(())
+}
+
+fn write_state(state: &State) -> Result<(), Error> {
+    let pending = eventlog::prepare(&state.serialize())?;
+    journal::begin(&pending, &state.events, &state.audit, &state.renames)?;
+    eventlog::commit(&pending)?;
+    // before appending, so the new entries aren't renamed along with the old ones
+    history::rename_all_in_history(&state.renames);
+    history::append_history(&state.events);
+    audit::append(&state.audit);
+    journal::finish();
+    achievements::evaluate(state);
+    Ok(())
+}
+
+/// Held while the state file is being modified, so that concurrent processes don't lose writes.
+/// It's a lock the system holds on `state.lock` for the open file, so a process killed while
+/// holding it, like at a prompt, doesn't leave it behind
+struct StateLock {
+    _file: File,
+}
+
+impl StateLock {
+    fn acquire() -> Result<Self, Error> {
+        let mut path = ensure_data_dir()?;
+        path.push("state.lock");
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)
+            .map_err(|err| Error::Lock(path.clone(), err))?;
+        for _ in 0..100 {
+            match file.try_lock() {
+                Ok(()) => return Ok(Self { _file: file }),
+                Err(TryLockError::WouldBlock) => {
+                    thread::sleep(time::Duration::from_millis(50));
+                }
+                Err(TryLockError::Error(err)) => return Err(Error::Lock(path, err)),
+            }
+        }
+        Err(Error::LockTimeout(path))
+    }
+}
+
+fn modify_state<F: FnOnce(&mut State)>(action: F) -> Result<(), Error> {
+    let state = {
+        let _lock = StateLock::acquire()?;
+        let mut state = read_state()?;
+        action(&mut state);
+        write_state(&state)?;
+        state
+    };
+    // hooks may run streaks themselves, which would otherwise wait for the lock until it timed
+    // out, and a slow endpoint shouldn't hold up other processes writing
+    hooks::run(&state, &state.events);
+    webhooks::fire(&state, &state.events);
+    telegram::notify(&state, &state.events);
+    mqtt::publish(&state, &state.events);
+    Ok(())
+}
+
+/// Apply a change to the state, or for a dry run print how the state file and history would
+/// change without writing anything
+fn apply_state<F: FnOnce(&mut State)>(dry_run: bool, action: F) -> Result<(), Error> {
+    if !dry_run {
+        return modify_state(action);
+    }
+    let mut state = read_state()?;
+    let before = state.serialize();
+    action(&mut state);
+    let after = state.serialize();
+    // streak lines are sorted by name so unchanged ones line up
+    let before_lines: HashSet<_> = before.lines().collect();
+    let after_lines: HashSet<_> = after.lines().collect();
+    let removed: Vec<_> = before
+        .lines()
+        .filter(|line| !after_lines.contains(line))
+        .collect();
+    let added: Vec<_> = after
+        .lines()
+        .filter(|line| !before_lines.contains(line))
+        .collect();
+    println!("{}", tr!("dry run, nothing was written"));
+    if removed.is_empty() && added.is_empty() && state.events.is_empty() {
+        println!("{}", tr!("the state would not change"));
+        return Ok(());
+    }
+    for line in removed {
+        println!("- {}", line);
+    }
+    for line in added {
+        println!("+ {}", line);
+    }
+    for entry in state.events.iter() {
+        println!("{}", tr!("+ history: {}", entry.serialize()));
+    }
+    Ok(())
+}
+
+/// Replace a `-` among the names with the lines of stdin, so lists of names can be piped in
+fn read_stdin_names(names: &[String]) -> io::Result<Vec<String>> {
+    let mut expanded = Vec::new();
+    for name in names.iter() {
+        if name == "-" {
+            for line in io::stdin().lock().lines() {
+                let line = line?;
+                let line = line.trim();
+                if !line.is_empty() {
+                    expanded.push(line.to_owned());
+                }
+            }
+        } else {
+            expanded.push(name.clone());
+        }
+    }
+    Ok(expanded)
+}
+
+/// Remove a flag from the arguments, returning whether it was there
+fn take_flag(args: &[String], flag: &str) -> (bool, Vec<String>) {
+    let found = args.iter().any(|arg| arg == flag);
+    (
+        found,
+        args.iter().filter(|arg| *arg != flag).cloned().collect(),
+    )
+}
+
+/// How far back `graph` goes without --months
+const GRAPH_MONTHS: u32 = 3;
+
+/// The furthest back `graph --months` can go
+const MAX_GRAPH_MONTHS: u32 = 1200;
+
+/// Seconds between redraws of `display --watch` without an interval
+const WATCH_INTERVAL: u64 = 5;
+
+/// Print the streaks picked out by the selectors, or every streak including shared ones
+fn display_state(args: &[String]) -> Result<(), Error> {
+    let config = config::read_config();
+    let mut format = config.display_format.clone();
+    let mut sparklines = config.sparklines;
+    let mut use_glyphs = config.use_glyphs;
+    let mut hide_done = config.hide_done;
+    let mut show_summary = true;
+    let mut columns = match &config.display_columns {
+        Some(columns) => match Column::parse_list(columns) {
+            Ok(columns) => columns,
+            Err(err) => {
+                eprintln!("{}", tr!("invalid display.columns: {}", err));
+                return Err(Error::Failed);
+            }
         },
-        Err(err) => panic!("couldn't open state file: {}", err),
+        None => DEFAULT_COLUMNS.to_vec(),
+    };
+    let mut watch = None;
+    let mut by_risk = false;
+    let mut selectors = Vec::new();
+    let mut args = args.iter().peekable();
+    while let Some(arg) = args.next() {
+        if arg == "--columns" {
+            let parsed = match args.next() {
+                Some(value) => Column::parse_list(&value.split(',').collect::<Vec<_>>()),
+                None => Err(tr!("expected a list of columns after --columns")),
+            };
+            match parsed {
+                Ok(parsed) => columns = parsed,
+                Err(err) => return Err(Error::Usage(err)),
+            }
+        } else if arg == "--sparkline" {
+            sparklines = true;
+        } else if arg == "--glyphs" {
+            use_glyphs = true;
+        } else if arg == "--by-risk" {
+            by_risk = true;
+        } else if arg == "--hide-done" {
+            hide_done = true;
+        } else if arg == "--show-done" {
+            hide_done = false;
+        } else if arg == "--no-summary" {
+            show_summary = false;
+        } else if arg == "--watch" {
+            let interval = args.peek().and_then(|value| value.parse::<u64>().ok());
+            if interval.is_some() {
+                args.next();
+            }
+            watch = Some(interval.unwrap_or(WATCH_INTERVAL).max(1));
+        } else if arg == "--format" {
+            match args.next() {
+                Some(value) => format = Some(value.clone()),
+                None => return Err(Error::Usage(tr!("expected a format after --format"))),
+            }
+        } else {
+            selectors.push(arg.clone());
+        }
+    }
+    if sparklines && !columns.contains(&Column::Sparkline) {
+        // the sparkline goes before the state, which has the most variable width
+        let position = columns
+            .iter()
+            .position(|column| *column == Column::State)
+            .unwrap_or(columns.len());
+        columns.insert(position, Column::Sparkline);
+    }
+    let glyphs = use_glyphs.then_some(&config.glyphs);
+    let shared = config.shared.as_ref();
+    // everything is re-read on each redraw in watch mode
+    let show = || -> Result<(), Error> {
+        let entries = if columns.contains(&Column::Sparkline) {
+            history::read_history().unwrap_or_else(|err| {
+                eprintln!("{}", tr!("couldn't read history: {}", err));
+                Vec::new()
+            })
+        } else {
+            Vec::new()
+        };
+        let print = |state: &mut State, sparklines: bool| {
+            // the summary still counts the streaks hidden for being done
+            let summary = summary(state);
+            if hide_done {
+                state
+                    .streaks
+                    .retain(|_name, streak| !matches!(streak.state, StreakState::Done));
+            }
+            match &format {
+                Some(format) => display::print_formatted(state, format),
+                None => {
+                    let table = Table {
+                        state,
+                        columns: &columns,
+                        entries: sparklines.then_some(entries.as_slice()),
+                        glyphs,
+                        by_risk,
+                    };
+                    print!("{}", table);
+                    if let Some(summary) = summary.filter(|_| show_summary) {
+                        println!("{}", summary);
+                    }
+                    Ok(())
+                }
+            }
+        };
+        let mut state = read_state()?;
+        if !selectors.is_empty() {
+            match select::resolve(&state, &selectors) {
+                Ok(names) => {
+                    for name in names.iter() {
+                        if !state.streaks.contains_key(name) {
+                            state.not_found(name);
+                        }
+                    }
+                    state.streaks.retain(|name, _streak| names.contains(name));
+                    print(&mut state, true).map_err(Error::Usage)?;
+                }
+                Err(err) => return Err(Error::Usage(err)),
+            }
+            return Ok(());
+        }
+        print(&mut state, true).map_err(Error::Usage)?;
+        if let Some(shared) = shared {
+            match shared::fetch(shared) {
+                Ok(mut state) => {
+                    println!("{}", tr!("shared with {}:", shared.url));
+                    // the history of shared streaks lives on the server, so there's no sparkline
+                    if let Err(err) = print(&mut state, false) {
+                        eprintln!("{}", err);
+                    }
+                }
+                Err(err) => eprintln!("{}", err),
+            }
+        }
+        Ok(())
+    };
+    let interval = match watch {
+        Some(interval) => interval,
+        None => return show(),
+    };
+    loop {
+        // clear the screen and move back to its top left
+        print!("\x1b[2J\x1b[H");
+        if let Err(err) = show() {
+            eprintln!("{}", tr!("error: {}", err));
+        }
+        if let Err(err) = io::stdout().flush() {
+            eprintln!("{}", tr!("couldn't write to stdout: {}", err));
+        }
+        thread::sleep(time::Duration::from_secs(interval));
     }
 }
 
-fn write_state(state: State) {
-    let path = ensure_state_path();
-    match OpenOptions::new()
-        .write(true)
-        .truncate(true)
-        .create(true)
-        .open(&path)
-    {
-        Ok(mut file) => {
-            if let Err(err) = write!(file, "{}", state.serialize()) {
-                eprintln!("couldn't write state file: {}", err);
+/// List the streaks that still need hitting today as a checklist
+fn display_today() -> Result<(), Error> {
+    let state = read_state()?;
+    let today = current_day();
+    let entries = history::read_history().unwrap_or_else(|err| {
+        eprintln!(
+            "{}",
+            tr!("couldn't read history, snoozed streaks will show: {}", err)
+        );
+        Vec::new()
+    });
+    let snoozed = history::names_with_event(&entries, "snooze", today);
+    let (later, due): (Vec<_>, Vec<_>) = state
+        .streaks
+        .iter()
+        .filter(|(_name, streak)| streak.due_today(today))
+        .sorted_by_key(|(name, _streak)| state.sort_key(name))
+        .partition(|(name, _streak)| snoozed.contains(name.as_str()));
+    if due.is_empty() && later.is_empty() {
+        println!("{}", tr!("all done for today"));
+    } else if due.is_empty() {
+        println!(
+            "{}",
+            tr!("nothing left until later, {} snoozed", later.len())
+        );
+    }
+    for (name, streak) in due {
+        match streak.progress {
+            Some((date, hits)) if date == today => {
+                println!("[ ] {} ({}/{})", name, hits, streak.target)
+            }
+            _ => println!("[ ] {}", name),
+        }
+    }
+    Ok(())
+}
+
+/// List the milestones each streak has reached, with dates where the history has them
+fn display_milestones() -> Result<(), Error> {
+    let state = read_state()?;
+    let entries = history::read_history().unwrap_or_else(|err| {
+        eprintln!(
+            "{}",
+            tr!("couldn't read history, dates will be missing: {}", err)
+        );
+        Vec::new()
+    });
+    let milestones = &config::read_config().milestones;
+    for (name, streak) in state.streaks.iter().sorted_by_key(|pair| pair.0) {
+        let reached: Vec<_> = milestones
+            .iter()
+            .filter(|&&milestone| milestone <= streak.max_count)
+            .map(|&milestone| {
+                let date = entries.iter().rev().find(|entry| {
+                    entry.name == *name
+                        && matches!(entry.event, history::Event::Milestone(count) if count == milestone)
+                });
+                match date {
+                    Some(entry) => format!("{} ({})", milestone, entry.time.format("%Y-%m-%d")),
+                    None => milestone.to_string(),
+                }
+            })
+            .collect();
+        if !reached.is_empty() {
+            println!("{}: {}", name, reached.join(", "));
+        }
+    }
+    Ok(())
+}
+
+fn parse_count(value: &str) -> Result<u32, String> {
+    value
+        .parse::<u32>()
+        .map_err(|_| tr!("expected unsigned integer for count: \"{}\"", value))
+}
+
+fn parse_positive(option: &str, value: &str) -> Result<u32, String> {
+    value
+        .parse::<u32>()
+        .ok()
+        .filter(|&n| n > 0)
+        .ok_or_else(|| tr!("expected positive number for {}: \"{}\"", option, value))
+}
+
+/// Split the arguments of `add` into the fields for the new streaks and their names
+fn parse_add_args(args: &[String]) -> Result<(templates::Template, Vec<String>), String> {
+    let mut options = Vec::new();
+    let mut names = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg.starts_with("--") {
+            let value = args
+                .next()
+                .ok_or_else(|| tr!("expected a value for {}", arg))?;
+            options.push((arg.as_str(), value.as_str()));
+        } else {
+            names.push(arg.clone());
+        }
+    }
+    // the template goes first so that the other options override it
+    options.sort_by_key(|(option, _value)| *option != "--template");
+    let mut template = templates::Template::default();
+    for (option, value) in options {
+        match option {
+            "--template" => {
+                template = templates::find(value)
+                    .ok_or_else(|| tr!("unknown template \"{}\", see `templates`", value))?
+            }
+            "--every" => template.schedule = Schedule::Every(parse_positive(option, value)?),
+            "--days" => {
+                template.schedule = Schedule::Days(
+                    value
+                        .split(',')
+                        .map(parse_weekday)
+                        .collect::<Result<_, _>>()?,
+                )
             }
+            "--target" => template.target = parse_positive(option, value)?,
+            "--tag" => {
+                validate_tag(value)?;
+                if !template.tags.iter().any(|tag| tag == value) {
+                    template.tags.push(value.to_owned());
+                }
+            }
+            "--note" => {
+                validate_note(value)?;
+                template.note = Some(value.to_owned()).filter(|note| !note.is_empty());
+            }
+            _ => return Err(tr!("unknown option {}", option)),
         }
-        Err(err) => eprintln!("couldn't open state file: {}", err),
     }
+    Ok((template, names))
 }
 
-fn modify_state<F: FnOnce(&mut State)>(action: F) {
-    let mut state = read_state();
-    action(&mut state);
-    write_state(state);
+/// Prompt for a value until one is valid, taking the default for an empty answer, or `None` if
+/// the input ends
+fn ask<T>(
+    prompt: &str,
+    default: Option<&str>,
+    parse: impl Fn(&str) -> Result<T, String>,
+) -> Option<T> {
+    loop {
+        match default {
+            Some(default) => print!("{} [{}] :", prompt, default),
+            None => print!("{} :", prompt),
+        }
+        let answer = read_answer()?;
+        let answer = match answer.trim() {
+            "" => default.unwrap_or_default(),
+            answer => answer,
+        };
+        match parse(answer) {
+            Ok(value) => return Some(value),
+            Err(err) => eprintln!("{}", err),
+        }
+    }
 }
 
-fn display_state() {
-    print!("{}", read_state());
+/// Walk through the fields of a new streak with prompts, for `add --interactive`
+fn add_wizard() -> Option<(String, templates::Template)> {
+    if assumed_answer().is_some() {
+        eprintln!(
+            "{}",
+            tr!("--interactive needs a terminal to answer its questions")
+        );
+        return None;
+    }
+    let name = ask(&tr!("name"), None, |name| {
+        validate_name(name).map(|()| name.to_owned())
+    })?;
+    let schedule = ask(
+        &tr!("schedule: daily, every <n> days, or weekdays like mon,wed"),
+        Some("daily"),
+        |value| match value.split_whitespace().collect::<Vec<_>>().as_slice() {
+            ["daily"] => Ok(Schedule::Daily),
+            ["every", days] | ["every", days, "days"] => {
+                parse_positive("every", days).map(Schedule::Every)
+            }
+            _ => value
+                .split(',')
+                .map(|day| parse_weekday(day.trim()))
+                .collect::<Result<_, _>>()
+                .map(Schedule::Days),
+        },
+    )?;
+    let target = ask(&tr!("hits needed each day"), Some("1"), |value| {
+        parse_positive("the target", value)
+    })?;
+    let tags = ask(&tr!("tags, separated by spaces"), Some("none"), |value| {
+        if value == "none" {
+            return Ok(Vec::new());
+        }
+        value
+            .split_whitespace()
+            .unique()
+            .map(|tag| validate_tag(tag).map(|()| tag.to_owned()))
+            .collect()
+    })?;
+    let reminder = ask(
+        &tr!("time to be reminded, HH:MM"),
+        Some("none"),
+        |value| match value {
+            "none" => Ok(None),
+            time => parse_time_of_day(time).map(Some),
+        },
+    )?;
+    let template = templates::Template {
+        schedule,
+        target,
+        tags,
+        reminder,
+        ..templates::Template::default()
+    };
+    Some((name, template))
 }
 
-fn run_command(path: &str, command: &str, args: &[String]) {
+fn run_command(path: &str, command: &str, args: &[String]) -> Result<(), Error> {
     match command {
         "update" => {
-            modify_state(|state| state.update());
-            println!("updated streak states");
+            modify_state(|state| {
+                state.update();
+                state.reset_hit_in_future();
+            })?;
+            println!("{}", tr!("updated streak states"));
         }
         "hit" => {
-            if args.is_empty() {
-                eprintln!("expected an argument");
+            let (create, mut args) = take_flag(args, "--create");
+            let mut at = None;
+            if let Some(i) = args.iter().position(|arg| arg == "--at") {
+                let time = match args.get(i + 1).map(|time| parse_past_time(time)) {
+                    Some(Ok(time)) => time,
+                    Some(Err(err)) => {
+                        return Err(Error::Usage(err));
+                    }
+                    None => {
+                        return Err(Error::Usage(tr!("expected a time after --at")));
+                    }
+                };
+                args.drain(i..i + 2);
+                at = Some(time);
+            }
+            let names = match read_stdin_names(&args) {
+                Ok(names) => names,
+                Err(err) => {
+                    eprintln!("{}", tr!("couldn't read streak names from stdin: {}", err));
+                    return Err(Error::Failed);
+                }
+            };
+            if names.is_empty() {
+                return Err(Error::Usage(tr!("expected an argument")));
             } else {
-                let mut output = None;
-                for arg in args.iter() {
-                    modify_state(|state| {
-                        output = state.hit_streak(arg, true);
-                    });
-                    if let Some((name, count)) = &output {
-                        println!("hit streak \"{}\": now at {}", name, count);
+                let shared = &config::read_config().shared;
+                let (remote, local): (Vec<_>, Vec<_>) = names.into_iter().partition(|name| {
+                    shared
+                        .as_ref()
+                        .is_some_and(|shared| shared.streaks.contains(name))
+                });
+                let mut outputs = Vec::new();
+                if !local.is_empty() {
+                    // the whole batch is hit with a single read and write of the state
+                    modify_state(|state| match select::resolve(state, &local) {
+                        Ok(names) => {
+                            outputs = names
+                                .iter()
+                                .map(|name| {
+                                    let time = at.unwrap_or_else(Local::now);
+                                    state.hit_streak(name, true, create, time)
+                                })
+                                .collect()
+                        }
+                        Err(err) => outputs.push(Err(err)),
+                    })?;
+                }
+                if let Some(shared) = shared.as_ref() {
+                    for name in remote {
+                        outputs.push(match at {
+                            Some(_) => Err(tr!(
+                                "shared streak \"{}\" can only be hit now, not --at a time",
+                                name
+                            )),
+                            None => shared::hit(shared, &name, create),
+                        });
                     }
                 }
+                let mut failed = false;
+                for output in outputs {
+                    match output {
+                        Ok(Some((name, count))) => {
+                            println!("{}", tr!("hit streak \"{}\": now at {}", name, count))
+                        }
+                        Ok(None) => (),
+                        Err(err) => {
+                            eprintln!("{}", err);
+                            failed = true;
+                        }
+                    }
+                }
+                if failed {
+                    return Err(Error::Failed);
+                }
             }
         }
         "add" => {
-            if args.is_empty() {
-                eprintln!("expected an argument");
+            let (interactive, args) = take_flag(args, "--interactive");
+            let parsed = if !interactive {
+                parse_add_args(&args)
+            } else if !args.is_empty() {
+                Err(tr!("expected no other arguments with --interactive"))
             } else {
-                modify_state(|state| state.add_streaks(args));
-                for arg in args.iter() {
-                    println!("added streak \"{}\"", arg);
+                match add_wizard() {
+                    Some((name, template)) => Ok((template, vec![name])),
+                    None => return Ok(()),
+                }
+            };
+            let (template, names) = match parsed {
+                Ok(parsed) => parsed,
+                Err(err) => {
+                    return Err(Error::Usage(err));
+                }
+            };
+            let names = match read_stdin_names(&names) {
+                Ok(names) => names,
+                Err(err) => {
+                    eprintln!("{}", tr!("couldn't read streak names from stdin: {}", err));
+                    return Err(Error::Failed);
+                }
+            };
+            if names.is_empty() {
+                return Err(Error::Usage(tr!("expected an argument")));
+            } else {
+                let mut added = Vec::new();
+                modify_state(|state| added = state.add_streaks(&names, &template))?;
+                for name in added.iter() {
+                    println!("{}", tr!("added streak \"{}\"", name));
                 }
             }
         }
         "remove" => {
+            let (dry_run, args) = take_flag(args, "--dry-run");
+            let (force, args) = take_flag(&args, "--force");
+            let (fuzzy, args) = take_flag(&args, "--fuzzy");
             if args.is_empty() {
-                eprintln!("expected an argument");
+                return Err(Error::Usage(tr!("expected an argument")));
             } else {
-                modify_state(|state| state.remove_streaks(args));
-                for arg in args.iter() {
-                    println!("removed streak \"{}\"", arg);
+                let mut removed = Vec::new();
+                let mut unresolved = None;
+                apply_state(dry_run, |state| match select::resolve(state, &args) {
+                    Ok(names) => removed = state.remove_streaks(&names, force || dry_run, fuzzy),
+                    Err(err) => unresolved = Some(err),
+                })?;
+                if let Some(err) = unresolved {
+                    return Err(Error::Usage(err));
+                }
+                if !dry_run {
+                    for name in removed.iter() {
+                        println!("{}", tr!("removed streak \"{}\"", name));
+                    }
                 }
             }
         }
         "rename" => {
+            let (dry_run, args) = take_flag(args, "--dry-run");
+            let (fuzzy, args) = take_flag(&args, "--fuzzy");
+            if let [flag, pattern] = args.as_slice() {
+                if flag == "--pattern" {
+                    let pattern = match select::Rename::parse(pattern) {
+                        Ok(pattern) => pattern,
+                        Err(err) => {
+                            return Err(Error::Usage(err));
+                        }
+                    };
+                    let mut renamed = Vec::new();
+                    apply_state(dry_run, |state| {
+                        renamed = state.rename_matching(&pattern, dry_run)
+                    })?;
+                    if !dry_run && !renamed.is_empty() {
+                        println!("{}", tr!("renamed {} streak(s)", renamed.len()));
+                    }
+                    return Ok(());
+                }
+            }
+            if args.len() != 2 {
+                return Err(Error::Usage(tr!("expected 2 arguments")));
+            } else {
+                let mut renamed = None;
+                apply_state(dry_run, |state| {
+                    renamed = state.rename_streak(&args[0], &args[1], fuzzy)
+                })?;
+                match renamed {
+                    Some(_) if dry_run => {
+                        println!("{}", tr!("its history would follow it to the new name"))
+                    }
+                    Some(name) => {
+                        println!("{}", tr!("renamed streak \"{}\" to \"{}\"", name, &args[1]));
+                    }
+                    None => (),
+                }
+            }
+        }
+        "reset" => {
+            let (max, args) = take_flag(args, "--max");
+            let (name, reason) = match args.as_slice() {
+                [name] => (name, None),
+                [name, flag, reason] if flag == "--reason" => (name, Some(reason.clone())),
+                _ => {
+                    return Err(Error::Usage(tr!(
+                        "expected <streak name> [--max] [--reason <text>]"
+                    )));
+                }
+            };
+            if let Some(Err(err)) = reason.as_deref().map(validate_reason) {
+                return Err(Error::Usage(err));
+            }
+            let mut reset = false;
+            modify_state(|state| reset = state.reset_streak(name, max, reason))?;
+            if reset {
+                println!("{}", tr!("reset streak \"{}\"", name));
+            }
+        }
+        "set-count" => {
+            let parsed = match args {
+                [name, count] => parse_count(count).map(|count| (name, count, None)),
+                [name, count, flag, max] if flag == "--max" => {
+                    parse_count(count).and_then(|count| Ok((name, count, Some(parse_count(max)?))))
+                }
+                _ => Err(tr!("expected <streak name> <count> [--max <count>]")),
+            };
+            match parsed {
+                Ok((name, count, max)) => {
+                    let mut set = false;
+                    modify_state(|state| set = state.set_count(name, count, max))?;
+                    if set {
+                        println!("{}", tr!("set \"{}\" to {}", name, count));
+                    }
+                }
+                Err(err) => return Err(Error::Usage(err)),
+            }
+        }
+        "alias" => match args {
+            [] => {
+                let state = read_state()?;
+                for (alias, name) in state.aliases.iter().sorted() {
+                    println!("{} -> {}", alias, name);
+                }
+            }
+            [flag, alias] if flag == "--remove" => {
+                let mut removed = false;
+                modify_state(|state| removed = state.aliases.remove(alias).is_some())?;
+                if removed {
+                    println!("{}", tr!("removed alias \"{}\"", alias));
+                } else {
+                    eprintln!("{}", tr!("alias \"{}\" not found", alias));
+                    return Err(Error::NotFound);
+                }
+            }
+            [alias, name] => {
+                let mut set = false;
+                modify_state(|state| set = state.set_alias(alias, name))?;
+                if set {
+                    println!("{}", tr!("\"{}\" now hits \"{}\"", alias, name));
+                }
+            }
+            _ => {
+                return Err(Error::Usage(tr!(
+                    "expected <alias> <streak name> or --remove <alias>"
+                )))
+            }
+        },
+        "depend" => match args {
+            [name, flag] if flag == "--clear" => {
+                let mut cleared = false;
+                modify_state(|state| cleared = state.set_dependency(name, None))?;
+                if cleared {
+                    println!(
+                        "{}",
+                        tr!("\"{}\" no longer depends on another streak", name)
+                    );
+                }
+            }
+            [name, dependency] => {
+                let mut set = false;
+                modify_state(|state| set = state.set_dependency(name, Some(dependency)))?;
+                if set {
+                    println!("{}", tr!("\"{}\" now comes after \"{}\"", name, dependency));
+                }
+            }
+            _ => {
+                return Err(Error::Usage(tr!(
+                    "expected <streak name> <streak it depends on> or <streak name> --clear"
+                )))
+            }
+        },
+        "pin" => {
+            if args.is_empty() {
+                return Err(Error::Usage(tr!("expected an argument")));
+            } else {
+                let mut pinned = false;
+                modify_state(|state| pinned = state.pin(args, false))?;
+                if pinned {
+                    println!("{}", tr!("pinned {}", args.join(", ")));
+                }
+            }
+        }
+        "unpin" => {
+            if args.is_empty() {
+                return Err(Error::Usage(tr!("expected an argument")));
+            } else {
+                modify_state(|state| state.order.retain(|pinned| !args.contains(pinned)))?;
+                println!("{}", tr!("unpinned {}", args.join(", ")));
+            }
+        }
+        "order" => match args {
+            [] => {
+                let state = read_state()?;
+                for name in state
+                    .order
+                    .iter()
+                    .filter(|name| state.streaks.contains_key(*name))
+                {
+                    println!("{}", name);
+                }
+            }
+            [flag] if flag == "--clear" => {
+                modify_state(|state| state.order.clear())?;
+                println!("{}", tr!("unpinned every streak"));
+            }
+            names => {
+                let mut ordered = false;
+                modify_state(|state| ordered = state.pin(names, true))?;
+                if ordered {
+                    println!("{}", tr!("listing {} first", names.join(", ")));
+                }
+            }
+        },
+        "group" => match args {
+            [] => {
+                let state = read_state()?;
+                for (group, members) in state.groups.iter().sorted_by_key(|pair| pair.0) {
+                    let members: Vec<_> = members
+                        .iter()
+                        .filter_map(|name| state.streaks.get(name))
+                        .collect();
+                    let done = members
+                        .iter()
+                        .filter(|streak| matches!(streak.state, StreakState::Done))
+                        .count();
+                    let percent = (100 * done).checked_div(members.len()).unwrap_or(0);
+                    println!("{}: {}/{} ({}%)", group, done, members.len(), percent);
+                }
+            }
+            [command, group, names @ ..] if command == "create" && !names.is_empty() => {
+                let mut created = false;
+                let mut exists = false;
+                modify_state(|state| {
+                    exists = state.groups.contains_key(group);
+                    if !exists {
+                        created = state.add_to_group(group, names);
+                    }
+                })?;
+                if exists {
+                    eprintln!("{}", tr!("group \"{}\" already exists", group));
+                    return Err(Error::Failed);
+                }
+                if created {
+                    println!("{}", tr!("created group \"{}\"", group));
+                }
+            }
+            [command, group, names @ ..] if command == "add" && !names.is_empty() => {
+                let mut added = false;
+                modify_state(|state| added = state.add_to_group(group, names))?;
+                if added {
+                    println!(
+                        "{}",
+                        tr!("added {} to group \"{}\"", names.join(", "), group)
+                    );
+                }
+            }
+            [command, group, names @ ..] if command == "remove" && !names.is_empty() => {
+                let mut removed = false;
+                modify_state(|state| removed = state.remove_from_group(group, names))?;
+                if removed {
+                    println!(
+                        "{}",
+                        tr!("removed {} from group \"{}\"", names.join(", "), group)
+                    );
+                }
+            }
+            [command, group] if command == "delete" => {
+                let mut deleted = false;
+                modify_state(|state| deleted = state.groups.remove(group).is_some())?;
+                if deleted {
+                    println!("{}", tr!("deleted group \"{}\"", group));
+                } else {
+                    eprintln!("{}", tr!("group \"{}\" not found", group));
+                    return Err(Error::NotFound);
+                }
+            }
+            _ => {
+                return Err(Error::Usage(tr!(
+                    "expected create|add|remove <group> <streak names> or delete <group>"
+                )))
+            }
+        },
+        "remind" => match args {
+            [] => {
+                let state = read_state()?;
+                for (name, streak) in state.streaks.iter().sorted_by_key(|pair| pair.0) {
+                    if let Some(reminder) = streak.reminder {
+                        println!("{} {}", reminder.format("%H:%M"), name);
+                    }
+                }
+            }
+            [flag] if flag == "--check" => {
+                let entries = match history::read_history() {
+                    Ok(entries) => entries,
+                    Err(err) => {
+                        eprintln!("{}", tr!("couldn't read history: {}", err));
+                        return Err(Error::Failed);
+                    }
+                };
+                let mut reminded = Vec::new();
+                modify_state(|state| {
+                    state.update();
+                    reminded = state.send_reminders(&entries, Local::now());
+                })?;
+                for name in reminded.iter() {
+                    println!("{}", tr!("\"{}\" still needs hitting today", name));
+                }
+            }
+            [name, flag] if flag == "--clear" => {
+                let mut cleared = false;
+                modify_state(|state| cleared = state.set_reminder(name, None))?;
+                if cleared {
+                    println!("{}", tr!("cleared reminder for \"{}\"", name));
+                }
+            }
+            [name, time] => match parse_time_of_day(time) {
+                Ok(reminder) => {
+                    let mut set = false;
+                    modify_state(|state| set = state.set_reminder(name, Some(reminder)))?;
+                    if set {
+                        println!("{}", tr!("reminding of \"{}\" at {}", name, time));
+                    }
+                }
+                Err(err) => return Err(Error::Usage(err)),
+            },
+            _ => {
+                return Err(Error::Usage(tr!(
+                    "expected <streak name> <HH:MM>, <streak name> --clear or --check"
+                )))
+            }
+        },
+        "undelete" => match args {
+            [] => {
+                let state = read_state()?;
+                if state.trash.is_empty() {
+                    println!("{}", tr!("the trash is empty"));
+                }
+                for trashed in state.trash.iter() {
+                    println!(
+                        "{}",
+                        tr!(
+                            "{} (removed {}, at {}, max {})",
+                            trashed.name,
+                            trashed.removed.format("%Y-%m-%d %H:%M"),
+                            trashed.streak.current_count,
+                            trashed.streak.max_count
+                        )
+                    );
+                }
+            }
+            [name] => {
+                let mut restored = false;
+                modify_state(|state| restored = state.undelete_streak(name))?;
+                if restored {
+                    println!("{}", tr!("restored streak \"{}\"", name));
+                }
+            }
+            _ => return Err(Error::Usage(tr!("expected at most 1 argument"))),
+        },
+        "merge" => {
+            let (dry_run, args) = take_flag(args, "--dry-run");
             if args.len() != 2 {
-                eprintln!("expected 2 arguments");
+                return Err(Error::Usage(tr!("expected 2 arguments")));
             } else {
-                modify_state(|state| state.rename_streak(&args[0], &args[1]));
-                println!("renamed streak \"{}\" to \"{}\"", &args[0], &args[1]);
+                let mut merged = false;
+                apply_state(dry_run, |state| {
+                    merged = state.merge_streaks(&args[0], &args[1])
+                })?;
+                if merged && dry_run {
+                    println!("{}", tr!("its history would move to \"{}\"", &args[1]));
+                } else if merged {
+                    println!(
+                        "{}",
+                        tr!("merged streak \"{}\" into \"{}\"", &args[0], &args[1])
+                    );
+                }
+            }
+        }
+        "stats" => stats::print_stats()?,
+        "week" => views::week()?,
+        "graph" => match args {
+            [name] => views::graph(name, GRAPH_MONTHS)?,
+            [name, flag, months] if flag == "--months" => match months.parse::<u32>() {
+                Ok(months) if (1..=MAX_GRAPH_MONTHS).contains(&months) => {
+                    views::graph(name, months)?
+                }
+                _ => {
+                    return Err(Error::Usage(tr!(
+                        "expected a number of months from 1 to {}",
+                        MAX_GRAPH_MONTHS
+                    )))
+                }
+            },
+            _ => return Err(Error::Usage(tr!("expected <streak name> [--months <n>]"))),
+        },
+        "month" => match args {
+            [] => views::month(None)?,
+            [name] => views::month(Some(name))?,
+            _ => return Err(Error::Usage(tr!("expected at most 1 argument"))),
+        },
+        "info" => match args {
+            [name] => info::print_info(name)?,
+            _ => return Err(Error::Usage(tr!("expected 1 argument"))),
+        },
+        "edit" => match args {
+            [] => return Err(Error::Usage(tr!("expected a streak name"))),
+            [name, rest @ ..] => {
+                let mut sets = Vec::new();
+                let mut rest = rest.iter();
+                while let Some(arg) = rest.next() {
+                    match (arg.as_str(), rest.next()) {
+                        ("--set", Some(set)) => sets.push(set.clone()),
+                        _ => {
+                            return Err(Error::Usage(tr!("expected --set key=value")));
+                        }
+                    }
+                }
+                edit::edit(name, &sets)?;
+            }
+        },
+        "describe" => match args {
+            [name] => {
+                let mut state = read_state()?;
+                match state.streaks.get(name) {
+                    Some(streak) => {
+                        println!("{}", streak.note.as_deref().unwrap_or("no description"))
+                    }
+                    None => state.not_found(name),
+                }
+            }
+            [name, note] => {
+                if let Err(err) = validate_note(note) {
+                    return Err(Error::Usage(err));
+                }
+                let mut described = false;
+                modify_state(|state| described = state.describe_streak(name, note))?;
+                if described {
+                    println!("{}", tr!("updated description of \"{}\"", name));
+                }
+            }
+            _ => return Err(Error::Usage(tr!("expected <streak name> [description]"))),
+        },
+        "challenge" => match args {
+            [name, flag, days] if flag == "--days" => match parse_positive(flag, days) {
+                Ok(days) => {
+                    let mut started = false;
+                    modify_state(|state| started = state.start_challenge(name, days))?;
+                    if started {
+                        println!(
+                            "{}",
+                            tr!("started a {} day challenge for \"{}\"", days, name)
+                        );
+                    }
+                }
+                Err(err) => return Err(Error::Usage(err)),
+            },
+            _ => return Err(Error::Usage(tr!("expected <streak name> --days <n>"))),
+        },
+        "today" => display_today()?,
+        "skip" => {
+            let date = match args {
+                [_name] => Ok(current_day()),
+                [_name, flag, date] if flag == "--date" => parse_date(date),
+                _ => Err(tr!("expected <streak name> [--date <date>]")),
+            };
+            match date {
+                Ok(date) => {
+                    let mut skipped = None;
+                    modify_state(|state| skipped = state.skip_day(&args[0], date))?;
+                    if let Some(name) = skipped {
+                        println!("{}", tr!("skipped {} for \"{}\"", date, name));
+                    }
+                }
+                Err(err) => return Err(Error::Usage(err)),
+            }
+        }
+        "repair" => match args {
+            [name, date] => {
+                let date = match parse_date(date) {
+                    Ok(date) => date,
+                    Err(err) => {
+                        return Err(Error::Usage(err));
+                    }
+                };
+                let entries = match history::read_history_of(name) {
+                    Ok(entries) => entries,
+                    Err(err) => {
+                        eprintln!("{}", tr!("couldn't read history: {}", err));
+                        return Err(Error::Failed);
+                    }
+                };
+                let mut repaired = None;
+                modify_state(|state| repaired = state.repair(name, date, &entries))?;
+                if let Some(count) = repaired {
+                    println!("{}", tr!("repaired \"{}\": now at {}", name, count));
+                }
+            }
+            _ => return Err(Error::Usage(tr!("expected <streak name> <date>"))),
+        },
+        "log" => {
+            let mut name = None;
+            let mut limit = None;
+            let mut args = args.iter();
+            while let Some(arg) = args.next() {
+                if arg == "-n" {
+                    match args.next().map(|count| count.parse::<usize>()) {
+                        Some(Ok(count)) => limit = Some(count),
+                        _ => {
+                            return Err(Error::Usage(tr!("expected a number of records after -n")));
+                        }
+                    }
+                } else {
+                    name = Some(arg.as_str());
+                }
+            }
+            audit::print_log(name, limit)?
+        }
+        "doctor" => doctor::doctor()?,
+        "snooze" => match args {
+            [name] => {
+                let mut snoozed = None;
+                modify_state(|state| snoozed = state.snooze(name))?;
+                if let Some(name) = snoozed {
+                    println!("{}", tr!("snoozed \"{}\" until tomorrow", name));
+                }
+            }
+            _ => return Err(Error::Usage(tr!("expected <streak name>"))),
+        },
+        "status" => match args {
+            [flag] if flag == "--short" => status::short(false)?,
+            [flag] if flag == "--plain" => status::plain()?,
+            [flag, color] | [color, flag] if flag == "--short" && color == "--color" => {
+                status::short(true)?
+            }
+            _ => return Err(Error::Usage(tr!("expected --short [--color] or --plain"))),
+        },
+        "statusbar" => match args {
+            [] => status::statusbar()?,
+            _ => return Err(Error::Usage(tr!("expected no arguments"))),
+        },
+        "profiles" => list_profiles()?,
+        "templates" => templates::list(),
+        "milestones" => display_milestones()?,
+        "badges" => achievements::print_badges(),
+        "export" => match args {
+            [flag, file] if flag == "--ical" => {
+                export::export_ical(&read_state()?, Path::new(file))?
+            }
+            [flag, dir] if flag == "--csv" => export::export_csv(&read_state()?, Path::new(dir))?,
+            [flag, file, name @ ..] if (flag == "--svg" || flag == "--png") && name.len() <= 1 => {
+                heatmap::export(
+                    name.first().map(String::as_str),
+                    Path::new(file),
+                    flag == "--png",
+                )?
+            }
+            _ => {
+                return Err(Error::Usage(tr!(
+                    "expected --ical <file>, --csv <dir>, or --svg or --png <file> [streak name]"
+                )))
+            }
+        },
+        "import" => {
+            let (dry_run, args) = take_flag(args, "--dry-run");
+            let mut imported = Ok(());
+            match args.as_slice() {
+                [flag, export] if flag == "--loop" => apply_state(dry_run, |state| {
+                    imported = import::import_loop(state, Path::new(export))
+                })?,
+                [file] => apply_state(dry_run, |state| {
+                    imported = import::import(state, Path::new(file))
+                })?,
+                _ => return Err(Error::Usage(tr!("expected <file> or --loop <export>"))),
+            }
+            imported?
+        }
+        "habitica" => match args {
+            [action] if action == "pull" => {
+                let mut pulled = Ok(());
+                modify_state(|state| pulled = habitica::pull(state))?;
+                pulled?
+            }
+            [action] if action == "push" => habitica::push(&read_state()?)?,
+            _ => return Err(Error::Usage(tr!("expected pull or push"))),
+        },
+        "review" => match args {
+            [flag, year] if flag == "--year" => match year.parse::<i32>() {
+                Ok(year) => report::print_review(&read_state()?, year)?,
+                Err(_) => return Err(Error::Usage(tr!("expected a year: \"{}\"", year))),
+            },
+            _ => return Err(Error::Usage(tr!("expected --year <year>"))),
+        },
+        "report" => match args {
+            [flag, dir] if flag == "--html" => report::write_html(&read_state()?, Path::new(dir))?,
+            _ => {
+                let period = match args {
+                    [flag] if flag == "--markdown" => Ok(report::Period::Week),
+                    [flag, period_flag, period]
+                        if flag == "--markdown" && period_flag == "--period" =>
+                    {
+                        report::Period::deserialize(period)
+                    }
+                    _ => Err(tr!(
+                        "expected --markdown [--period day|week|month] or --html <dir>"
+                    )),
+                };
+                match period {
+                    Ok(period) => report::print_markdown(&read_state()?, &period)?,
+                    Err(err) => return Err(Error::Usage(err)),
+                }
+            }
+        },
+        "digest" => {
+            let (smtp, args) = take_flag(args, "--send");
+            let period = match args.as_slice() {
+                [flag] if flag == "--email" => Ok(report::Period::Day),
+                [flag, period_flag, period] if flag == "--email" && period_flag == "--period" => {
+                    report::Period::deserialize(period)
+                }
+                _ => Err(tr!("expected --email [--period day|week|month] [--send]")),
+            };
+            match period {
+                Ok(period) => digest::email(&read_state()?, &period, smtp)?,
+                Err(err) => return Err(Error::Usage(err)),
             }
         }
-        "display" => display_state(),
+        "daemon" => match args {
+            [] => dbus::serve()?,
+            _ => return Err(Error::Usage(tr!("expected no arguments"))),
+        },
+        "telegram" => match args {
+            [flag] if flag == "--poll" => telegram::poll(false)?,
+            [flag, once] if flag == "--poll" && once == "--once" => telegram::poll(true)?,
+            _ => return Err(Error::Usage(tr!("expected --poll [--once]"))),
+        },
+        "serve" => match args {
+            [flag, port] if flag == "--port" => match port.parse::<u16>() {
+                Ok(port) => server::serve(port)?,
+                Err(err) => return Err(Error::Usage(tr!("invalid port: {}", err))),
+            },
+            _ => return Err(Error::Usage(tr!("expected --port <port>"))),
+        },
+        "backup" => match args {
+            [] => {
+                backup::backup(None)?;
+            }
+            [path] => {
+                backup::backup(Some(Path::new(path)))?;
+            }
+            _ => return Err(Error::Usage(tr!("expected at most 1 argument"))),
+        },
+        "restore" => match args {
+            [path] => backup::restore(Path::new(path))?,
+            _ => return Err(Error::Usage(tr!("expected 1 argument"))),
+        },
+        "sync" => sync::sync()?,
+        "display" => display_state(args)?,
         _ => {
-            eprintln!("unknown command {}", command);
             print_usage(path);
+            return Err(Error::Usage(tr!("unknown command {}", command)));
         }
     }
+    Ok(())
 }
 
-fn main() {
-    let args: Vec<_> = env::args().collect();
+/// Run the command line, returning why it failed
+fn run(mut args: Vec<String>) -> Result<(), Error> {
+    if let Some(i) = args.iter().position(|arg| arg == "--profile") {
+        let profile = match args.get(i + 1) {
+            Some(profile) => profile.clone(),
+            None => return Err(Error::InvalidProfile(tr!("expected a profile name"))),
+        };
+        validate_profile(&profile).map_err(Error::InvalidProfile)?;
+        env::set_var(PROFILE_VAR, profile);
+        args.drain(i..i + 2);
+    }
+    let mut verbosity = 0;
+    args.retain(|arg| match arg.as_str() {
+        "-v" | "--verbose" => {
+            verbosity += 1;
+            false
+        }
+        "-vv" => {
+            verbosity += 2;
+            false
+        }
+        "--yes" | "-y" => {
+            ASSUME_YES.store(true, Ordering::Relaxed);
+            false
+        }
+        "--no-input" => {
+            NO_INPUT.store(true, Ordering::Relaxed);
+            false
+        }
+        _ => true,
+    });
+    log::set_verbosity(verbosity);
+    debug!(
+        "config file {}",
+        config::config_path().map_or_else(
+            || "unavailable".to_owned(),
+            |path| path.display().to_string()
+        )
+    );
+    // a broken config would otherwise only be noticed by whichever part reads it first
+    let config = config::init()?;
+    DAY_START_HOUR.store(config.day_start_hour, Ordering::Relaxed);
     if args.len() < 2 {
         print_usage(&args[0]);
+        Ok(())
     } else {
-        run_command(&args[0], &args[1], &args[2..]);
+        journal::recover()?;
+        match run_command(&args[0], &args[1], &args[2..]) {
+            // names that weren't found explain a failure reported along the way
+            Ok(()) | Err(Error::Failed) if NOT_FOUND.load(Ordering::Relaxed) => {
+                Err(Error::NotFound)
+            }
+            result => result,
+        }
+    }
+}
+
+fn main() {
+    if let Err(err) = run(env::args().collect()) {
+        if !matches!(err, Error::Failed | Error::NotFound) {
+            eprintln!("{}", tr!("error: {}", err));
+            if let Some(hint) = err.hint() {
+                eprintln!("{}", tr!("hint: {}", hint));
+            }
+        }
+        process::exit(err.exit_code());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A state file with a line in each of its five sections
+    const FULL_STATE: &str = "# streaks state v16
+gym,3,5,2024-03-01 12:00:00 +00:00,Done,daily,1,,fit,,2024-01-01 12:00:00 +00:00,0,,,,normal,,
+read,0,2,2024-02-28 12:00:00 +00:00,Expired,daily,1,,,,,0,,,,normal,sat+sun,a note, with a comma
+
+run,2024-02-01 12:00:00 +00:00,1,1,2024-01-31 12:00:00 +00:00,New,daily,1,,,,,0,,,,normal,,
+
+g,gym
+
+fit,gym,run
+
+gym,read";
+
+    /// A daily streak on a count of 3, last hit at noon on 2024-03-01
+    fn pending_streak() -> Streak {
+        Streak {
+            state: StreakState::Pending,
+            last_hit: Local.ymd(2024, 3, 1).and_hms(12, 0, 0),
+            current_count: 3,
+            ..Streak::new()
+        }
+    }
+
+    #[test]
+    fn hits_later_on_an_already_counted_day_are_refused() {
+        let mut streak = pending_streak();
+        assert_eq!(
+            streak.hit(None, Local.ymd(2024, 3, 1).and_hms(20, 0, 0)),
+            None
+        );
+        assert_eq!(streak.current_count, 3);
+        assert!(matches!(streak.state, StreakState::Pending));
+    }
+
+    #[test]
+    fn hits_on_an_earlier_day_are_refused() {
+        let mut streak = pending_streak();
+        assert_eq!(
+            streak.hit(None, Local.ymd(2024, 2, 29).and_hms(20, 0, 0)),
+            None
+        );
+        assert_eq!(streak.current_count, 3);
+    }
+
+    #[test]
+    fn hits_past_the_previous_deadline_are_refused() {
+        let mut streak = pending_streak();
+        assert_eq!(
+            streak.hit(None, Local.ymd(2024, 3, 3).and_hms(12, 0, 0)),
+            None
+        );
+        assert_eq!(streak.current_count, 3);
+        assert!(matches!(streak.state, StreakState::Pending));
+    }
+
+    #[test]
+    fn hits_on_the_next_day_count() {
+        let mut streak = pending_streak();
+        assert_eq!(
+            streak.hit(None, Local.ymd(2024, 3, 2).and_hms(8, 0, 0)),
+            Some(4)
+        );
+        assert!(matches!(streak.state, StreakState::Done));
+    }
+
+    #[test]
+    fn streaks_created_or_merged_away_are_audited() {
+        let mut state = State::deserialize(FULL_STATE).unwrap();
+        state.hit_streak("walk", false, true, Local::now()).unwrap();
+        assert!(state.merge_streaks("walk", "gym"));
+        let actions: Vec<_> = state
+            .audit
+            .iter()
+            .map(|record| (record.action.as_str(), record.name.as_str()))
+            .collect();
+        assert_eq!(
+            actions,
+            [("add", "walk"), ("hit", "walk"), ("merge", "walk")]
+        );
+        assert_eq!(state.renames, [("walk".to_owned(), "gym".to_owned())]);
+    }
+
+    #[test]
+    fn state_round_trips_through_every_section() {
+        let state = State::deserialize(FULL_STATE).unwrap();
+        assert_eq!(state.streaks.len(), 2);
+        assert_eq!(state.trash.len(), 1);
+        assert_eq!(state.aliases["g"], "gym");
+        assert_eq!(state.groups["fit"], ["gym", "run"]);
+        assert_eq!(state.order, ["gym", "read"]);
+        assert_eq!(
+            state.streaks["read"].note.as_deref(),
+            Some("a note, with a comma")
+        );
+        let serialized = state.serialize();
+        assert_eq!(serialized.lines().filter(|line| line.is_empty()).count(), 4);
+        let reparsed = State::deserialize(&serialized).unwrap();
+        assert_eq!(reparsed.serialize(), serialized);
+    }
+
+    #[test]
+    fn empty_sections_keep_the_later_ones_in_place() {
+        let mut state = State::deserialize(FULL_STATE).unwrap();
+        state.streaks.clear();
+        state.trash.clear();
+        state.groups.clear();
+        let reparsed = State::deserialize(&state.serialize()).unwrap();
+        assert!(reparsed.streaks.is_empty() && reparsed.trash.is_empty());
+        assert_eq!(reparsed.aliases["g"], "gym");
+        assert!(reparsed.groups.is_empty());
+        assert_eq!(reparsed.order, ["gym", "read"]);
+    }
+
+    /// A moment with the UTC offset it had locally, like one in Europe/London on either side of
+    /// its clock changes
+    fn at(string: &str) -> DateTime<FixedOffset> {
+        DateTime::parse_from_str(string, "%Y-%m-%d %H:%M %z").unwrap()
+    }
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    #[test]
+    fn days_are_local_dates_across_spring_forward() {
+        // the clocks went from 01:00 GMT to 02:00 BST on the 31st of March 2024
+        let before = at("2024-03-31 00:30 +0000");
+        let after = at("2024-03-31 23:30 +0100");
+        assert_eq!(after.signed_duration_since(before), Duration::hours(22));
+        assert_eq!(day_starting_at(before, 0), date(2024, 3, 31));
+        assert_eq!(day_starting_at(after, 0), date(2024, 3, 31));
+        let day_before = at("2024-03-30 23:30 +0000");
+        assert_eq!(after.signed_duration_since(day_before), Duration::hours(23));
+        assert_eq!(day_starting_at(day_before, 0), date(2024, 3, 30));
+    }
+
+    #[test]
+    fn days_are_local_dates_across_fall_back() {
+        // the clocks went from 02:00 BST back to 01:00 GMT on the 27th of October 2024
+        let first = at("2024-10-27 01:30 +0100");
+        let second = at("2024-10-27 01:30 +0000");
+        assert_eq!(day_starting_at(first, 0), date(2024, 10, 27));
+        assert_eq!(day_starting_at(second, 0), date(2024, 10, 27));
+        let start = at("2024-10-27 00:00 +0100");
+        let end = at("2024-10-27 23:59 +0000");
+        assert_eq!(
+            end.signed_duration_since(start),
+            Duration::minutes(25 * 60 - 1)
+        );
+        assert_eq!(day_starting_at(start, 0), day_starting_at(end, 0));
+        assert_eq!(
+            day_starting_at(at("2024-10-26 23:59 +0100"), 0),
+            date(2024, 10, 26)
+        );
+    }
+
+    #[test]
+    fn days_start_at_the_configured_hour() {
+        assert_eq!(
+            day_starting_at(at("2024-03-31 03:59 +0100"), 4),
+            date(2024, 3, 30)
+        );
+        assert_eq!(
+            day_starting_at(at("2024-03-31 04:00 +0100"), 4),
+            date(2024, 3, 31)
+        );
+        // both times 01:30 came round when the clocks went back belong to the day before
+        assert_eq!(
+            day_starting_at(at("2024-10-27 01:30 +0100"), 4),
+            date(2024, 10, 26)
+        );
+        assert_eq!(
+            day_starting_at(at("2024-10-27 01:30 +0000"), 4),
+            date(2024, 10, 26)
+        );
+        assert_eq!(
+            day_starting_at(at("2024-10-27 03:59 +0000"), 4),
+            date(2024, 10, 26)
+        );
+        assert_eq!(
+            day_starting_at(at("2024-10-27 04:00 +0000"), 4),
+            date(2024, 10, 27)
+        );
+        assert_eq!(
+            day_starting_at(at("2024-01-01 00:30 +0000"), 4),
+            date(2023, 12, 31)
+        );
+    }
+
+    /// The error the strict parser stops at, which has to be the first the lenient one reports
+    fn first_error(string: &str) -> ParseError {
+        let lenient = match State::deserialize_lenient(string) {
+            Ok((_state, errors)) => errors.into_iter().next(),
+            Err(err) => Some(err),
+        };
+        match State::deserialize(string) {
+            Ok(_state) => panic!("parsed a damaged state"),
+            Err(err) => {
+                assert_eq!(lenient.as_ref(), Some(&err));
+                err
+            }
+        }
+    }
+
+    #[test]
+    fn truncated_lines_are_kept_aside() {
+        let truncated = FULL_STATE.replacen("2024-03-01 12:00:00 +00:00,Done,daily,1,,fit,,2024-01-01 12:00:00 +00:00,0,,,,normal,,", "2024-03-01 12:0", 1);
+        assert_eq!(
+            first_error(&truncated),
+            ParseError::BadFieldCount {
+                line: 2,
+                expected: 18,
+                found: 4
+            }
+        );
+        let (state, errors) = State::deserialize_lenient(&truncated).unwrap();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(state.streaks.keys().collect::<Vec<_>>(), ["read"]);
+        assert_eq!(state.trash.len(), 1);
+        assert_eq!(state.order, ["gym", "read"]);
+        // the damaged line goes back where it was for `doctor` to look at
+        let serialized = state.serialize();
+        assert_eq!(serialized.lines().nth(1), Some("read,0,2,2024-02-28 12:00:00 +00:00,Expired,daily,1,,,,,0,,,,normal,sat+sun,a note, with a comma"));
+        assert_eq!(serialized.lines().nth(2), Some("gym,3,5,2024-03-01 12:0"));
+        // a file cut off after the header is just empty
+        let (state, errors) = State::deserialize_lenient("# streaks state v16").unwrap();
+        assert!(errors.is_empty() && state.streaks.is_empty());
+    }
+
+    #[test]
+    fn lines_with_the_wrong_number_of_fields_are_reported() {
+        let short_streak = FULL_STATE.replacen(",normal,,\nread", ",normal,\nread", 1);
+        assert_eq!(
+            first_error(&short_streak),
+            ParseError::BadFieldCount {
+                line: 2,
+                expected: 18,
+                found: 17
+            }
+        );
+        let short_trash = FULL_STATE.replacen("run,2024-02-01 12:00:00 +00:00,1,1,2024-01-31 12:00:00 +00:00,New,daily,1,,,,,0,,,,normal,,", "run", 1);
+        assert_eq!(
+            first_error(&short_trash),
+            ParseError::BadFieldCount {
+                line: 5,
+                expected: 19,
+                found: 1
+            }
+        );
+        let short_alias = FULL_STATE.replacen("g,gym", "g", 1);
+        assert_eq!(
+            first_error(&short_alias),
+            ParseError::BadFieldCount {
+                line: 7,
+                expected: 2,
+                found: 1
+            }
+        );
+        let (state, errors) = State::deserialize_lenient(&short_alias).unwrap();
+        assert_eq!(errors.len(), 1);
+        assert!(state.aliases.is_empty());
+        assert_eq!(state.groups["fit"], ["gym", "run"]);
+    }
+
+    #[test]
+    fn invalid_utf8_only_loses_the_lines_it_is_in() {
+        let mut bytes = FULL_STATE.as_bytes().to_vec();
+        let count = FULL_STATE.find(",3,").unwrap() + 1;
+        bytes[count] = 0xff;
+        assert!(crypto::decode(bytes.clone()).is_err());
+        let (string, lossy) = crypto::decode_lossy(bytes).unwrap();
+        assert!(lossy);
+        assert_eq!(
+            first_error(&string),
+            ParseError::BadCount {
+                line: 2,
+                column: 2,
+                value: "\u{fffd}".to_owned()
+            }
+        );
+        let (state, errors) = State::deserialize_lenient(&string).unwrap();
+        assert_eq!(errors.len(), 1);
+        assert!(state.streaks.contains_key("read") && !state.streaks.contains_key("gym"));
+    }
+
+    #[test]
+    fn unknown_versions_are_refused() {
+        let body = FULL_STATE.split_once('\n').unwrap().1;
+        let newer = format!("{}{}\n{}", STATE_HEADER, STATE_VERSION + 1, body);
+        assert_eq!(
+            first_error(&newer),
+            ParseError::UnsupportedVersion(STATE_VERSION + 1)
+        );
+        assert_eq!(
+            first_error(&format!("{}0\n{}", STATE_HEADER, body)),
+            ParseError::UnsupportedVersion(0)
+        );
+        assert_eq!(
+            first_error(&format!("{}x\n{}", STATE_HEADER, body)),
+            ParseError::BadVersion("x".to_owned())
+        );
     }
 }
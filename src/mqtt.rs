@@ -0,0 +1,159 @@
+use crate::{config, history::Entry, tr, State, StreakState};
+use serde_json::json;
+use std::{
+    io::{self, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+
+const CONNECT: u8 = 0x10;
+const CONNACK: u8 = 0x20;
+const PUBLISH: u8 = 0x30;
+const DISCONNECT: u8 = 0xe0;
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A length-prefixed UTF-8 string as MQTT encodes them
+fn string(bytes: &mut Vec<u8>, string: &str) {
+    bytes.extend_from_slice(&(string.len() as u16).to_be_bytes());
+    bytes.extend_from_slice(string.as_bytes());
+}
+
+/// A control packet with its fixed header, the remaining length being a base 128 varint
+fn packet(kind: u8, body: &[u8]) -> Vec<u8> {
+    let mut bytes = vec![kind];
+    let mut length = body.len();
+    loop {
+        let mut byte = (length % 128) as u8;
+        length /= 128;
+        if length > 0 {
+            byte |= 0x80;
+        }
+        bytes.push(byte);
+        if length == 0 {
+            break;
+        }
+    }
+    bytes.extend_from_slice(body);
+    bytes
+}
+
+/// Connect to the first of the broker's addresses that answers in time
+fn open(broker: &config::Mqtt) -> io::Result<TcpStream> {
+    let mut failure = io::Error::new(io::ErrorKind::NotFound, "the broker has no addresses");
+    for address in (broker.host.as_str(), broker.port).to_socket_addrs()? {
+        match TcpStream::connect_timeout(&address, TIMEOUT) {
+            Ok(stream) => return Ok(stream),
+            Err(err) => failure = err,
+        }
+    }
+    Err(failure)
+}
+
+fn connect(broker: &config::Mqtt) -> io::Result<TcpStream> {
+    let mut stream = open(broker)?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    // MQTT 3.1.1 with a clean session and a minute's keep alive
+    let mut body = Vec::new();
+    string(&mut body, "MQTT");
+    body.push(4);
+    let mut flags = 0x02;
+    if broker.username.is_some() {
+        flags |= 0x80;
+    }
+    if broker.password.is_some() {
+        flags |= 0x40;
+    }
+    body.push(flags);
+    body.extend_from_slice(&60u16.to_be_bytes());
+    string(&mut body, &format!("streaks-{}", std::process::id()));
+    if let Some(username) = &broker.username {
+        string(&mut body, username);
+    }
+    if let Some(password) = &broker.password {
+        string(&mut body, password);
+    }
+    stream.write_all(&packet(CONNECT, &body))?;
+    let mut connack = [0; 4];
+    stream.read_exact(&mut connack)?;
+    match connack {
+        [CONNACK, 2, _, 0] => Ok(stream),
+        [CONNACK, 2, _, 4] | [CONNACK, 2, _, 5] => Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            tr!("the broker refused the username or password"),
+        )),
+        [CONNACK, 2, _, code] => Err(io::Error::other(tr!(
+            "the broker refused the connection with code {}",
+            code
+        ))),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            tr!("expected CONNACK from the broker"),
+        )),
+    }
+}
+
+fn payload(state: &State, entry: &Entry, kind: &str) -> String {
+    // lets automations react to the last streak being done without tracking them all
+    let all_done = state
+        .streaks
+        .values()
+        .all(|streak| matches!(streak.state, StreakState::Done));
+    json!({
+        "name": entry.name,
+        "event": kind,
+        "count": entry.count(state),
+        "time": entry.time.to_rfc3339(),
+        "all_done": all_done,
+    })
+    .to_string()
+}
+
+/// Publish the events the broker is interested in at QoS 0, over a single connection
+pub fn publish(state: &State, entries: &[Entry]) {
+    if entries.is_empty() {
+        return;
+    }
+    let broker = match &config::read_config().mqtt {
+        Some(broker) => broker,
+        None => return,
+    };
+    let wanted: Vec<_> = entries
+        .iter()
+        .filter(|entry| {
+            broker.events.as_ref().is_none_or(|events| {
+                events
+                    .iter()
+                    .any(|event| event.as_str() == entry.event.kind())
+            })
+        })
+        .collect();
+    if wanted.is_empty() {
+        return;
+    }
+    let result = connect(broker).and_then(|mut stream| {
+        for entry in wanted {
+            let kind = entry.event.kind();
+            let topic = broker
+                .topic
+                .replace("{name}", &entry.name)
+                .replace("{event}", kind);
+            let mut body = Vec::new();
+            string(&mut body, &topic);
+            body.extend_from_slice(payload(state, entry, kind).as_bytes());
+            stream.write_all(&packet(PUBLISH, &body))?;
+        }
+        stream.write_all(&packet(DISCONNECT, &[]))
+    });
+    if let Err(err) = result {
+        eprintln!(
+            "{}",
+            tr!(
+                "couldn't publish to mqtt broker {}:{}: {}",
+                broker.host,
+                broker.port,
+                err
+            )
+        );
+    }
+}
@@ -0,0 +1,396 @@
+use crate::{
+    config, current_day, day_of,
+    error::Error,
+    heatmap::{xml_escape, Heatmap},
+    history::{self, Event},
+    stats, tr, State, StreakState,
+};
+use chrono::{prelude::*, Duration};
+use itertools::Itertools;
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::Path,
+};
+
+/// Days of counts in each trend chart of the HTML report
+const TREND_DAYS: i64 = 90;
+const STYLE: &str =
+    "body { font-family: sans-serif; max-width: 760px; margin: 2em auto; color: #24292f; }
+table { border-collapse: collapse; }
+th, td { padding: 4px 10px; text-align: left; border-bottom: 1px solid #d0d7de; }
+section { margin-top: 2em; }
+svg { display: block; margin: 0.5em 0; }";
+
+pub enum Period {
+    Day,
+    Week,
+    Month,
+}
+
+impl Period {
+    pub fn deserialize(string: &str) -> Result<Self, String> {
+        match string {
+            "day" => Ok(Period::Day),
+            "week" => Ok(Period::Week),
+            "month" => Ok(Period::Month),
+            _ => Err(tr!("unknown period: \"{}\"", string)),
+        }
+    }
+
+    pub fn days(&self) -> i64 {
+        match self {
+            Period::Day => 1,
+            Period::Week => 7,
+            Period::Month => 30,
+        }
+    }
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            Period::Day => "Daily",
+            Period::Week => "Weekly",
+            Period::Month => "Monthly",
+        }
+    }
+}
+
+/// Escape characters that would break a markdown table cell
+fn md_escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('|', "\\|")
+}
+
+/// How many days of the period up to today each streak was hit on
+pub fn days_hit<'a>(
+    entries: &'a [history::Entry],
+    period: &Period,
+    today: NaiveDate,
+) -> HashMap<&'a str, usize> {
+    let start = today - Duration::days(period.days() - 1);
+    let mut hits: HashMap<&str, usize> = HashMap::new();
+    for (name, _date) in entries
+        .iter()
+        .filter(|entry| matches!(entry.event, Event::Hit))
+        .map(|entry| (entry.name.as_str(), day_of(entry.time)))
+        .filter(|(_name, date)| *date >= start && *date <= today)
+        .unique()
+    {
+        *hits.entry(name).or_insert(0) += 1;
+    }
+    hits
+}
+
+fn markdown(state: &State, entries: &[history::Entry], period: &Period) -> String {
+    let today = current_day();
+    let start = today - Duration::days(period.days() - 1);
+    let hits = days_hit(entries, period, today);
+    let hits_of = |name: &str| hits.get(name).copied().unwrap_or(0);
+
+    let mut lines = vec![
+        format!("# {} streak report", period.title()),
+        String::new(),
+        format!(
+            "{} to {}",
+            start.format("%Y-%m-%d"),
+            today.format("%Y-%m-%d")
+        ),
+        String::new(),
+    ];
+    if state.streaks.is_empty() {
+        lines.push("No streaks are being tracked.".to_owned());
+        return lines.join("\n") + "\n";
+    }
+
+    lines.push("| Streak | Current | Max | State | Days hit |".to_owned());
+    lines.push("| --- | ---: | ---: | --- | ---: |".to_owned());
+    for (name, streak) in state.streaks.iter().sorted_by_key(|pair| pair.0) {
+        lines.push(format!(
+            "| {} | {} | {} | {} | {}/{} |",
+            md_escape(name),
+            streak.current_count,
+            streak.max_count,
+            streak.state.serialize(),
+            hits_of(name),
+            period.days()
+        ));
+    }
+
+    lines.push(String::new());
+    lines.push("## Highlights".to_owned());
+    lines.push(String::new());
+    if let Some((name, streak)) = state
+        .streaks
+        .iter()
+        .filter(|pair| pair.1.current_count > 0)
+        .max_by_key(|pair| pair.1.current_count)
+    {
+        lines.push(format!(
+            "- Longest active streak: **{}** at {}",
+            md_escape(name),
+            streak.current_count
+        ));
+    }
+    if let Some((name, count)) = hits.iter().max_by_key(|pair| *pair.1) {
+        lines.push(format!(
+            "- Most consistent: **{}** with {} of {} days",
+            md_escape(name),
+            count,
+            period.days()
+        ));
+    }
+    let records: Vec<_> = state
+        .streaks
+        .iter()
+        .filter(|pair| pair.1.current_count > 0 && pair.1.current_count == pair.1.max_count)
+        .map(|pair| md_escape(pair.0))
+        .sorted()
+        .collect();
+    if !records.is_empty() {
+        lines.push(format!("- At their personal best: {}", records.join(", ")));
+    }
+
+    let broken: Vec<_> = state
+        .streaks
+        .iter()
+        .filter(|pair| matches!(pair.1.state, StreakState::Expired))
+        .sorted_by_key(|pair| pair.0)
+        .collect();
+    if !broken.is_empty() {
+        lines.push(String::new());
+        lines.push("## Broken streaks".to_owned());
+        lines.push(String::new());
+        for (name, streak) in broken {
+            lines.push(format!(
+                "- {} (best was {}, hit {} times this period)",
+                md_escape(name),
+                streak.max_count,
+                hits_of(name)
+            ));
+        }
+    }
+    lines.join("\n") + "\n"
+}
+
+pub fn print_markdown(state: &State, period: &Period) -> Result<(), Error> {
+    match history::read_history() {
+        Ok(entries) => {
+            print!("{}", markdown(state, &entries, period));
+            Ok(())
+        }
+        Err(err) => {
+            eprintln!("{}", tr!("couldn't generate report: {}", err));
+            Err(Error::Failed)
+        }
+    }
+}
+
+/// A line chart of the count at the end of each day, scaled to its highest point
+fn trend_svg(counts: &[u32]) -> String {
+    let (width, height) = (3 * counts.len(), 60);
+    let max = counts.iter().copied().max().unwrap_or(0).max(1) as usize;
+    let points = counts
+        .iter()
+        .enumerate()
+        .map(|(day, &count)| format!("{},{}", day * 3, height - count as usize * height / max))
+        .join(" ");
+    format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\"><polyline points=\"{}\" fill=\"none\" stroke=\"#30a14e\" stroke-width=\"2\"/></svg>",
+        width,
+        height + 1,
+        points
+    )
+}
+
+fn html(state: &State, entries: &[history::Entry]) -> String {
+    let today = current_day();
+    let title = tr!("Streaks on {}", today.format("%Y-%m-%d"));
+    let mut body = format!("<h1>{}</h1>\n", xml_escape(&title));
+    body += &Heatmap::new(entries, None, today).svg();
+    body += "<table>\n";
+    let mut hits = stats::RecentHits::new();
+    for entry in entries.iter() {
+        stats::add_recent_hit(&mut hits, entry, today);
+    }
+    for (i, row) in stats::rows(state, &hits, today).iter().enumerate() {
+        let tag = if i == 0 { "th" } else { "td" };
+        body += "<tr>";
+        for cell in row.iter() {
+            body += &format!("<{}>{}</{}>", tag, xml_escape(cell), tag);
+        }
+        body += "</tr>\n";
+    }
+    body += "</table>\n";
+    let start = today - Duration::days(TREND_DAYS - 1);
+    let config = config::read_config();
+    let holidays = config.holidays.all();
+    for (name, streak) in state.streaks.iter().sorted_by_key(|pair| pair.0) {
+        let counts = history::daily_counts(
+            entries,
+            name,
+            streak,
+            &holidays,
+            config.skip_every,
+            start,
+            today,
+        );
+        body += &format!(
+            "<section>\n<h2>{}</h2>\n<p>{}</p>\n{}{}\n</section>\n",
+            xml_escape(name),
+            xml_escape(&tr!(
+                "at {} (max {}), {}",
+                streak.current_count,
+                streak.max_count,
+                streak.state.serialize()
+            )),
+            Heatmap::new(entries, Some(name), today).svg(),
+            trend_svg(&counts)
+        );
+    }
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>\n{}\n</style>\n</head>\n<body>\n{}</body>\n</html>\n",
+        xml_escape(&title),
+        STYLE,
+        body
+    )
+}
+
+/// Write a self-contained `index.html` with heatmaps, stats and trend charts into the directory
+pub fn write_html(state: &State, dir: &Path) -> Result<(), Error> {
+    let entries = match history::read_history() {
+        Ok(entries) => entries,
+        Err(err) => {
+            eprintln!("{}", tr!("couldn't generate report: {}", err));
+            return Err(Error::Failed);
+        }
+    };
+    let path = dir.join("index.html");
+    match fs::create_dir_all(dir).and_then(|()| fs::write(&path, html(state, &entries))) {
+        Ok(()) => {
+            println!("{}", tr!("wrote report to {}", path.display()));
+            Ok(())
+        }
+        Err(err) => {
+            eprintln!("{}", tr!("couldn't write report: {}", err));
+            Err(Error::Failed)
+        }
+    }
+}
+
+/// Print a retrospective of a year of history
+pub fn print_review(state: &State, year: i32) -> Result<(), Error> {
+    let entries: Vec<_> = match history::read_history() {
+        Ok(entries) => entries
+            .into_iter()
+            .filter(|entry| entry.time.year() == year)
+            .collect(),
+        Err(err) => {
+            eprintln!("{}", tr!("couldn't read history: {}", err));
+            return Err(Error::Failed);
+        }
+    };
+    let hits: HashSet<_> = entries
+        .iter()
+        .filter(|entry| matches!(entry.event, Event::Hit))
+        .map(|entry| (entry.name.as_str(), day_of(entry.time)))
+        .collect();
+    if hits.is_empty() {
+        println!("{}", tr!("no hits recorded in {}", year));
+        return Ok(());
+    }
+    println!("{}", tr!("{} in review", year));
+    println!();
+    println!("{}", tr!("total hits: {}", hits.len()));
+    println!(
+        "{}",
+        tr!(
+            "streaks hit: {}",
+            hits.iter().map(|(name, _date)| name).unique().count()
+        )
+    );
+    println!(
+        "{}",
+        tr!(
+            "days with a hit: {}",
+            hits.iter().map(|(_name, date)| date).unique().count()
+        )
+    );
+
+    let today = current_day();
+    let last_month = if year == today.year() {
+        today.month()
+    } else {
+        12
+    };
+    let months: Vec<_> = (1..=last_month)
+        .map(|month| {
+            let count = hits
+                .iter()
+                .filter(|(_name, date)| date.month() == month)
+                .count();
+            (month, count)
+        })
+        .collect();
+    let month_name = |month| NaiveDate::from_ymd(year, month, 1).format("%B");
+    if let Some((month, count)) = months.iter().max_by_key(|(_month, count)| *count) {
+        println!(
+            "{}",
+            tr!("best month: {} ({} hits)", month_name(*month), count)
+        );
+    }
+    if let Some((month, count)) = months.iter().min_by_key(|(_month, count)| *count) {
+        println!(
+            "{}",
+            tr!("worst month: {} ({} hits)", month_name(*month), count)
+        );
+    }
+
+    let holidays = config::read_config().holidays.all();
+    let runs: Vec<_> = state
+        .streaks
+        .iter()
+        .filter_map(|(name, streak)| {
+            history::best_run(&entries, name, streak, &holidays).map(|run| (name, run))
+        })
+        .sorted_by_key(|(name, (_start, _end, length))| (std::cmp::Reverse(*length), *name))
+        .take(3)
+        .collect();
+    if !runs.is_empty() {
+        println!();
+        println!("{}", tr!("longest runs:"));
+        for (name, (start, end, length)) in runs {
+            println!(
+                "{}",
+                tr!("  {}: {} hits from {} to {}", name, length, start, end)
+            );
+        }
+    }
+
+    let year_start = NaiveDate::from_ymd(year, 1, 1);
+    let year_end = NaiveDate::from_ymd(year, 12, 31).min(today.pred());
+    let missed: Vec<_> = state
+        .streaks
+        .iter()
+        .map(|(name, streak)| {
+            let start = streak
+                .created
+                .map_or(year_start, |created| day_of(created).max(year_start));
+            let count = (0..=(year_end - start).num_days())
+                .map(|offset| start + Duration::days(offset))
+                .filter(|date| streak.schedule.due_on(*date))
+                .filter(|date| !hits.contains(&(name.as_str(), *date)))
+                .count();
+            (name, count)
+        })
+        .filter(|(_name, count)| *count > 0)
+        .sorted_by_key(|(name, count)| (std::cmp::Reverse(*count), *name))
+        .take(3)
+        .collect();
+    if !missed.is_empty() {
+        println!();
+        println!("{}", tr!("most missed:"));
+        for (name, count) in missed {
+            println!("{}", tr!("  {}: {} days", name, count));
+        }
+    }
+    Ok(())
+}
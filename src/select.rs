@@ -0,0 +1,138 @@
+use crate::{tr, State};
+use itertools::Itertools;
+use regex::Regex;
+
+/// Whether a name matches a shell-style pattern, where `*` is any run of characters and `?` is
+/// any single character
+fn glob_match(pattern: &[char], name: &[char]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some(('*', rest)) => (0..=name.len()).any(|skip| glob_match(rest, &name[skip..])),
+        Some((&c, rest)) => match name.split_first() {
+            Some((&first, name_rest)) => (c == '?' || c == first) && glob_match(rest, name_rest),
+            None => false,
+        },
+    }
+}
+
+/// A change to streak names for `rename --pattern`
+pub enum Rename {
+    /// `s/<regex>/<replacement>/`, with a trailing `g` replacing every match
+    Regex {
+        regex: Regex,
+        replacement: String,
+        all: bool,
+    },
+    /// `<from>/<to>`, swapping one prefix for another
+    Prefix { from: String, to: String },
+}
+
+impl Rename {
+    pub fn parse(pattern: &str) -> Result<Self, String> {
+        let mut chars = pattern.chars();
+        match (chars.next(), chars.next()) {
+            (Some('s'), Some(delimiter)) if !delimiter.is_alphanumeric() => {
+                let parts: Vec<_> = chars.as_str().split(delimiter).collect();
+                match parts.as_slice() {
+                    [regex, replacement, flags] if ["", "g"].contains(flags) => Ok(Rename::Regex {
+                        regex: Regex::new(regex)
+                            .map_err(|err| tr!("invalid regex \"{}\": {}", regex, err))?,
+                        replacement: (*replacement).to_owned(),
+                        all: *flags == "g",
+                    }),
+                    _ => Err(tr!(
+                        "expected s{0}<regex>{0}<replacement>{0}: \"{1}\"",
+                        delimiter,
+                        pattern
+                    )),
+                }
+            }
+            _ => match pattern.split_once('/') {
+                Some((from, to)) if !from.is_empty() => Ok(Rename::Prefix {
+                    from: from.to_owned(),
+                    to: to.to_owned(),
+                }),
+                _ => Err(tr!(
+                    "expected s/<regex>/<replacement>/ or <from>/<to>: \"{}\"",
+                    pattern
+                )),
+            },
+        }
+    }
+
+    /// The new name for a streak, if the pattern changes it
+    pub fn apply(&self, name: &str) -> Option<String> {
+        let renamed = match self {
+            Rename::Regex {
+                regex,
+                replacement,
+                all: true,
+            } => regex.replace_all(name, replacement.as_str()).into_owned(),
+            Rename::Regex {
+                regex, replacement, ..
+            } => regex.replace(name, replacement.as_str()).into_owned(),
+            Rename::Prefix { from, to } => format!("{}{}", to, name.strip_prefix(from.as_str())?),
+        };
+        Some(renamed).filter(|renamed| renamed != name)
+    }
+}
+
+fn is_pattern(arg: &str) -> bool {
+    arg.contains(['*', '?'])
+}
+
+/// Expand streak selectors into names: `--regex <pattern>` and arguments containing `*` or `?`
+/// select every matching streak and `--group <group>` the streaks in a group, while anything else, including the name of a streak which
+/// happens to contain those characters, is passed through for the command to look up
+pub fn resolve(state: &State, args: &[String]) -> Result<Vec<String>, String> {
+    let mut names = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let (pattern, matching): (_, Vec<_>) = if arg == "--regex" {
+            let pattern = args
+                .next()
+                .ok_or_else(|| tr!("expected a pattern after --regex"))?;
+            let regex =
+                Regex::new(pattern).map_err(|err| tr!("invalid regex \"{}\": {}", pattern, err))?;
+            let matching = state
+                .streaks
+                .keys()
+                .filter(|name| regex.is_match(name))
+                .cloned()
+                .collect();
+            (pattern, matching)
+        } else if arg == "--group" {
+            let group = args
+                .next()
+                .ok_or_else(|| tr!("expected a group after --group"))?;
+            let members = state
+                .groups
+                .get(group)
+                .ok_or_else(|| tr!("group \"{}\" not found", group))?;
+            // removed streaks stay in their groups in case they're brought back
+            let matching = members
+                .iter()
+                .filter(|name| state.streaks.contains_key(*name))
+                .cloned()
+                .collect();
+            (group, matching)
+        } else if is_pattern(arg) && !state.streaks.contains_key(arg) {
+            let glob: Vec<_> = arg.chars().collect();
+            let matching = state
+                .streaks
+                .keys()
+                .filter(|name| glob_match(&glob, &name.chars().collect::<Vec<_>>()))
+                .cloned()
+                .collect();
+            (arg, matching)
+        } else {
+            names.push(arg.clone());
+            continue;
+        };
+        if matching.is_empty() {
+            return Err(tr!("no streaks match \"{}\"", pattern));
+        }
+        names.extend(matching.into_iter().sorted());
+    }
+    Ok(names.into_iter().unique().collect())
+}
@@ -0,0 +1,371 @@
+use crate::{
+    audit, error::Error, history, modify_state, read_state, tr, validate_name, Streak, StreakState,
+};
+use itertools::Itertools;
+use serde_json::{json, Value};
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    time::Duration,
+};
+
+/// Most bytes taken for the request line and headers
+const MAX_HEAD: u64 = 16 * 1024;
+/// Most bytes of body drained from a request, which no endpoint needs
+const MAX_BODY: u64 = 64 * 1024;
+/// How long a client can keep the server waiting, as it handles one request at a time
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(10);
+
+struct Request {
+    method: String,
+    path: String,
+    query: Option<String>,
+}
+
+struct Response {
+    status: u16,
+    content_type: &'static str,
+    body: String,
+}
+
+impl Response {
+    fn json(status: u16, body: Value) -> Self {
+        Self {
+            status,
+            content_type: "application/json",
+            body: body.to_string(),
+        }
+    }
+
+    fn ok(body: Value) -> Self {
+        Self::json(200, body)
+    }
+
+    fn error(status: u16, message: &str) -> Self {
+        Self::json(status, json!({ "error": message }))
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        201 => "Created",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        409 => "Conflict",
+        413 => "Payload Too Large",
+        431 => "Request Header Fields Too Large",
+        _ => "Internal Server Error",
+    }
+}
+
+/// Decode %XX escapes (and + as space) in a URL component
+fn percent_decode(string: &str) -> Option<String> {
+    let mut bytes = Vec::new();
+    let mut iter = string.bytes();
+    while let Some(byte) = iter.next() {
+        match byte {
+            b'%' => {
+                let hex = [iter.next()?, iter.next()?];
+                bytes.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+            }
+            b'+' => bytes.push(b' '),
+            byte => bytes.push(byte),
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+/// The response to a request that couldn't be read
+fn unreadable(err: io::Error) -> Response {
+    match err.kind() {
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => {
+            Response::error(408, "timed out waiting for the request")
+        }
+        _ => Response::error(400, &err.to_string()),
+    }
+}
+
+fn read_request(stream: &TcpStream) -> Result<Request, Response> {
+    let mut reader = BufReader::new(stream.take(MAX_HEAD));
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).map_err(unreadable)?;
+    let mut parts = request_line.split_whitespace();
+    let invalid = || Response::error(400, "malformed request line");
+    let method = parts.next().ok_or_else(invalid)?.to_owned();
+    let target = parts.next().ok_or_else(invalid)?;
+    let (path, query) = match target.split_once('?') {
+        Some((path, query)) => (path.to_owned(), Some(query.to_owned())),
+        None => (target.to_owned(), None),
+    };
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header).map_err(unreadable)? == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((key, value)) = header.split_once(':') {
+            if key.trim().eq_ignore_ascii_case("content-length") {
+                content_length = value
+                    .trim()
+                    .parse()
+                    .map_err(|_| Response::error(400, "malformed content length"))?;
+            }
+        }
+    }
+    if reader.get_ref().limit() == 0 {
+        return Err(Response::error(431, "request headers too large"));
+    }
+    if content_length > MAX_BODY {
+        return Err(Response::error(413, "request body too large"));
+    }
+    // no endpoint takes a body, but it still has to be drained from the connection
+    reader.get_mut().set_limit(MAX_BODY);
+    let drained =
+        io::copy(&mut reader.take(content_length), &mut io::sink()).map_err(unreadable)?;
+    if drained < content_length {
+        return Err(Response::error(400, "request body cut short"));
+    }
+    Ok(Request {
+        method,
+        path,
+        query,
+    })
+}
+
+fn write_response(mut stream: &TcpStream, response: &Response) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        response.status,
+        reason(response.status),
+        response.content_type,
+        response.body.len(),
+        response.body
+    )?;
+    stream.flush()
+}
+
+fn streak_json(name: &str, streak: &Streak) -> Value {
+    json!({
+        "name": name,
+        "current_count": streak.current_count,
+        "max_count": streak.max_count,
+        "last_hit": streak.last_hit.to_rfc3339(),
+        "state": streak.state.serialize(),
+        "schedule": streak.schedule.serialize(),
+        "target": streak.target,
+        "tags": streak.tags,
+    })
+}
+
+fn list_streaks() -> Response {
+    let state = match read_state() {
+        Ok(state) => state,
+        Err(err) => return Response::error(500, &err.to_string()),
+    };
+    let streaks: Vec<_> = state
+        .streaks
+        .iter()
+        .sorted_by_key(|pair| pair.0)
+        .map(|(name, streak)| streak_json(name, streak))
+        .collect();
+    Response::ok(Value::from(streaks))
+}
+
+fn add_streak(name: &str) -> Response {
+    if let Err(err) = validate_name(name) {
+        return Response::error(400, &err);
+    }
+    let mut response = Response::error(409, "streak already exists");
+    modify_state(|state| {
+        if !state.streaks.contains_key(name) {
+            let streak = Streak::new();
+            response = Response::json(201, streak_json(name, &streak));
+            state.streaks.insert(name.to_owned(), streak);
+            state
+                .audit
+                .push(audit::Record::new("add", name, String::new()));
+        }
+    })
+    .map_or_else(|err| Response::error(500, &err.to_string()), |()| response)
+}
+
+fn hit_streak(name: &str) -> Response {
+    let mut response = Response::error(404, "streak not found");
+    modify_state(|state| {
+        let done = match state.streaks.get(name) {
+            Some(streak) => matches!(streak.state, StreakState::Done),
+            None => return,
+        };
+        if done {
+            response = Response::error(409, "streak already completed today");
+        } else {
+            // a hit short of the daily target still counts as progress
+            state.hit_exact(name);
+            response = Response::ok(streak_json(name, &state.streaks[name]));
+        }
+    })
+    .map_or_else(|err| Response::error(500, &err.to_string()), |()| response)
+}
+
+fn list_history(query: Option<&str>) -> Response {
+    let name = query
+        .into_iter()
+        .flat_map(|query| query.split('&'))
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _value)| *key == "name")
+        .map(|(_key, value)| percent_decode(value));
+    let name = match name {
+        Some(None) => return Response::error(400, "malformed name"),
+        Some(Some(name)) => Some(name),
+        None => None,
+    };
+    match history::read_history() {
+        Ok(entries) => Response::ok(Value::from(
+            entries
+                .iter()
+                .filter(|entry| name.as_ref().is_none_or(|name| entry.name == *name))
+                .map(|entry| {
+                    json!({
+                        "name": entry.name,
+                        "time": entry.time.to_rfc3339(),
+                        "event": entry.event.serialize(),
+                    })
+                })
+                .collect::<Vec<_>>(),
+        )),
+        Err(err) => Response::error(500, &err),
+    }
+}
+
+/// Escape a Prometheus label value
+fn label_escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn gauge(lines: &mut Vec<String>, metric: &str, help: &str, samples: Vec<(&String, u32)>) {
+    lines.push(format!("# HELP {} {}", metric, help));
+    lines.push(format!("# TYPE {} gauge", metric));
+    for (name, value) in samples {
+        lines.push(format!(
+            "{}{{name=\"{}\"}} {}",
+            metric,
+            label_escape(name),
+            value
+        ));
+    }
+}
+
+/// Render the state in the Prometheus text exposition format
+fn metrics() -> Response {
+    let state = match read_state() {
+        Ok(state) => state,
+        Err(err) => return Response::error(500, &err.to_string()),
+    };
+    let streaks: Vec<_> = state.streaks.iter().sorted_by_key(|pair| pair.0).collect();
+    let mut lines = Vec::new();
+    gauge(
+        &mut lines,
+        "streaks_current_count",
+        "Current length of the streak.",
+        streaks
+            .iter()
+            .map(|(name, streak)| (*name, streak.current_count))
+            .collect(),
+    );
+    gauge(
+        &mut lines,
+        "streaks_max_count",
+        "Longest length the streak has reached.",
+        streaks
+            .iter()
+            .map(|(name, streak)| (*name, streak.max_count))
+            .collect(),
+    );
+    lines.push("# HELP streaks_state Whether the streak is in the given state.".to_owned());
+    lines.push("# TYPE streaks_state gauge".to_owned());
+    for (name, streak) in streaks.iter() {
+        for possible in ["Done", "Pending", "Expired", "New"].iter() {
+            lines.push(format!(
+                "streaks_state{{name=\"{}\",state=\"{}\"}} {}",
+                label_escape(name),
+                possible,
+                (streak.state.serialize() == *possible) as u8
+            ));
+        }
+    }
+    Response {
+        status: 200,
+        content_type: "text/plain; version=0.0.4",
+        body: lines.join("\n") + "\n",
+    }
+}
+
+fn route(request: &Request) -> Response {
+    let segments: Vec<_> = request
+        .path
+        .trim_matches('/')
+        .split('/')
+        .map(percent_decode)
+        .collect();
+    let segments: Vec<_> = match segments.into_iter().collect::<Option<_>>() {
+        Some(segments) => segments,
+        None => return Response::error(400, "malformed path"),
+    };
+    let segments: Vec<_> = segments.iter().map(String::as_str).collect();
+    match (request.method.as_str(), segments.as_slice()) {
+        ("GET", ["streaks"]) => list_streaks(),
+        ("POST", ["streaks", name]) if !name.is_empty() => add_streak(name),
+        ("POST", ["streaks", name, "hit"]) => hit_streak(name),
+        ("GET", ["history"]) => list_history(request.query.as_deref()),
+        ("GET", ["metrics"]) => metrics(),
+        (_, ["streaks"])
+        | (_, ["streaks", _])
+        | (_, ["streaks", _, "hit"])
+        | (_, ["history"])
+        | (_, ["metrics"]) => Response::error(405, "method not allowed"),
+        _ => Response::error(404, "no such endpoint"),
+    }
+}
+
+/// Serve the REST API on all interfaces, handling one request at a time
+pub fn serve(port: u16) -> Result<(), Error> {
+    let listener = match TcpListener::bind(("0.0.0.0", port)) {
+        Ok(listener) => listener,
+        Err(err) => {
+            eprintln!("{}", tr!("couldn't listen on port {}: {}", port, err));
+            return Err(Error::Failed);
+        }
+    };
+    println!("{}", tr!("serving streaks on port {}", port));
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                eprintln!("{}", tr!("couldn't accept connection: {}", err));
+                continue;
+            }
+        };
+        let timeouts = stream
+            .set_read_timeout(Some(CLIENT_TIMEOUT))
+            .and_then(|()| stream.set_write_timeout(Some(CLIENT_TIMEOUT)));
+        if let Err(err) = timeouts {
+            eprintln!("{}", tr!("couldn't accept connection: {}", err));
+            continue;
+        }
+        let response = match read_request(&stream) {
+            Ok(request) => route(&request),
+            Err(response) => response,
+        };
+        if let Err(err) = write_response(&stream, &response) {
+            eprintln!("{}", tr!("couldn't send response: {}", err));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,104 @@
+use crate::{config, tr, yes_or_no, State, Streak, StreakState};
+use serde_json::Value;
+use std::{collections::HashMap, convert::TryFrom, io::Read};
+
+/// Encode a streak name as a URL path segment
+fn percent_encode(name: &str) -> String {
+    name.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (byte as char).to_string()
+            }
+            byte => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+fn url(shared: &config::Shared, path: &str) -> String {
+    format!("{}/{}", shared.url.trim_end_matches('/'), path)
+}
+
+fn read_json(response: ureq::Response) -> Result<Value, String> {
+    let mut body = String::new();
+    response
+        .into_reader()
+        .read_to_string(&mut body)
+        .map_err(|err| tr!("couldn't read response: {}", err))?;
+    serde_json::from_str(&body).map_err(|err| tr!("invalid response: {}", err))
+}
+
+fn parse_streak(value: &Value) -> Option<(String, Streak)> {
+    let count = |key| value[key].as_u64().and_then(|n| u32::try_from(n).ok());
+    Some((
+        value["name"].as_str()?.to_owned(),
+        Streak {
+            current_count: count("current_count")?,
+            max_count: count("max_count")?,
+            last_hit: value["last_hit"].as_str()?.parse().ok()?,
+            state: StreakState::deserialize(value["state"].as_str()?).ok()?,
+            ..Streak::new()
+        },
+    ))
+}
+
+/// Hit a streak on the shared server, creating it there if allowed
+pub fn hit(
+    shared: &config::Shared,
+    name: &str,
+    create: bool,
+) -> Result<Option<(String, u32)>, String> {
+    let hit_url = url(shared, &format!("streaks/{}/hit", percent_encode(name)));
+    let response = match ureq::post(&hit_url).call() {
+        Ok(response) => response,
+        Err(ureq::Error::Status(404, _)) => {
+            if !create && !yes_or_no(&tr!("create new shared streak \"{}\"?", name)) {
+                return Err(tr!(
+                    "shared streak \"{}\" not found, pass --create to start it",
+                    name
+                ));
+            }
+            ureq::post(&url(shared, &format!("streaks/{}", percent_encode(name))))
+                .call()
+                .map_err(|err| tr!("couldn't create shared streak: {}", err))?;
+            ureq::post(&hit_url)
+                .call()
+                .map_err(|err| tr!("couldn't hit shared streak: {}", err))?
+        }
+        Err(ureq::Error::Status(409, _)) => {
+            eprintln!(
+                "{}",
+                tr!("\"{}\": shared streak already completed today", name)
+            );
+            return Ok(None);
+        }
+        Err(err) => return Err(tr!("couldn't hit shared streak: {}", err)),
+    };
+    let (name, streak) = parse_streak(&read_json(response)?)
+        .ok_or_else(|| tr!("unexpected response from shared server"))?;
+    Ok(Some((name, streak.current_count)))
+}
+
+/// Fetch the configured shared streaks from the server
+pub fn fetch(shared: &config::Shared) -> Result<State, String> {
+    let response = ureq::get(&url(shared, "streaks"))
+        .call()
+        .map_err(|err| tr!("couldn't fetch shared streaks: {}", err))?;
+    let streaks: HashMap<_, _> = read_json(response)?
+        .as_array()
+        .ok_or_else(|| tr!("unexpected response from shared server"))?
+        .iter()
+        .filter_map(parse_streak)
+        .filter(|(name, _streak)| shared.streaks.contains(name))
+        .collect();
+    Ok(State {
+        streaks,
+        trash: Vec::new(),
+        aliases: HashMap::new(),
+        groups: HashMap::new(),
+        order: Vec::new(),
+        unreadable: Vec::new(),
+        events: Vec::new(),
+        audit: Vec::new(),
+        renames: Vec::new(),
+    })
+}
@@ -0,0 +1,162 @@
+use crate::{
+    config, current_day, day_of, error::Error, history, i18n, read_state, tr, Schedule, State,
+    Streak, StreakState,
+};
+use chrono::{prelude::*, Duration};
+use itertools::Itertools;
+use std::collections::{HashMap, HashSet};
+use unicode_width::UnicodeWidthStr;
+
+/// The longest window rates are worked out over, so hits before it don't matter
+const WINDOW_DAYS: i64 = 30;
+
+/// The dates each streak was hit on in the window rates are worked out over
+pub type RecentHits = HashMap<String, HashSet<NaiveDate>>;
+
+/// Note down an entry if it's a hit inside the window
+pub fn add_recent_hit(hits: &mut RecentHits, entry: &history::Entry, today: NaiveDate) {
+    if let Some((name, date)) = history::hit_date(entry) {
+        if date >= today - Duration::days(WINDOW_DAYS) {
+            hits.entry(name).or_default().insert(date);
+        }
+    }
+}
+
+/// How many points the weekly rate has to differ from the monthly one to count as a trend
+const TREND_THRESHOLD: u32 = 10;
+
+/// The percentage of the hits the schedule asked for over the given number of finished days,
+/// or none if the streak wasn't tracked in that window
+fn completion_rate(
+    streak: &Streak,
+    hits: &HashSet<NaiveDate>,
+    today: NaiveDate,
+    days: i64,
+) -> Option<u32> {
+    let mut start = today - Duration::days(days);
+    if let Some(created) = streak.created {
+        start = start.max(day_of(created));
+    }
+    let tracked = today.signed_duration_since(start).num_days();
+    if tracked <= 0 {
+        return None;
+    }
+    let dates: Vec<_> = (0..tracked)
+        .map(|offset| start + Duration::days(offset))
+        .collect();
+    let hit = dates.iter().filter(|date| hits.contains(date)).count() as u32;
+    let expected = match streak.schedule {
+        Schedule::Every(interval) => (tracked as u32).div_ceil(interval),
+        _ => dates
+            .iter()
+            .filter(|date| streak.schedule.due_on(**date))
+            .count() as u32,
+    };
+    if expected == 0 {
+        return None;
+    }
+    Some((hit.min(expected) * 100) / expected)
+}
+
+fn trend(week: Option<u32>, month: Option<u32>) -> &'static str {
+    match (week, month) {
+        (Some(week), Some(month)) if week >= month + TREND_THRESHOLD => "improving",
+        (Some(week), Some(month)) if week + TREND_THRESHOLD <= month => "declining",
+        (Some(_), Some(_)) => "steady",
+        _ => "-",
+    }
+}
+
+/// The next milestone and the date it would be reached by hitting the streak whenever the
+/// schedule asks for it, starting from the current run
+fn projection(streak: &Streak, milestones: &[u32], today: NaiveDate) -> Option<(u32, NaiveDate)> {
+    let count = match streak.state {
+        StreakState::Expired | StreakState::New => 0,
+        _ => streak.current_count,
+    };
+    let milestone = milestones
+        .iter()
+        .copied()
+        .filter(|&milestone| milestone > count)
+        .min()?;
+    let done_today = matches!(streak.state, StreakState::Done);
+    let first = if done_today { today.succ() } else { today };
+    let remaining = (milestone - count) as usize;
+    let date = match streak.schedule {
+        Schedule::Every(interval) => {
+            first + Duration::days(i64::from(interval) * (remaining as i64 - 1))
+        }
+        _ => (0..)
+            .map(|offset| first + Duration::days(offset))
+            .filter(|date| streak.schedule.due_on(*date))
+            .nth(remaining - 1)?,
+    };
+    Some((milestone, date))
+}
+
+fn format_rate(rate: Option<u32>) -> String {
+    rate.map_or_else(|| "-".to_owned(), |rate| format!("{}%", rate))
+}
+
+/// A header row and a row per streak with its completion rate over the last 7 and 30 days,
+/// which way it's heading, and when it would reach its next milestone
+pub fn rows(state: &State, hits: &RecentHits, today: NaiveDate) -> Vec<[String; 5]> {
+    let milestones = &config::read_config().milestones;
+    let mut table = vec![[
+        tr!("name"),
+        tr!("7 days"),
+        tr!("30 days"),
+        tr!("trend"),
+        tr!("next milestone"),
+    ]];
+    let never = HashSet::new();
+    for (name, streak) in state.streaks.iter().sorted_by_key(|pair| pair.0) {
+        let hits = hits.get(name).unwrap_or(&never);
+        let week = completion_rate(streak, hits, today, 7);
+        let month = completion_rate(streak, hits, today, WINDOW_DAYS);
+        table.push([
+            name.clone(),
+            format_rate(week),
+            format_rate(month),
+            i18n::translate(trend(week, month), &[]),
+            projection(streak, milestones, today).map_or_else(
+                || "-".to_owned(),
+                |(milestone, date)| tr!("{} days on {}", milestone, date.format("%b %-d")),
+            ),
+        ]);
+    }
+    table
+}
+
+/// Print each streak's completion rate over the last 7 and 30 days, which way it's heading, and
+/// when it would reach its next milestone
+pub fn print_stats() -> Result<(), Error> {
+    let state = read_state()?;
+    let today = current_day();
+    // only the last month of hits is kept while going through the history
+    let mut hits = RecentHits::new();
+    if let Err(err) = history::for_each_entry(|entry| add_recent_hit(&mut hits, &entry, today)) {
+        eprintln!("{}", tr!("couldn't read history: {}", err));
+    }
+    let table = rows(&state, &hits, today);
+    // padded by display width, so names with wide characters like CJK or emoji still line up
+    let widths: Vec<_> = (0..4)
+        .map(|i| table.iter().map(|row| row[i].width()).max().unwrap())
+        .collect();
+    let pad = |cell: &str, i: usize| " ".repeat(widths[i] - cell.width());
+    for row in table {
+        println!(
+            "{}{} {}{} {}{} {}{} {}",
+            row[0],
+            pad(&row[0], 0),
+            pad(&row[1], 1),
+            row[1],
+            pad(&row[2], 2),
+            row[2],
+            row[3],
+            pad(&row[3], 3),
+            row[4]
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,133 @@
+use crate::{config, current_day, day_of, error::Error, read_state, tr, State, StreakState};
+use itertools::Itertools;
+use serde_json::json;
+
+const GREEN: &str = "\x1b[32m";
+const YELLOW: &str = "\x1b[33m";
+const RED: &str = "\x1b[31m";
+const RESET: &str = "\x1b[0m";
+
+/// Counts of today's streaks for status lines
+struct Summary {
+    done: usize,
+    /// Streaks due today, including the done ones
+    total: usize,
+    /// Due streaks with a running count that would be lost
+    at_risk: usize,
+    /// Streaks which lost their count and haven't been started again
+    expired: usize,
+    /// Names and counts of the due streaks not yet done
+    pending: Vec<(String, u32)>,
+}
+
+fn summarize(state: &State) -> Summary {
+    let today = current_day();
+    let mut summary = Summary {
+        done: 0,
+        total: 0,
+        at_risk: 0,
+        expired: 0,
+        pending: Vec::new(),
+    };
+    for (name, streak) in state.streaks.iter().sorted_by_key(|pair| pair.0) {
+        if matches!(streak.state, StreakState::Done) && day_of(streak.last_hit) == today {
+            summary.done += 1;
+            summary.total += 1;
+        } else if streak.due_today(today) {
+            summary.total += 1;
+            if streak.current_count > 0 {
+                summary.at_risk += 1;
+            }
+            summary.pending.push((name.clone(), streak.current_count));
+        }
+        if matches!(streak.state, StreakState::Expired) {
+            summary.expired += 1;
+        }
+    }
+    summary
+}
+
+fn paint(text: String, color: &str, enabled: bool) -> String {
+    if enabled {
+        format!("{}{}{}", color, text, RESET)
+    } else {
+        text
+    }
+}
+
+/// Print a single line like `3/5 done, 1 at risk` for shell prompts
+pub fn short(color: bool) -> Result<(), Error> {
+    let summary = summarize(&read_state()?);
+    let done_color = if summary.done == summary.total {
+        GREEN
+    } else {
+        YELLOW
+    };
+    let mut line = paint(
+        tr!("{}/{} done", summary.done, summary.total),
+        done_color,
+        color,
+    );
+    if summary.at_risk > 0 {
+        line += ", ";
+        line += &paint(tr!("{} at risk", summary.at_risk), RED, color);
+    }
+    println!("{}", line);
+    Ok(())
+}
+
+/// Print counts of done, pending and expired streaks like `●3 ○1 ✗1` for tmux and polybar
+pub fn plain() -> Result<(), Error> {
+    let summary = summarize(&read_state()?);
+    let symbols = &config::read_config().status_symbols;
+    println!(
+        "{}{} {}{} {}{}",
+        symbols.done,
+        summary.done,
+        symbols.pending,
+        summary.total - summary.done,
+        symbols.expired,
+        summary.expired
+    );
+    Ok(())
+}
+
+/// Print the JSON object a waybar custom module expects, with the pending streaks as tooltip and
+/// the class telling whether any running streaks are at risk
+pub fn statusbar() -> Result<(), Error> {
+    let mut state = read_state()?;
+    // catch up on the date without writing, as this runs every few seconds and `update` is what
+    // records expiries
+    state.update();
+    let summary = summarize(&state);
+    let class = if summary.done == summary.total {
+        "done"
+    } else if summary.at_risk > 0 {
+        "at-risk"
+    } else {
+        "pending"
+    };
+    let tooltip = if summary.pending.is_empty() {
+        tr!("all done for today")
+    } else {
+        summary
+            .pending
+            .iter()
+            .map(|(name, count)| format!("{} ({})", name, count))
+            .join("\n")
+    };
+    let percentage = match summary.total {
+        0 => 100,
+        total => summary.done * 100 / total,
+    };
+    println!(
+        "{}",
+        json!({
+            "text": tr!("{}/{} done", summary.done, summary.total),
+            "tooltip": tooltip,
+            "class": class,
+            "percentage": percentage,
+        })
+    );
+    Ok(())
+}
@@ -0,0 +1,342 @@
+use crate::{
+    config, crypto, ensure_data_dir, error::Error, eventlog, history, read_state, tr, write_state,
+    State, StateLock,
+};
+use chrono::prelude::*;
+use std::{
+    collections::HashSet,
+    fs,
+    io::Read,
+    path::Path,
+    process::{Command, Output},
+};
+
+/// Files in the data directory that are versioned
+const SYNCED_FILES: [&str; 2] = ["state.txt", "history.txt"];
+
+fn git(dir: &Path, args: &[&str]) -> Result<Output, String> {
+    Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()
+        .map_err(|err| tr!("couldn't run git: {}", err))
+}
+
+/// Run git, treating a nonzero exit status as an error
+fn git_ok(dir: &Path, args: &[&str]) -> Result<String, String> {
+    let output = git(dir, args)?;
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    } else {
+        Err(tr!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))
+    }
+}
+
+fn ensure_repo(dir: &Path, git_config: &config::Git) -> Result<(), String> {
+    if !dir.join(".git").exists() {
+        git_ok(dir, &["init"])?;
+        // history is append-only, so concurrent additions can always be combined
+        fs::write(dir.join(".gitattributes"), "history.txt merge=union\n")
+            .map_err(|err| tr!("couldn't write .gitattributes: {}", err))?;
+        git_ok(dir, &["add", ".gitattributes"])?;
+    }
+    if git(dir, &["remote", "get-url", "origin"])?.status.success() {
+        git_ok(dir, &["remote", "set-url", "origin", &git_config.remote])?;
+    } else {
+        git_ok(dir, &["remote", "add", "origin", &git_config.remote])?;
+    }
+    Ok(())
+}
+
+/// Combine two conflicting versions of the state, keeping whichever side of each streak was hit
+/// most recently
+fn merge_states(mut ours: State, theirs: State) -> State {
+    for (name, streak) in theirs.streaks {
+        match ours.streaks.get_mut(&name) {
+            Some(existing) => {
+                let max_count = existing.max_count.max(streak.max_count);
+                if streak.last_hit > existing.last_hit {
+                    *existing = streak;
+                }
+                existing.max_count = max_count;
+            }
+            None => {
+                ours.streaks.insert(name, streak);
+            }
+        }
+    }
+    for trashed in theirs.trash {
+        let known = ours
+            .trash
+            .iter()
+            .any(|ours| ours.name == trashed.name && ours.removed == trashed.removed);
+        if !known {
+            ours.trash.push(trashed);
+        }
+    }
+    ours.trash.sort_by_key(|trashed| trashed.removed);
+    for (alias, name) in theirs.aliases {
+        ours.aliases.entry(alias).or_insert(name);
+    }
+    for name in theirs.order {
+        if !ours.order.contains(&name) {
+            ours.order.push(name);
+        }
+    }
+    for (group, members) in theirs.groups {
+        let ours = ours.groups.entry(group).or_default();
+        for member in members {
+            if !ours.contains(&member) {
+                ours.push(member);
+            }
+        }
+    }
+    ours
+}
+
+/// Combine two versions of the history, dropping duplicated entries
+fn merge_histories(
+    mut ours: Vec<history::Entry>,
+    theirs: Vec<history::Entry>,
+) -> Vec<history::Entry> {
+    ours.extend(theirs);
+    let mut seen = HashSet::new();
+    ours.retain(|entry| seen.insert(entry.serialize()));
+    ours.sort_by_key(|entry| entry.time);
+    ours
+}
+
+/// Read one side of a merge conflict, decrypting it if needed
+fn conflict_version(dir: &Path, stage: u8, file: &str) -> Result<String, String> {
+    let output = git(dir, &["show", &format!(":{}:{}", stage, file)])?;
+    if !output.status.success() {
+        // the file was added on only one side
+        return Ok(String::new());
+    }
+    crypto::decode(output.stdout).map_err(|err| tr!("couldn't decode {}: {}", file, err))
+}
+
+/// Merge the conflicting data files, since neither is meant to be edited by hand
+fn resolve_conflicts(dir: &Path, conflicted: &[&str]) -> Result<(), String> {
+    for &file in conflicted.iter() {
+        let (ours, theirs) = (
+            conflict_version(dir, 2, file)?,
+            conflict_version(dir, 3, file)?,
+        );
+        let merged = match file {
+            "state.txt" => {
+                let parse = |string: &str| {
+                    State::deserialize(string)
+                        .map_err(|err| tr!("couldn't parse conflicting state: {}", err))
+                };
+                merge_states(parse(&ours)?, parse(&theirs)?).serialize()
+            }
+            "history.txt" => {
+                let parse = |string: &str| {
+                    history::deserialize_history(string)
+                        .map_err(|err| tr!("couldn't parse conflicting history: {}", err))
+                };
+                history::serialize_history(&merge_histories(parse(&ours)?, parse(&theirs)?))
+            }
+            _ => return Err(tr!("can't resolve conflict in {}", file)),
+        };
+        fs::write(dir.join(file), crypto::encode(&merged)?)
+            .map_err(|err| tr!("couldn't write merged {}: {}", file, err))?;
+        git_ok(dir, &["add", file])?;
+        println!("{}", tr!("merged conflicting changes to {}", file));
+    }
+    git_ok(dir, &["commit", "--no-edit"])?;
+    Ok(())
+}
+
+fn sync_git(git_config: &config::Git) -> Result<(), String> {
+    let dir = ensure_data_dir()?;
+    ensure_repo(&dir, git_config)?;
+    // only the snapshot is versioned, so it has to have every change in it
+    eventlog::compact().map_err(|err| err.to_string())?;
+    for file in SYNCED_FILES.iter() {
+        if dir.join(file).exists() {
+            git_ok(&dir, &["add", file])?;
+        }
+    }
+    let staged = !git(&dir, &["diff", "--cached", "--quiet"])?
+        .status
+        .success();
+    if staged {
+        let message = format!("sync at {}", Local::now().format("%Y-%m-%d %H:%M:%S"));
+        git_ok(&dir, &["commit", "-m", &message])?;
+    }
+    let remote_branch = git(
+        &dir,
+        &[
+            "ls-remote",
+            "--exit-code",
+            "--heads",
+            "origin",
+            &git_config.branch,
+        ],
+    )?;
+    if remote_branch.status.success() {
+        let pull = git(
+            &dir,
+            &[
+                "pull",
+                "--no-rebase",
+                "--no-edit",
+                "--allow-unrelated-histories",
+                "origin",
+                &git_config.branch,
+            ],
+        )?;
+        if !pull.status.success() {
+            let conflicted = git_ok(&dir, &["diff", "--name-only", "--diff-filter=U"])?;
+            let conflicted: Vec<_> = conflicted.lines().collect();
+            if !conflicted.is_empty() {
+                resolve_conflicts(&dir, &conflicted)?;
+            } else {
+                return Err(tr!(
+                    "git pull failed: {}",
+                    String::from_utf8_lossy(&pull.stderr).trim()
+                ));
+            }
+        }
+        // the pulled snapshot replaced the one the log was started for
+        eventlog::restart().map_err(|err| err.to_string())?;
+    }
+    let refspec = format!("HEAD:{}", git_config.branch);
+    git_ok(&dir, &["push", "origin", &refspec])?;
+    Ok(())
+}
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64(bytes: &[u8]) -> String {
+    let mut encoded = String::new();
+    for chunk in bytes.chunks(3) {
+        let triple = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |acc, (i, &byte)| acc | (byte as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                let index = (triple >> (18 - 6 * i)) & 0x3f;
+                encoded.push(BASE64_ALPHABET[index as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+fn webdav_request(webdav: &config::WebDav, method: &str, file: &str) -> ureq::Request {
+    let url = format!("{}/{}", webdav.url.trim_end_matches('/'), file);
+    let request = ureq::request(method, &url);
+    match &webdav.username {
+        Some(username) => {
+            let credentials = format!(
+                "{}:{}",
+                username,
+                webdav.password.as_deref().unwrap_or_default()
+            );
+            request.set(
+                "Authorization",
+                &format!("Basic {}", base64(credentials.as_bytes())),
+            )
+        }
+        None => request,
+    }
+}
+
+/// Fetch a file from the WebDAV folder, if it exists yet
+fn download(webdav: &config::WebDav, file: &str) -> Result<Option<String>, String> {
+    let response = match webdav_request(webdav, "GET", file).call() {
+        Ok(response) => response,
+        Err(ureq::Error::Status(404, _)) => return Ok(None),
+        Err(err) => return Err(tr!("couldn't download {}: {}", file, err)),
+    };
+    let mut bytes = Vec::new();
+    response
+        .into_reader()
+        .read_to_end(&mut bytes)
+        .map_err(|err| tr!("couldn't read remote {}: {}", file, err))?;
+    crypto::decode(bytes)
+        .map(Some)
+        .map_err(|err| tr!("couldn't decode remote {}: {}", file, err))
+}
+
+fn upload(webdav: &config::WebDav, file: &str, contents: &str) -> Result<(), String> {
+    webdav_request(webdav, "PUT", file)
+        .send_bytes(&crypto::encode(contents)?)
+        .map(|_| ())
+        .map_err(|err| tr!("couldn't upload {}: {}", file, err))
+}
+
+/// Merge the remote state and history into the local copies, then upload the result
+fn sync_webdav(webdav: &config::WebDav) -> Result<(), String> {
+    let mut state = read_state()?;
+    if let Some(remote) = download(webdav, "state.txt")? {
+        let remote = State::deserialize(&remote)
+            .map_err(|err| tr!("couldn't parse remote state: {}", err))?;
+        state = merge_states(state, remote);
+    }
+    let serialized_state = state.serialize();
+    write_state(&state)?;
+
+    let mut entries = history::read_history()?;
+    if let Some(remote) = download(webdav, "history.txt")? {
+        let remote = history::deserialize_history(&remote)
+            .map_err(|err| tr!("couldn't parse remote history: {}", err))?;
+        entries = merge_histories(entries, remote);
+        history::write_history(&entries);
+    }
+
+    upload(webdav, "state.txt", &serialized_state)?;
+    upload(webdav, "history.txt", &history::serialize_history(&entries))?;
+    Ok(())
+}
+
+/// Exchange the state and history with the configured remotes
+pub fn sync() -> Result<(), Error> {
+    let config = config::read_config();
+    if config.git.is_none() && config.webdav.is_none() {
+        eprintln!(
+            "{}",
+            tr!(
+                "no [git] or [webdav] section to sync with in {}",
+                config::config_path().unwrap_or_default().display()
+            )
+        );
+        return Err(Error::Failed);
+    }
+    let _lock = StateLock::acquire()?;
+    let mut failed = false;
+    if let Some(git_config) = &config.git {
+        match sync_git(git_config) {
+            Ok(()) => println!("{}", tr!("synced streaks with {}", git_config.remote)),
+            Err(err) => {
+                eprintln!("{}", tr!("couldn't sync with git: {}", err));
+                failed = true;
+            }
+        }
+    }
+    if let Some(webdav) = &config.webdav {
+        match sync_webdav(webdav) {
+            Ok(()) => println!("{}", tr!("synced streaks with {}", webdav.url)),
+            Err(err) => {
+                eprintln!("{}", tr!("couldn't sync with webdav: {}", err));
+                failed = true;
+            }
+        }
+    }
+    if failed {
+        return Err(Error::Failed);
+    }
+    Ok(())
+}
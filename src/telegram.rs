@@ -0,0 +1,208 @@
+use crate::{
+    config, ensure_data_dir,
+    error::Error,
+    history::{Entry, Event},
+    modify_state, tr, State, StreakState,
+};
+use serde_json::Value;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+const API_URL: &str = "https://api.telegram.org";
+/// How long `getUpdates` waits for a message before returning empty-handed
+const POLL_SECONDS: u64 = 30;
+/// How long sending a message can take before giving up on it
+const SEND_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A message sent to the bot
+struct Message {
+    update_id: i64,
+    chat_id: String,
+    chat_username: Option<String>,
+    text: String,
+}
+
+fn url(bot: &config::Telegram, method: &str) -> String {
+    format!("{}/bot{}/{}", API_URL, bot.token, method)
+}
+
+fn send_message(bot: &config::Telegram, text: &str) -> Result<(), String> {
+    ureq::post(&url(bot, "sendMessage"))
+        .timeout(SEND_TIMEOUT)
+        .send_form(&[("chat_id", &bot.chat_id), ("text", text)])
+        .map(|_| ())
+        .map_err(|err| tr!("request failed: {}", err))
+}
+
+fn get_updates(bot: &config::Telegram, offset: i64, wait: u64) -> Result<Vec<Message>, String> {
+    let body = ureq::get(&url(bot, "getUpdates"))
+        .query("offset", &offset.to_string())
+        .query("timeout", &wait.to_string())
+        .query("allowed_updates", r#"["message"]"#)
+        .timeout(Duration::from_secs(wait + 10))
+        .call()
+        .map_err(|err| tr!("request failed: {}", err))?
+        .into_string()
+        .map_err(|err| tr!("couldn't read response: {}", err))?;
+    let root: Value =
+        serde_json::from_str(&body).map_err(|err| tr!("invalid response: {}", err))?;
+    let updates = root
+        .get("result")
+        .and_then(Value::as_array)
+        .ok_or_else(|| tr!("expected a list of updates in the response"))?;
+    Ok(updates
+        .iter()
+        .filter_map(|update| {
+            let message = update.get("message")?;
+            let chat = message.get("chat")?;
+            Some(Message {
+                update_id: update.get("update_id")?.as_i64()?,
+                chat_id: chat.get("id")?.as_i64()?.to_string(),
+                chat_username: chat
+                    .get("username")
+                    .and_then(Value::as_str)
+                    .map(|username| format!("@{}", username)),
+                text: message.get("text")?.as_str()?.to_owned(),
+            })
+        })
+        .collect())
+}
+
+fn bot() -> Option<&'static config::Telegram> {
+    config::read_config().telegram.as_ref()
+}
+
+/// Send a message to the chat for each reminder among the events
+pub fn notify(state: &State, entries: &[Entry]) {
+    let reminders: Vec<_> = entries
+        .iter()
+        .filter(|entry| matches!(entry.event, Event::Reminded))
+        .collect();
+    if reminders.is_empty() {
+        return;
+    }
+    let bot = match bot() {
+        Some(bot) => bot,
+        None => return,
+    };
+    for entry in reminders {
+        let text = tr!(
+            "\"{}\" still needs hitting today, it's at {}. Reply /hit {} once done.",
+            entry.name,
+            entry.count(state),
+            entry.name
+        );
+        if let Err(err) = send_message(bot, &text) {
+            eprintln!("{}", tr!("couldn't send telegram reminder: {}", err));
+        }
+    }
+}
+
+/// Hit the streak named in a `/hit` command, returning the reply
+fn hit(name: &str) -> Result<String, Error> {
+    let mut reply = tr!("no streak called \"{}\"", name);
+    modify_state(|state| {
+        let name = match state.lookup_or_alias(name) {
+            Some(name) => name,
+            None => {
+                // there's no one to answer a prompt, so suggest instead of choosing
+                let candidates = state.ranked_matches(name);
+                if !candidates.is_empty() {
+                    reply += &format!(
+                        ", {}",
+                        tr!("did you mean \"{}\"?", candidates.join("\", \""))
+                    );
+                }
+                return;
+            }
+        };
+        if matches!(state.streaks[&name].state, StreakState::Done) {
+            reply = tr!("\"{}\" is already done today", name);
+        } else if let Some(count) = state.hit_exact(&name) {
+            reply = tr!("hit streak \"{}\": now at {}", name, count);
+        }
+    })?;
+    Ok(reply)
+}
+
+fn reply_to(text: &str) -> Result<String, Error> {
+    let mut words = text.splitn(2, char::is_whitespace);
+    // commands in groups can be addressed as /hit@botname
+    let command = words.next().unwrap_or("").split('@').next().unwrap_or("");
+    let argument = words.next().unwrap_or("").trim();
+    match command {
+        "/hit" if !argument.is_empty() => hit(argument),
+        _ => Ok(tr!("send /hit <streak name> to hit a streak")),
+    }
+}
+
+fn offset_path() -> Result<PathBuf, Error> {
+    let mut path = ensure_data_dir()?;
+    path.push("telegram-offset.txt");
+    Ok(path)
+}
+
+/// The id of the first update not yet handled, so messages aren't handled twice across runs
+fn read_offset(path: &Path) -> i64 {
+    match fs::read_to_string(path) {
+        Ok(string) => string.trim().parse().unwrap_or(0),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => 0,
+        Err(err) => {
+            eprintln!("{}", tr!("couldn't read {}: {}", path.display(), err));
+            0
+        }
+    }
+}
+
+/// Answer `/hit <name>` messages from the configured chat, until interrupted or, with `once`,
+/// after the messages already waiting
+pub fn poll(once: bool) -> Result<(), Error> {
+    let bot = match bot() {
+        Some(bot) => bot,
+        None => {
+            eprintln!(
+                "{}",
+                tr!(
+                    "no [telegram] section with token and chat_id in {}",
+                    config::config_path().unwrap_or_default().display()
+                )
+            );
+            return Err(Error::Failed);
+        }
+    };
+    let path = offset_path()?;
+    let mut offset = read_offset(&path);
+    loop {
+        let wait = if once { 0 } else { POLL_SECONDS };
+        let messages = match get_updates(bot, offset, wait) {
+            Ok(messages) => messages,
+            Err(err) => {
+                eprintln!("{}", tr!("couldn't fetch telegram messages: {}", err));
+                return Err(Error::Failed);
+            }
+        };
+        for message in messages.iter() {
+            offset = offset.max(message.update_id + 1);
+            // anyone can message a bot, so only its owner's chat may hit streaks
+            let ours = message.chat_id == bot.chat_id
+                || message.chat_username.as_ref() == Some(&bot.chat_id);
+            if !ours {
+                continue;
+            }
+            let reply = reply_to(&message.text).unwrap_or_else(|err| err.to_string());
+            println!("{}", reply);
+            if let Err(err) = send_message(bot, &reply) {
+                eprintln!("{}", tr!("couldn't send telegram reply: {}", err));
+            }
+        }
+        if let Err(err) = fs::write(&path, offset.to_string()) {
+            eprintln!("{}", tr!("couldn't write {}: {}", path.display(), err));
+        }
+        if once {
+            return Ok(());
+        }
+    }
+}
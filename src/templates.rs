@@ -0,0 +1,106 @@
+use crate::{config, tr, Schedule, Streak};
+use chrono::{NaiveTime, Weekday};
+
+/// Preset fields for a new streak
+#[derive(Clone)]
+pub struct Template {
+    pub schedule: Schedule,
+    pub target: u32,
+    pub tags: Vec<String>,
+    pub note: Option<String>,
+    pub reminder: Option<NaiveTime>,
+}
+
+impl Default for Template {
+    fn default() -> Self {
+        Self {
+            schedule: Schedule::Daily,
+            target: 1,
+            tags: Vec::new(),
+            note: None,
+            reminder: None,
+        }
+    }
+}
+
+impl Template {
+    pub fn streak(&self) -> Streak {
+        Streak {
+            schedule: self.schedule.clone(),
+            target: self.target,
+            tags: self.tags.clone(),
+            note: self.note.clone(),
+            reminder: self.reminder,
+            ..Streak::new()
+        }
+    }
+
+    fn describe(&self) -> String {
+        let mut parts = vec![self.schedule.describe()];
+        if self.target > 1 {
+            parts.push(tr!("{} times a day", self.target));
+        }
+        if !self.tags.is_empty() {
+            parts.push(tr!("tags: {}", self.tags.join(" ")));
+        }
+        if let Some(note) = &self.note {
+            parts.push(tr!("note: {}", note));
+        }
+        parts.join(", ")
+    }
+}
+
+fn builtin() -> Vec<(&'static str, Template)> {
+    let template = |schedule, target, tags: &[&str]| Template {
+        schedule,
+        target,
+        tags: tags.iter().map(|&tag| tag.to_owned()).collect(),
+        note: None,
+        reminder: None,
+    };
+    vec![
+        (
+            "workout",
+            template(
+                Schedule::Days(vec![Weekday::Mon, Weekday::Wed, Weekday::Fri]),
+                1,
+                &["fitness"],
+            ),
+        ),
+        ("run", template(Schedule::Every(2), 1, &["fitness"])),
+        ("meditate", template(Schedule::Daily, 1, &["mindfulness"])),
+        ("read", template(Schedule::Daily, 1, &["learning"])),
+        ("water", template(Schedule::Daily, 8, &["health"])),
+        ("floss", template(Schedule::Daily, 1, &["health"])),
+    ]
+}
+
+/// Look up a template by name, letting ones in the config override the built-in ones
+pub fn find(name: &str) -> Option<Template> {
+    config::read_config()
+        .templates
+        .iter()
+        .find(|(configured, _template)| configured == name)
+        .map(|(_name, template)| template.clone())
+        .or_else(|| {
+            builtin()
+                .into_iter()
+                .find(|(builtin, _template)| *builtin == name)
+                .map(|(_name, template)| template)
+        })
+}
+
+pub fn list() {
+    let configured = &config::read_config().templates;
+    for (name, template) in configured.iter() {
+        println!("{}", tr!("{} (configured) - {}", name, template.describe()));
+    }
+    for (name, template) in builtin() {
+        if !configured
+            .iter()
+            .any(|(configured, _template)| configured == name)
+        {
+            println!("{} - {}", name, template.describe());
+        }
+    }
+}
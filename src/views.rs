@@ -0,0 +1,259 @@
+use crate::{config, current_day, day_of, error::Error, history, read_state, tr, Streak};
+use chrono::{prelude::*, Duration};
+use itertools::Itertools;
+use std::{cmp, collections::HashSet, convert::TryFrom};
+use unicode_width::UnicodeWidthStr;
+
+fn hit_dates() -> HashSet<(String, NaiveDate)> {
+    let mut hits = HashSet::new();
+    let read = history::for_each_entry(|entry| hits.extend(history::hit_date(&entry)));
+    if let Err(err) = read {
+        eprintln!("{}", tr!("couldn't read history: {}", err));
+    }
+    hits
+}
+
+/// ✓ for a hit, ✗ for a missed due day, and – for a day that didn't need hitting (yet)
+fn day_mark(streak: &Streak, hit: bool, date: NaiveDate, today: NaiveDate) -> char {
+    let tracked = streak.created.is_none_or(|created| date >= day_of(created));
+    if hit {
+        '✓'
+    } else if date < today && tracked && streak.schedule.due_on(date) {
+        '✗'
+    } else {
+        '–'
+    }
+}
+
+/// Print a grid with a row per streak and a column for each of the last 7 days
+pub fn week() -> Result<(), Error> {
+    let state = read_state()?;
+    let hits = hit_dates();
+    let today = current_day();
+    let dates: Vec<_> = (0..7)
+        .rev()
+        .map(|offset| today - Duration::days(offset))
+        .collect();
+    let width = state
+        .streaks
+        .keys()
+        .map(|name| name.width())
+        .max()
+        .unwrap_or(0);
+    println!(
+        "{:width$} {}",
+        "",
+        dates.iter().map(|date| date.format("%a")).join(" "),
+        width = width
+    );
+    for (name, streak) in state.streaks.iter().sorted_by_key(|pair| pair.0) {
+        let marks = dates
+            .iter()
+            .map(|&date| {
+                let hit = hits.contains(&(name.clone(), date));
+                format!(" {} ", day_mark(streak, hit, date, today))
+            })
+            .join(" ");
+        // pad by hand since wide characters take up two columns
+        let padding = " ".repeat(width - name.width());
+        println!("{}{} {}", name, padding, marks);
+    }
+    Ok(())
+}
+
+/// Print a calendar of the current month, marking the hits of one streak or showing the share
+/// of all streaks hit on each day
+pub fn month(name: Option<&str>) -> Result<(), Error> {
+    let mut state = read_state()?;
+    if let Some(name) = name {
+        if !state.streaks.contains_key(name) {
+            state.not_found(name);
+            return Ok(());
+        }
+    }
+    let hits = hit_dates();
+    let today = current_day();
+    let first = NaiveDate::from_ymd(today.year(), today.month(), 1);
+    let cell = |date: NaiveDate| match name {
+        _ if date > today => format!("{:>4}", date.day()),
+        Some(name) => {
+            let hit = hits.contains(&(name.to_owned(), date));
+            format!("{:>3}{}", date.day(), if hit { '✓' } else { ' ' })
+        }
+        None => {
+            let tracked: Vec<_> = state
+                .streaks
+                .iter()
+                .filter(|(_name, streak)| {
+                    streak.created.is_none_or(|created| date >= day_of(created))
+                })
+                .collect();
+            let hit = tracked
+                .iter()
+                .filter(|(name, _streak)| hits.contains(&((*name).clone(), date)))
+                .count();
+            match (hit * 100).checked_div(tracked.len()) {
+                Some(percent) => format!("{:>3}%", percent),
+                None => format!("{:>4}", "-"),
+            }
+        }
+    };
+    println!(
+        "{} ({})",
+        first.format("%B %Y"),
+        name.unwrap_or("% of streaks hit")
+    );
+    println!(
+        "{}",
+        [
+            Weekday::Mon,
+            Weekday::Tue,
+            Weekday::Wed,
+            Weekday::Thu,
+            Weekday::Fri,
+            Weekday::Sat,
+            Weekday::Sun
+        ]
+        .iter()
+        .map(|day| format!("{:>4}", day))
+        .join(" ")
+    );
+    let mut line: Vec<_> = (0..first.weekday().num_days_from_monday())
+        .map(|_| "    ".to_owned())
+        .collect();
+    let mut date = first;
+    while date.month() == first.month() {
+        line.push(cell(date));
+        if date.weekday() == Weekday::Sun {
+            println!("{}", line.join(" "));
+            line.clear();
+        }
+        date = date.succ();
+    }
+    if !line.is_empty() {
+        println!("{}", line.join(" "));
+    }
+    Ok(())
+}
+
+/// Rows of braille characters in `graph`, each four dots tall
+const GRAPH_HEIGHT: usize = 8;
+/// Most columns of braille characters in `graph`, each two days (or buckets of days) wide
+const GRAPH_WIDTH: usize = 60;
+/// The bit of each dot in a braille character by column then row from the top
+const BRAILLE_DOTS: [[u8; 4]; 2] = [[0x01, 0x02, 0x04, 0x40], [0x08, 0x10, 0x20, 0x80]];
+
+/// The same day of the month some months earlier, or the last day of that month if shorter,
+/// or nothing if that's before the earliest representable date
+fn months_before(date: NaiveDate, months: u32) -> Option<NaiveDate> {
+    let total =
+        (date.year() * 12 + date.month0() as i32).checked_sub(i32::try_from(months).ok()?)?;
+    (1..=date.day()).rev().find_map(|day| {
+        NaiveDate::from_ymd_opt(total.div_euclid(12), total.rem_euclid(12) as u32 + 1, day)
+    })
+}
+
+/// Plot the count of a streak over the last months as a braille line chart
+pub fn graph(name: &str, months: u32) -> Result<(), Error> {
+    let mut state = read_state()?;
+    let name = match state.lookup(name) {
+        Some(name) => name,
+        None => {
+            state.not_found(name);
+            return Ok(());
+        }
+    };
+    let entries = history::read_history_of(&name).unwrap_or_else(|err| {
+        eprintln!("{}", tr!("couldn't read history: {}", err));
+        Vec::new()
+    });
+    let today = current_day();
+    let config = config::read_config();
+    let start = months_before(today, months)
+        .ok_or_else(|| Error::Usage(tr!("can't go back {} months", months)))?;
+    let counts = history::daily_counts(
+        &entries,
+        &name,
+        &state.streaks[&name],
+        &config.holidays.all(),
+        config.skip_every,
+        start,
+        today,
+    );
+    // squeeze long ranges into the width by taking the count at the end of each bucket of days
+    let bucket = counts.len().div_ceil(GRAPH_WIDTH * 2);
+    let points: Vec<u32> = counts
+        .chunks(bucket)
+        .map(|chunk| *chunk.last().unwrap())
+        .collect();
+    let max = points.iter().copied().max().unwrap_or(0).max(1) as usize;
+    let levels = GRAPH_HEIGHT * 4;
+    let mut grid = vec![vec![0u8; points.len().div_ceil(2)]; GRAPH_HEIGHT];
+    let mut previous: Option<usize> = None;
+    for (x, &count) in points.iter().enumerate() {
+        let level = (count as usize * (levels - 1) + max / 2) / max;
+        // join up with the previous point so drops and jumps read as a line
+        let (low, high) = match previous {
+            Some(previous) => (cmp::min(previous, level), cmp::max(previous, level)),
+            None => (level, level),
+        };
+        for dot in low..=high {
+            grid[GRAPH_HEIGHT - 1 - dot / 4][x / 2] |= BRAILLE_DOTS[x % 2][3 - dot % 4];
+        }
+        previous = Some(level);
+    }
+    println!("{}", tr!("\"{}\" over the last {} months", name, months));
+    let label_width = max.to_string().len();
+    for (row, cells) in grid.iter().enumerate() {
+        let label = match row {
+            0 => max.to_string(),
+            _ if row == GRAPH_HEIGHT - 1 => "0".to_owned(),
+            _ => String::new(),
+        };
+        let line: String = cells
+            .iter()
+            .map(|&bits| char::from_u32(0x2800 + u32::from(bits)).unwrap())
+            .collect();
+        println!("{:>width$} ┤{}", label, line, width = label_width);
+    }
+    let first = start.format("%Y-%m-%d").to_string();
+    let last = today.format("%Y-%m-%d").to_string();
+    let gap = grid[0]
+        .len()
+        .saturating_sub(first.len() + last.len())
+        .max(1);
+    println!(
+        "{}{}{}{}",
+        " ".repeat(label_width + 2),
+        first,
+        " ".repeat(gap),
+        last
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn months_before_clamps_to_the_end_of_shorter_months() {
+        let date = NaiveDate::from_ymd_opt(2024, 3, 31).unwrap();
+        assert_eq!(months_before(date, 1), NaiveDate::from_ymd_opt(2024, 2, 29));
+        assert_eq!(
+            months_before(date, 15),
+            NaiveDate::from_ymd_opt(2022, 12, 31)
+        );
+    }
+
+    #[test]
+    fn months_before_the_earliest_date_is_nothing() {
+        let date = NaiveDate::from_ymd_opt(2024, 3, 31).unwrap();
+        assert_eq!(months_before(date, u32::MAX), None);
+        assert_eq!(months_before(date, i32::MAX as u32), None);
+        assert_eq!(
+            months_before(NaiveDate::from_ymd_opt(-262_000, 1, 1).unwrap(), 12_000),
+            None
+        );
+    }
+}
@@ -0,0 +1,108 @@
+use crate::{
+    config::{self, Service},
+    history::{Entry, Event},
+    tr, State,
+};
+use serde_json::json;
+use std::time::Duration;
+
+/// How long posting a webhook can take before giving up on it
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+const DEFAULT_TEMPLATE: &str =
+    r#"{"name": "{name}", "event": "{event}", "count": {count}, "time": "{time}"}"#;
+
+/// Escape a value for use inside a JSON string, since most templates are JSON
+fn json_escape(value: &str) -> String {
+    let mut escaped = String::new();
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn render(
+    template: &str,
+    state: &State,
+    entry: &Entry,
+    kind: &str,
+    escape: fn(&str) -> String,
+) -> String {
+    template
+        .replace("{name}", &escape(&entry.name))
+        .replace("{event}", kind)
+        .replace("{count}", &entry.count(state).to_string())
+        .replace("{time}", &entry.time.to_rfc3339())
+}
+
+/// The chat message for an event when no template is configured
+fn default_message(state: &State, entry: &Entry) -> String {
+    let count = entry.count(state);
+    match &entry.event {
+        Event::Hit => tr!("\"{}\" was hit, now at {}", entry.name, count),
+        Event::Expired => tr!("\"{}\" expired, it was at {}", entry.name, count),
+        Event::Milestone(count) => tr!("\"{}\" reached {} days!", entry.name, count),
+        Event::Reset(_) => tr!("\"{}\" was started over", entry.name),
+        Event::Reminded => tr!("\"{}\" still needs hitting today", entry.name),
+        Event::Snoozed => tr!("\"{}\" was put off until later today", entry.name),
+        Event::Skipped => tr!("\"{}\" took a day off, still at {}", entry.name, count),
+    }
+}
+
+fn body(webhook: &config::Webhook, state: &State, entry: &Entry, kind: &str) -> String {
+    let text = || match &webhook.template {
+        Some(template) => render(template, state, entry, kind, str::to_owned),
+        None => default_message(state, entry),
+    };
+    match webhook.service {
+        Service::Json => render(
+            webhook.template.as_deref().unwrap_or(DEFAULT_TEMPLATE),
+            state,
+            entry,
+            kind,
+            json_escape,
+        ),
+        Service::Slack => json!({ "text": text() }).to_string(),
+        Service::Discord => json!({ "content": text() }).to_string(),
+    }
+}
+
+/// Post every configured webhook interested in the given events
+pub fn fire(state: &State, entries: &[Entry]) {
+    if entries.is_empty() {
+        return;
+    }
+    let webhooks = &config::read_config().webhooks;
+    for entry in entries.iter() {
+        let kind = entry.event.kind();
+        for webhook in webhooks.iter() {
+            let wanted = match &webhook.events {
+                Some(events) => events.iter().any(|event| event == kind),
+                // chat channels only hear about the things worth congratulating or teasing
+                None if webhook.service != Service::Json => {
+                    matches!(entry.event, Event::Milestone(_) | Event::Expired)
+                }
+                None => true,
+            };
+            if !wanted {
+                continue;
+            }
+            if let Err(err) = ureq::post(&webhook.url)
+                .timeout(TIMEOUT)
+                .set("Content-Type", "application/json")
+                .send_string(&body(webhook, state, entry, kind))
+            {
+                eprintln!(
+                    "{}",
+                    tr!("couldn't send {} webhook to {}: {}", kind, webhook.url, err)
+                );
+            }
+        }
+    }
+}